@@ -0,0 +1,87 @@
+//! Compares acquiring a permit with taking a read lock on `std::sync::RwLock`
+//!
+//! Both allow many holders at once, so a semaphore with a max of at least the thread count
+//! does the same job as the read lock. Run with `cargo bench --bench acquire`
+//!
+//! Baseline from `--warm-up-time 1 --measurement-time 2` on a single-core VM, for spotting regressions
+//! rather than comparing machines. With one core the threads take turns, so the contended times
+//! grow with the thread count instead of showing contention on the count:
+//!
+//! | benchmark             | semaphore | `RwLock::read` |
+//! |-----------------------|-----------|----------------|
+//! | uncontended           | 58 ns     | 20 ns          |
+//! | contended, 1 thread   | 66 ns     | 22 ns          |
+//! | contended, 4 threads  | 246 ns    | 75 ns          |
+//! | contended, 16 threads | 755 ns    | 260 ns         |
+
+use std::{
+    hint::black_box,
+    sync::RwLock,
+    thread,
+    time::{Duration, Instant},
+};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use semaphorus::Semaphore;
+
+const THREADS: [usize; 3] = [1, 4, 16];
+
+/// Run `iters` acquires on each of `threads` threads at once, returning the average time a thread took
+fn on_threads(threads: usize, iters: u64, acquire: impl Fn() + Sync) -> Duration {
+    thread::scope(|s| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                s.spawn(|| {
+                    let started = Instant::now();
+                    for _ in 0..iters {
+                        acquire();
+                    }
+                    started.elapsed()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap())
+            .sum::<Duration>()
+            / u32::try_from(threads).unwrap()
+    })
+}
+
+fn uncontended(c: &mut Criterion) {
+    let mut group = c.benchmark_group("uncontended");
+
+    let semaphore = Semaphore::new(0_u64, 1);
+    group.bench_function("try_get", |b| {
+        b.iter(|| drop(black_box(&semaphore).try_get()));
+    });
+
+    let lock = RwLock::new(0_u64);
+    group.bench_function("RwLock::read", |b| {
+        b.iter(|| drop(black_box(&lock).read()));
+    });
+
+    group.finish();
+}
+
+fn contended(c: &mut Criterion) {
+    for threads in THREADS {
+        let mut group = c.benchmark_group(format!("contended/{threads}"));
+        group.sample_size(10);
+
+        group.bench_function("get", |b| {
+            let semaphore = Semaphore::new(0_u64, threads);
+            b.iter_custom(|iters| on_threads(threads, iters, || drop(black_box(&semaphore).get())));
+        });
+
+        group.bench_function("RwLock::read", |b| {
+            let lock = RwLock::new(0_u64);
+            b.iter_custom(|iters| on_threads(threads, iters, || drop(black_box(&lock).read())));
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, uncontended, contended);
+criterion_main!(benches);
@@ -0,0 +1,66 @@
+//! Measures contended `try_get` while another thread keeps changing the semaphore's settings
+//!
+//! The settings live next to the count unless it gets a cache line of its own. Compare
+//! `cargo bench --bench cache_lines` and `cargo bench --bench cache_lines --features split_cache_lines`
+
+use std::{
+    hint::black_box,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use semaphorus::{raw, WaitStrategy};
+
+const ACQUIRERS: usize = 4;
+
+fn acquire_while_adjusting(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cache_lines");
+    group.sample_size(10);
+
+    group.bench_function("try_get while adjusting settings", |b| {
+        b.iter_custom(|iters| {
+            let semaphore = raw::Semaphore::new(ACQUIRERS);
+            let done = AtomicBool::new(false);
+
+            thread::scope(|s| {
+                s.spawn(|| {
+                    let mut spin = true;
+                    while !done.load(Ordering::Relaxed) {
+                        semaphore.set_wait_strategy(if spin {
+                            WaitStrategy::Spin
+                        } else {
+                            WaitStrategy::Yield
+                        });
+                        spin = !spin;
+                        thread::sleep(Duration::from_micros(10));
+                    }
+                });
+
+                let acquirers: Vec<_> = (0..ACQUIRERS)
+                    .map(|_| {
+                        s.spawn(|| {
+                            let started = Instant::now();
+                            for _ in 0..iters {
+                                drop(black_box(&semaphore).try_get());
+                            }
+                            started.elapsed()
+                        })
+                    })
+                    .collect();
+                let total = acquirers
+                    .into_iter()
+                    .map(|acquirer| acquirer.join().unwrap())
+                    .sum::<Duration>();
+                done.store(true, Ordering::Relaxed);
+                total / ACQUIRERS as u32
+            })
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, acquire_while_adjusting);
+criterion_main!(benches);
@@ -0,0 +1,57 @@
+//! Compares contended `try_get` on `raw::Semaphore` and `ShardedSemaphore`
+//!
+//! Every acquirer writes the single count of the raw semaphore, while the sharded one mostly
+//! keeps each thread on its own shard. Run with `cargo bench --bench sharded --features sharded`
+
+use std::{
+    hint::black_box,
+    thread,
+    time::{Duration, Instant},
+};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use semaphorus::{raw, sharded::ShardedSemaphore};
+
+const ACQUIRERS: usize = 4;
+
+/// Run `acquire` `iters` times on every acquirer thread and return the average time per thread
+fn contended(iters: u64, acquire: impl Fn() + Sync) -> Duration {
+    thread::scope(|s| {
+        let acquirers: Vec<_> = (0..ACQUIRERS)
+            .map(|_| {
+                s.spawn(|| {
+                    let started = Instant::now();
+                    for _ in 0..iters {
+                        acquire();
+                    }
+                    started.elapsed()
+                })
+            })
+            .collect();
+        acquirers
+            .into_iter()
+            .map(|acquirer| acquirer.join().unwrap())
+            .sum::<Duration>()
+            / ACQUIRERS as u32
+    })
+}
+
+fn contended_try_get(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sharded");
+    group.sample_size(10);
+
+    let semaphore = raw::Semaphore::new(ACQUIRERS * 4);
+    group.bench_function("raw", |b| {
+        b.iter_custom(|iters| contended(iters, || drop(black_box(&semaphore).try_get())));
+    });
+
+    let semaphore = ShardedSemaphore::with_shards((), ACQUIRERS * 4, ACQUIRERS);
+    group.bench_function("sharded", |b| {
+        b.iter_custom(|iters| contended(iters, || drop(black_box(&semaphore).try_get())));
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, contended_try_get);
+criterion_main!(benches);
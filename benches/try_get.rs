@@ -0,0 +1,28 @@
+//! Compares `try_get` followed by a drop on `raw::Semaphore` and the wrapper
+//!
+//! The wrapper only delegates to `raw`, so both should take the same time.
+//! Run with `cargo bench --bench try_get`
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use semaphorus::{raw, Semaphore};
+
+fn try_get_and_drop(c: &mut Criterion) {
+    let mut group = c.benchmark_group("try_get");
+
+    let semaphore = raw::Semaphore::new(1);
+    group.bench_function("raw", |b| {
+        b.iter(|| drop(black_box(&semaphore).try_get()));
+    });
+
+    let semaphore = Semaphore::new(0_u64, 1);
+    group.bench_function("wrapper", |b| {
+        b.iter(|| drop(black_box(&semaphore).try_get()));
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, try_get_and_drop);
+criterion_main!(benches);
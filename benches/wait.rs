@@ -0,0 +1,46 @@
+//! Measures how long a thread blocked in `Semaphore::get` takes to notice a released permit
+//!
+//! Compare the default wait loop against `crossbeam_utils::Backoff` with
+//! `cargo bench --bench wait` and `cargo bench --bench wait --features crossbeam`
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use semaphorus::Semaphore;
+
+fn acquire_after_release(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get");
+    group.sample_size(10);
+
+    group.bench_function("acquire after release", |b| {
+        b.iter_custom(|iters| {
+            let mut total = Duration::ZERO;
+            for _ in 0..iters {
+                let semaphore = Semaphore::new((), 1);
+                let held = semaphore.get();
+
+                thread::scope(|s| {
+                    let waiter = s.spawn(|| {
+                        let _guard = semaphore.get();
+                        Instant::now()
+                    });
+
+                    // Give the waiter time to enter the wait loop
+                    thread::sleep(Duration::from_millis(1));
+                    let released = Instant::now();
+                    drop(held);
+                    total += waiter.join().unwrap() - released;
+                });
+            }
+            total
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, acquire_after_release);
+criterion_main!(benches);
@@ -0,0 +1,43 @@
+//! Tracks the permits held by live connections in a `slab::Slab`
+//!
+//! Owned guards are `'static`, so an event loop can store them next to the connection
+//! and release the permit just by removing the entry
+
+use std::sync::{atomic::Ordering, Arc};
+
+use semaphorus::{OwnedSemaphoreGuard, Semaphore};
+use slab::Slab;
+
+struct Connection {
+    name: String,
+    _permit: OwnedSemaphoreGuard<()>,
+}
+
+fn main() {
+    let limiter = Arc::new(Semaphore::new((), 2));
+    let mut connections = Slab::new();
+
+    for name in ["alice", "bob", "carol"] {
+        match limiter.try_get_owned() {
+            Ok(permit) => {
+                let key = connections.insert(Connection {
+                    name: name.to_string(),
+                    _permit: permit,
+                });
+                println!("accepted {name} as connection {key}");
+            }
+            Err(err) => println!("rejected {name}: {err}"),
+        }
+    }
+
+    let closed = connections.remove(0);
+    println!("closed {}", closed.name);
+    drop(closed);
+    println!("{} permits in use", limiter.count(Ordering::SeqCst));
+
+    let key = connections.insert(Connection {
+        name: "carol".to_string(),
+        _permit: limiter.try_get_owned().unwrap(),
+    });
+    println!("accepted carol as connection {key}");
+}
@@ -0,0 +1,45 @@
+//! Hands held permits to worker threads over a channel
+//!
+//! The dispatcher only sends a job once it holds a permit, so the semaphore bounds how many
+//! jobs are in flight while the workers are the ones releasing the permits
+
+use std::{
+    sync::{atomic::Ordering, mpsc, Arc},
+    thread,
+};
+
+use semaphorus::{spawn_with_guard, OwnedSemaphoreGuard, Semaphore};
+
+fn main() {
+    let in_flight = Arc::new(Semaphore::new((), 2));
+    let (jobs, queue) = mpsc::channel::<(u32, OwnedSemaphoreGuard<()>)>();
+
+    let worker = thread::spawn(move || {
+        for (job, permit) in queue {
+            println!("working on job {job}");
+            drop(permit);
+        }
+    });
+
+    for job in 0..4 {
+        let permit = loop {
+            if let Ok(permit) = in_flight.try_get_owned() {
+                break permit;
+            }
+            thread::yield_now();
+        };
+        jobs.send((job, permit)).unwrap();
+    }
+    drop(jobs);
+    worker.join().unwrap();
+
+    let permit = in_flight.try_get_owned().unwrap();
+    let handle = spawn_with_guard(permit, |permit| {
+        println!(
+            "{} permit held by the spawned thread",
+            permit.semaphore().count(Ordering::SeqCst)
+        );
+    });
+    handle.join().unwrap();
+    println!("{} permits in use", in_flight.count(Ordering::SeqCst));
+}
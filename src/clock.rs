@@ -0,0 +1,99 @@
+//! Time sources for the timed blocking acquires, such as [`Semaphore::get_timeout_with_clock`][`crate::Semaphore::get_timeout_with_clock`]
+//!
+//! [`RealClock`] is used by default, [`ManualClock`] lets tests control time so timeouts
+//! can be checked without real sleeps
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::lock_ignoring_poison;
+
+/// The source of time and sleeping used while waiting with a timeout
+pub trait Clock {
+    /// Get the current time
+    fn now(&self) -> Instant;
+
+    /// Block the thread for `duration`, used by [`WaitStrategy::Sleep`][`crate::WaitStrategy::Sleep`]
+    fn sleep(&self, duration: Duration);
+}
+
+/// The system clock, using [`Instant::now`] and [`std::thread::sleep`]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RealClock;
+
+impl Clock for RealClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// A clock that only moves when it is told to
+///
+/// Sleeping advances the clock by the sleep's duration instead of blocking,
+/// so a wait with [`WaitStrategy::Sleep`][`crate::WaitStrategy::Sleep`] times out immediately and deterministically
+#[derive(Debug)]
+pub struct ManualClock {
+    start: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl ManualClock {
+    /// Create a clock stopped at the current time
+    #[must_use]
+    pub fn new() -> Self {
+        ManualClock {
+            start: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    /// Move the clock forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        *lock_ignoring_poison(&self.elapsed) += duration;
+    }
+
+    /// Get how far the clock has been moved since it was created
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        *lock_ignoring_poison(&self.elapsed)
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock_only_moves_when_told() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+
+        clock.advance(Duration::from_secs(2));
+        clock.sleep(Duration::from_millis(500));
+
+        assert_eq!(clock.now() - start, Duration::from_millis(2500));
+        assert_eq!(clock.elapsed(), Duration::from_millis(2500));
+    }
+}
@@ -0,0 +1,135 @@
+//! A semaphore whose max is known at compile time, see [`ConstSemaphore`]
+
+use core::sync::atomic::Ordering;
+
+use crate::raw;
+
+/// A [`raw::Semaphore`] with a max of `MAX` that can be created in `const` context
+///
+/// This is meant for `static` resource tables where part of the table is known to be in use
+/// before the program starts, see [`ConstSemaphore::with_initial`].
+/// Only the methods that can't change the max are forwarded to the [`raw::Semaphore`], so it is always `MAX`
+///
+/// ```
+/// use semaphorus::ConstSemaphore;
+///
+/// // Two of the eight descriptors are reserved for stdin and stdout
+/// static DESCRIPTORS: ConstSemaphore<8> = ConstSemaphore::with_initial(2);
+///
+/// let guards: Vec<_> = (0..6).map(|_| DESCRIPTORS.try_get().unwrap()).collect();
+/// assert!(DESCRIPTORS.try_get().is_err());
+/// drop(guards);
+/// ```
+pub struct ConstSemaphore<const MAX: usize> {
+    raw: raw::Semaphore,
+}
+
+impl<const MAX: usize> ConstSemaphore<MAX> {
+    /// Create a semaphore with a max of `MAX` and nothing in use
+    #[must_use]
+    pub const fn new() -> Self {
+        ConstSemaphore::with_initial(0)
+    }
+
+    /// Create a semaphore with a max of `MAX` where `initial` permits are already in use
+    ///
+    /// The initial permits aren't held by any guard, so they stay in use for the life of the semaphore
+    /// # Panics
+    /// Panics if `initial` > `MAX`, which is a compile error when the semaphore is created in `const` context
+    #[must_use]
+    pub const fn with_initial(initial: usize) -> Self {
+        assert!(
+            initial <= MAX,
+            "A ConstSemaphore can't start with more than MAX permits in use"
+        );
+
+        let mut raw = raw::Semaphore::new(MAX);
+        raw.count = raw::Count::new(initial);
+        ConstSemaphore { raw }
+    }
+
+    /// Try to take a permit, see [`raw::Semaphore::try_get`]
+    /// # Errors
+    /// Will error in the same cases as [`raw::Semaphore::try_get`]
+    #[inline]
+    pub fn try_get(&self) -> Result<raw::SemaphoreGuard<'_>, crate::SemaphoreError> {
+        self.raw.try_get()
+    }
+
+    /// Try to take `permits` permits at once, see [`raw::Semaphore::try_get_many`]
+    /// # Errors
+    /// Will error in the same cases as [`raw::Semaphore::try_get_many`]
+    pub fn try_get_many(
+        &self,
+        permits: usize,
+    ) -> Result<raw::SemaphoreGuard<'_>, crate::SemaphoreError> {
+        self.raw.try_get_many(permits)
+    }
+
+    /// Get the current number of permits in use, see [`raw::Semaphore::count`]
+    #[must_use]
+    #[inline]
+    #[track_caller]
+    pub fn count(&self, ordering: Ordering) -> usize {
+        self.raw.count(ordering)
+    }
+
+    /// Get how many more permits can be taken right now, see [`raw::Semaphore::available`]
+    #[must_use]
+    #[inline]
+    #[track_caller]
+    pub fn available(&self, ordering: Ordering) -> usize {
+        self.raw.available(ordering)
+    }
+
+    /// Returns true if every permit is in use, see [`raw::Semaphore::at_max`]
+    #[must_use]
+    #[inline]
+    #[track_caller]
+    pub fn at_max(&self, ordering: Ordering) -> bool {
+        self.raw.at_max(ordering)
+    }
+
+    /// Get the maximum number of permits, which is always `MAX`
+    #[must_use]
+    #[inline]
+    pub const fn max(&self) -> usize {
+        MAX
+    }
+
+    /// Stop handing out permits, see [`raw::Semaphore::close`]
+    pub fn close(&self) {
+        self.raw.close();
+    }
+
+    /// Returns true once [`ConstSemaphore::close`] was called
+    #[must_use]
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.raw.is_closed()
+    }
+}
+
+impl<const MAX: usize> Default for ConstSemaphore<MAX> {
+    fn default() -> Self {
+        ConstSemaphore::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_initial_in_const_context() {
+        let pool = const { ConstSemaphore::<4>::with_initial(3) };
+
+        assert_eq!(pool.count(Ordering::SeqCst), 3);
+        let guard = pool.try_get().unwrap();
+        assert!(pool.try_get().is_err());
+
+        drop(guard);
+        assert_eq!(pool.count(Ordering::SeqCst), 3);
+        assert_eq!((pool.max(), pool.available(Ordering::SeqCst)), (4, 1));
+    }
+}
@@ -0,0 +1,195 @@
+//! A semaphore that owns its data and carries its max in the type, see [`FixedSemaphore`]
+
+use crate::SemaphoreError;
+use core::{
+    ops::Deref,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Allows up to `MAX` references to the data, where `MAX` is known at compile time
+///
+/// Only the count is stored, so the semaphore is one `usize` bigger than the data and every bound check
+/// compares against a constant. It has none of the settings of [`Semaphore`][`crate::Semaphore`]:
+/// [`FixedSemaphore::get`] always spins and there is no poisoning or closing.
+/// A `MAX` of 0 is a compile error
+///
+/// ```
+/// use semaphorus::fixed::FixedSemaphore;
+///
+/// static POOL: FixedSemaphore<(), 2> = FixedSemaphore::new(());
+///
+/// let _first = POOL.try_get().unwrap();
+/// let _second = POOL.try_get().unwrap();
+/// assert!(POOL.try_get().is_err());
+/// ```
+pub struct FixedSemaphore<T: ?Sized, const MAX: usize> {
+    count: AtomicUsize,
+    data: T,
+}
+
+impl<T: ?Sized, const MAX: usize> FixedSemaphore<T, MAX> {
+    /// Evaluated by every constructor, so naming a `FixedSemaphore<T, 0>` that is created fails to compile
+    const NONZERO: () = assert!(
+        MAX != 0,
+        "A FixedSemaphore with a MAX of 0 can never be acquired"
+    );
+
+    /// Attempt to get the value in the semaphore
+    ///
+    /// This function will never block
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if the semaphore is at `MAX`
+    pub fn try_get(&self) -> Result<FixedGuard<'_, T, MAX>, SemaphoreError> {
+        self.count
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count < MAX).then_some(count + 1)
+            })
+            .map_err(|_| SemaphoreError::AtMaxCount)?;
+        Ok(FixedGuard { semaphore: self })
+    }
+
+    /// Get the value in the semaphore, spinning until a permit is available
+    /// # Panics
+    /// This function will panic with `no_block` if the semaphore is at `MAX`
+    pub fn get(&self) -> FixedGuard<'_, T, MAX> {
+        loop {
+            match self.try_get() {
+                Ok(guard) => return guard,
+                Err(err) if cfg!(feature = "no_block") => {
+                    panic!("FixedSemaphore::get failed: {err}")
+                }
+                #[cfg(feature = "std")]
+                Err(_) => std::thread::yield_now(),
+                #[cfg(not(feature = "std"))]
+                Err(_) => core::hint::spin_loop(),
+            }
+        }
+    }
+
+    /// Returns true if the semaphore is at `MAX`
+    #[must_use]
+    pub fn at_max(&self, ordering: Ordering) -> bool {
+        self.count.load(ordering) >= MAX
+    }
+
+    /// Get the current number of references
+    #[must_use]
+    pub fn count(&self, ordering: Ordering) -> usize {
+        self.count.load(ordering)
+    }
+
+    /// Get how many more references can be taken right now
+    #[must_use]
+    pub fn available_permits(&self, ordering: Ordering) -> usize {
+        MAX - self.count.load(ordering)
+    }
+
+    /// Get a mutable reference to the data in the semaphore
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+}
+
+impl<T, const MAX: usize> FixedSemaphore<T, MAX> {
+    /// Create a new semaphore with 0 counted references
+    #[must_use]
+    pub const fn new(value: T) -> Self {
+        let () = Self::NONZERO;
+        FixedSemaphore {
+            count: AtomicUsize::new(0),
+            data: value,
+        }
+    }
+
+    /// Move the value out of the semaphore
+    pub fn into_inner(self) -> T {
+        self.data
+    }
+}
+
+impl<T: ?Sized, const MAX: usize> core::fmt::Debug for FixedSemaphore<T, MAX> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("FixedSemaphore")
+            .field("count", &self.count(Ordering::Relaxed))
+            .field("max", &MAX)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A reference to the data in a [`FixedSemaphore`]
+/// Decrements the count when it is dropped
+#[must_use = "if unused, the guard will immediatly unlock"]
+pub struct FixedGuard<'guard, T: ?Sized, const MAX: usize> {
+    semaphore: &'guard FixedSemaphore<T, MAX>,
+}
+
+impl<T: ?Sized, const MAX: usize> Deref for FixedGuard<'_, T, MAX> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.semaphore.data
+    }
+}
+
+impl<T: ?Sized, const MAX: usize> Drop for FixedGuard<'_, T, MAX> {
+    #[inline]
+    fn drop(&mut self) {
+        self.semaphore.count.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maximum_count_works() {
+        let semaphore = FixedSemaphore::<(), 4>::new(());
+
+        let (g1, g2, g3, g4) = (
+            semaphore.try_get(),
+            semaphore.try_get(),
+            semaphore.try_get(),
+            semaphore.try_get(),
+        );
+
+        assert_eq!(
+            (g1.is_ok(), g2.is_ok(), g3.is_ok(), g4.is_ok()),
+            (true, true, true, true)
+        );
+        assert!(semaphore.at_max(Ordering::SeqCst));
+        assert_eq!(semaphore.available_permits(Ordering::SeqCst), 0);
+
+        let g5 = semaphore.try_get();
+
+        assert!(g5.is_err());
+
+        drop(g1);
+
+        let g6 = semaphore.try_get();
+
+        assert!(g6.is_ok());
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_get_waits_for_release() {
+        let semaphore = FixedSemaphore::<_, 1>::new(AtomicUsize::new(0));
+
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        let guard = semaphore.get();
+                        assert_eq!(guard.fetch_add(1, Ordering::SeqCst), 0);
+                        guard.fetch_sub(1, Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,80 @@
+//! The [`global_semaphore!`][`crate::global_semaphore`] macro for limiters shared by the whole process
+
+/// Declare a module holding a global `Semaphore<()>` that is created with `max` on first use
+///
+/// The semaphore lives in a [`std::sync::OnceLock`], so `max` can be any expression, it is evaluated once
+/// by whichever thread gets there first. The module has `semaphore()` to reach the semaphore itself,
+/// and `try_get()` and `get()` which forward to it. `max` is evaluated inside the module,
+/// items from the surrounding module are in scope
+///
+/// ```
+/// semaphorus::global_semaphore!(pub DOWNLOADS, 2);
+///
+/// let _first = DOWNLOADS::get();
+/// let _second = DOWNLOADS::try_get().unwrap();
+/// assert!(DOWNLOADS::try_get().is_err());
+/// ```
+#[macro_export]
+macro_rules! global_semaphore {
+    ($(#[$attr:meta])* $vis:vis $name:ident, $max:expr) => {
+        $(#[$attr])*
+        #[allow(non_snake_case)]
+        $vis mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            static SEMAPHORE: ::std::sync::OnceLock<$crate::Semaphore<()>> =
+                ::std::sync::OnceLock::new();
+
+            /// Get the global semaphore, creating it if this is the first use
+            #[must_use]
+            pub fn semaphore() -> &'static $crate::Semaphore<()> {
+                SEMAPHORE.get_or_init(|| $crate::Semaphore::new((), $max))
+            }
+
+            /// Attempt to get a permit from the global semaphore, see [`Semaphore::try_get`][`$crate::Semaphore::try_get`]
+            /// # Errors
+            /// Fails in the same cases as `Semaphore::try_get`
+            pub fn try_get(
+            ) -> ::core::result::Result<$crate::SemaphoreGuard<'static, ()>, $crate::SemaphoreError> {
+                semaphore().try_get()
+            }
+
+            /// Block until the global semaphore has a permit, see [`Semaphore::get`][`$crate::Semaphore::get`]
+            /// # Panics
+            /// Panics in the same cases as `Semaphore::get`
+            pub fn get() -> $crate::SemaphoreGuard<'static, ()> {
+                semaphore().get()
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::Ordering;
+
+    const LIMIT: usize = 3;
+
+    crate::global_semaphore!(LIMITER, LIMIT);
+
+    #[test]
+    fn test_threads_share_one_instance() {
+        let guard = LIMITER::try_get().unwrap();
+
+        let (there, count) = std::thread::spawn(|| {
+            let _guard = LIMITER::get();
+            (
+                LIMITER::semaphore(),
+                LIMITER::semaphore().count(Ordering::SeqCst),
+            )
+        })
+        .join()
+        .unwrap();
+
+        assert!(core::ptr::eq(LIMITER::semaphore(), there));
+        assert_eq!(count, 2);
+        assert_eq!(there.max(), LIMIT);
+        drop(guard);
+    }
+}
@@ -0,0 +1,124 @@
+//! A semaphore that also counts against a shared parent, see [`ChildSemaphore`]
+
+use crate::{Semaphore, SemaphoreError, SemaphoreGuard};
+use core::{ops::Deref, sync::atomic::Ordering};
+use std::sync::Arc;
+
+/// Allows up to `max` references to the data, while also counting against a shared parent
+///
+/// Every child permit also takes a permit from the parent, so a subsystem can be bounded by
+/// its own max and a global max at the same time
+pub struct ChildSemaphore<T: ?Sized> {
+    parent: Arc<Semaphore<()>>,
+    semaphore: Semaphore<T>,
+}
+
+impl<T: ?Sized> ChildSemaphore<T> {
+    /// Attempt to get the value in the semaphore, taking a permit from both the child and the parent
+    ///
+    /// If either is at its max, the permit already taken from the other is released again.
+    /// This function will never block
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if the child or the parent is at its maximum count,
+    /// or [`SemaphoreError::Poisoned`] if either is poisoned
+    pub fn try_get(&self) -> Result<ChildGuard<'_, T>, SemaphoreError> {
+        let guard = self.semaphore.try_get()?;
+        let parent = self.parent.try_get()?;
+        Ok(ChildGuard {
+            guard,
+            _parent: parent,
+        })
+    }
+
+    /// Get the current number of references held through this child
+    #[must_use]
+    pub fn count(&self, ordering: Ordering) -> usize {
+        self.semaphore.count(ordering)
+    }
+
+    /// Get how many permits can be taken through this child right now, accounting for the parent
+    ///
+    /// This is the smaller of the child's and the parent's available permits. The two are read
+    /// one after the other, so with concurrent acquires the result may already be stale across
+    /// both levels and [`ChildSemaphore::try_get`] can still fail
+    #[must_use]
+    pub fn effective_available(&self, ordering: Ordering) -> usize {
+        self.semaphore
+            .available(ordering)
+            .min(self.parent.available(ordering))
+    }
+
+    /// Get the parent semaphore shared with the other children
+    #[must_use]
+    pub fn parent(&self) -> &Arc<Semaphore<()>> {
+        &self.parent
+    }
+
+    /// Get a mutable reference to the data in the semaphore
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.semaphore.get_mut()
+    }
+}
+
+impl<T> ChildSemaphore<T> {
+    /// Create a new child of `parent` allowing `max` references to `value`
+    pub fn new(parent: Arc<Semaphore<()>>, value: T, max: usize) -> Self {
+        ChildSemaphore {
+            parent,
+            semaphore: Semaphore::new(value, max),
+        }
+    }
+
+    /// Move the value out of the semaphore
+    pub fn into_inner(self) -> T {
+        self.semaphore.into_inner()
+    }
+}
+
+/// A reference to the data in a [`ChildSemaphore`]
+/// Automatically releases the child's permit and then the parent's when it is dropped
+#[must_use = "if unused, the guard will immediatly unlock"]
+pub struct ChildGuard<'guard, T: ?Sized> {
+    guard: SemaphoreGuard<'guard, T>,
+    _parent: SemaphoreGuard<'guard, ()>,
+}
+
+impl<T: ?Sized> Deref for ChildGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_children_are_bounded_by_parent() {
+        let parent = Arc::new(Semaphore::new((), 5));
+        let first = ChildSemaphore::new(Arc::clone(&parent), "first", 10);
+        let second = ChildSemaphore::new(Arc::clone(&parent), "second", 10);
+
+        let mut held: Vec<_> = (0..3).map(|_| first.try_get().unwrap()).collect();
+        held.extend((0..2).map(|_| second.try_get().unwrap()));
+        assert!(first.try_get().is_err());
+        assert!(second.try_get().is_err());
+        // The failed acquires released the child permits they had taken
+        assert_eq!(first.count(Ordering::SeqCst), 3);
+        assert_eq!(second.count(Ordering::SeqCst), 2);
+        assert_eq!(parent.count(Ordering::SeqCst), 5);
+
+        assert_eq!(first.effective_available(Ordering::SeqCst), 0);
+
+        drop(held.pop());
+        assert_eq!(first.effective_available(Ordering::SeqCst), 1);
+        assert_eq!(second.semaphore.available(Ordering::SeqCst), 9);
+        assert_eq!(*second.try_get().unwrap(), "second");
+        drop(held);
+        assert_eq!(parent.count(Ordering::SeqCst), 0);
+    }
+}
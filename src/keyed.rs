@@ -0,0 +1,324 @@
+//! A semaphore with a separate limit for every key, see [`KeyedSemaphore`]
+
+use crate::{lock_ignoring_poison, raw, SemaphoreError};
+use core::{hash::Hash, ops::Deref, sync::atomic::Ordering, time::Duration};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+/// Controls when idle keys are removed from a [`KeyedSemaphore`]
+///
+/// Eviction is lazy, it only runs when a new key is inserted, so there is no background thread.
+/// Evicting aggressively keeps memory low but means a key that comes back has to allocate a new semaphore,
+/// while evicting rarely avoids that re-creation cost at the price of holding on to more idle keys
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Evict keys with a count of 0 that haven't been acquired for at least this long
+    ///
+    /// `IdleFor(Duration::ZERO)` evicts every idle key and is the default
+    IdleFor(Duration),
+    /// Keep at most this many keys, evicting the least recently acquired idle keys first
+    ///
+    /// Keys that are currently held are never evicted, so the cap can be exceeded while they are in use
+    MaxKeys(usize),
+}
+
+impl Default for EvictionPolicy {
+    fn default() -> Self {
+        EvictionPolicy::IdleFor(Duration::ZERO)
+    }
+}
+
+struct Entry {
+    semaphore: Arc<raw::Semaphore>,
+    last_used: Instant,
+}
+
+impl Entry {
+    fn is_idle(&self) -> bool {
+        self.semaphore.count(Ordering::SeqCst) == 0
+    }
+}
+
+/// Allows up to `max` references to the data per key, and optionally a total max across all keys
+///
+/// Each key gets its own [`raw::Semaphore`] the first time it is used,
+/// so `max` concurrent holders of one key never block another key unless the total max is reached.
+/// Idle keys are evicted lazily according to an [`EvictionPolicy`]
+pub struct KeyedSemaphore<K, T: ?Sized> {
+    max: usize,
+    total: Option<raw::Semaphore>,
+    eviction: EvictionPolicy,
+    semaphores: Mutex<HashMap<K, Entry>>,
+    data: T,
+}
+
+impl<K: Eq + Hash + Clone, T: ?Sized> KeyedSemaphore<K, T> {
+    /// Attempt to get the value in the semaphore for `key`
+    ///
+    /// This function will never block on the count, but does briefly lock the key map
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if the count for `key` is >= the maximum count,
+    /// or if every permit of the total max is held
+    pub fn try_get(&self, key: K) -> Result<KeyedGuard<'_, K, T>, SemaphoreError> {
+        // Released again by dropping it if the key is at its max
+        let total = self
+            .total
+            .as_ref()
+            .map(raw::Semaphore::try_get)
+            .transpose()?;
+        let mut semaphores = lock_ignoring_poison(&self.semaphores);
+        let now = Instant::now();
+
+        if !semaphores.contains_key(&key) {
+            self.evict(&mut semaphores, now);
+        }
+        let entry = semaphores.entry(key.clone()).or_insert_with(|| Entry {
+            semaphore: Arc::new(raw::Semaphore::new(self.max)),
+            last_used: now,
+        });
+
+        // Acquiring while the map is locked means an entry can't be evicted between
+        // looking it up and incrementing its count
+        entry.semaphore.try_get()?.detach();
+        entry.last_used = now;
+
+        Ok(KeyedGuard {
+            key,
+            semaphore: Arc::clone(&entry.semaphore),
+            _total: total,
+            data: &self.data,
+        })
+    }
+
+    fn evict(&self, semaphores: &mut HashMap<K, Entry>, now: Instant) {
+        match self.eviction {
+            EvictionPolicy::IdleFor(idle) => semaphores
+                .retain(|_, entry| !entry.is_idle() || now.duration_since(entry.last_used) < idle),
+            EvictionPolicy::MaxKeys(max_keys) => {
+                if semaphores.len() < max_keys {
+                    return;
+                }
+                let mut idle: Vec<_> = semaphores
+                    .iter()
+                    .filter(|(_, entry)| entry.is_idle())
+                    .map(|(key, entry)| (entry.last_used, key.clone()))
+                    .collect();
+                idle.sort_unstable_by_key(|(last_used, _)| *last_used);
+
+                let excess = semaphores.len() + 1 - max_keys;
+                for (_, key) in idle.into_iter().take(excess) {
+                    semaphores.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Get the current number of references held for `key`
+    #[must_use]
+    pub fn count(&self, key: &K, ordering: Ordering) -> usize {
+        lock_ignoring_poison(&self.semaphores)
+            .get(key)
+            .map_or(0, |entry| entry.semaphore.count(ordering))
+    }
+
+    /// Get the number of keys currently tracked, including idle keys that haven't been evicted yet
+    #[must_use]
+    pub fn tracked_keys(&self) -> usize {
+        lock_ignoring_poison(&self.semaphores).len()
+    }
+
+    /// Get the maximum count for each key
+    #[must_use]
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Get the maximum count across all keys, if there is one
+    #[must_use]
+    pub fn total_max(&self) -> Option<usize> {
+        self.total.as_ref().map(raw::Semaphore::max)
+    }
+
+    /// Get a mutable reference to the data in the semaphore
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the eviction policy used for idle keys
+    #[must_use]
+    pub fn eviction_policy(&self) -> EvictionPolicy {
+        self.eviction
+    }
+}
+
+impl<K, T> KeyedSemaphore<K, T> {
+    /// Create a new keyed semaphore allowing `max` references per key
+    ///
+    /// Idle keys are evicted as soon as a new key is inserted
+    pub fn new(value: T, max: usize) -> Self {
+        Self::new_with_eviction(value, max, EvictionPolicy::default())
+    }
+
+    /// Create a new keyed semaphore allowing `max` references per key, evicting idle keys with `eviction`
+    pub fn new_with_eviction(value: T, max: usize, eviction: EvictionPolicy) -> Self {
+        debug_assert_ne!(
+            max, 0,
+            "A semaphore with a maximum count of '0' generally useless"
+        );
+
+        KeyedSemaphore {
+            max,
+            total: None,
+            eviction,
+            semaphores: Mutex::new(HashMap::new()),
+            data: value,
+        }
+    }
+
+    /// Also limit the references across all keys to `total_max`
+    ///
+    /// Every guard takes a permit from the total as well as from its key, so a key below its max
+    /// can still fail while the other keys hold the whole total
+    #[must_use]
+    pub fn with_total_max(mut self, total_max: usize) -> Self {
+        self.total = Some(raw::Semaphore::new(total_max));
+        self
+    }
+
+    /// Move the value out of the semaphore
+    pub fn into_inner(self) -> T {
+        self.data
+    }
+}
+
+/// A reference to the data in a [`KeyedSemaphore`]
+/// Automatically decrements the count for its key, and then the total, when it is dropped
+#[must_use = "if unused, the guard will immediatly unlock"]
+pub struct KeyedGuard<'guard, K, T: ?Sized> {
+    key: K,
+    semaphore: Arc<raw::Semaphore>,
+    _total: Option<raw::SemaphoreGuard<'guard>>,
+    data: &'guard T,
+}
+
+impl<K, T: ?Sized> KeyedGuard<'_, K, T> {
+    /// Get the key this guard was acquired for
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+}
+
+impl<K, T: ?Sized> Deref for KeyedGuard<'_, K, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.data
+    }
+}
+
+impl<K, T: ?Sized> Drop for KeyedGuard<'_, K, T> {
+    fn drop(&mut self) {
+        self.semaphore.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_are_limited_independently() {
+        let semaphore = KeyedSemaphore::new((), 2);
+
+        let a1 = semaphore.try_get("a").unwrap();
+        let _a2 = semaphore.try_get("a").unwrap();
+        assert!(semaphore.try_get("a").is_err());
+
+        let b1 = semaphore.try_get("b").unwrap();
+        assert_eq!(b1.key(), &"b");
+        assert_eq!(semaphore.count(&"a", Ordering::SeqCst), 2);
+        assert_eq!(semaphore.count(&"b", Ordering::SeqCst), 1);
+
+        drop(a1);
+        assert!(semaphore.try_get("a").is_ok());
+    }
+
+    #[test]
+    fn test_total_max_is_shared_by_all_keys() {
+        let semaphore = KeyedSemaphore::new((), 2).with_total_max(3);
+        assert_eq!(semaphore.total_max(), Some(3));
+
+        let a1 = semaphore.try_get("a").unwrap();
+        let _a2 = semaphore.try_get("a").unwrap();
+        // A key at its own max doesn't use up a permit of the total
+        assert!(semaphore.try_get("a").is_err());
+        let _b1 = semaphore.try_get("b").unwrap();
+        assert!(matches!(
+            semaphore.try_get("c"),
+            Err(SemaphoreError::AtMaxCount)
+        ));
+
+        drop(a1);
+        assert!(semaphore.try_get("c").is_ok());
+    }
+
+    #[test]
+    fn test_idle_keys_are_evicted() {
+        let semaphore = KeyedSemaphore::new((), 1);
+
+        drop(semaphore.try_get(1).unwrap());
+        drop(semaphore.try_get(2).unwrap());
+        let _held = semaphore.try_get(3).unwrap();
+        assert_eq!(semaphore.tracked_keys(), 1);
+
+        let _other = semaphore.try_get(4).unwrap();
+        assert_eq!(semaphore.tracked_keys(), 2);
+        assert_eq!(semaphore.count(&1, Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_recently_used_keys_survive_idle_eviction() {
+        let semaphore = KeyedSemaphore::new_with_eviction(
+            (),
+            1,
+            EvictionPolicy::IdleFor(Duration::from_mins(1)),
+        );
+
+        drop(semaphore.try_get(1).unwrap());
+        drop(semaphore.try_get(2).unwrap());
+        assert_eq!(semaphore.tracked_keys(), 2);
+    }
+
+    #[test]
+    fn test_max_keys_evicts_least_recently_used() {
+        let semaphore = KeyedSemaphore::new_with_eviction((), 1, EvictionPolicy::MaxKeys(2));
+
+        drop(semaphore.try_get(1).unwrap());
+        std::thread::sleep(Duration::from_millis(1));
+        drop(semaphore.try_get(2).unwrap());
+        std::thread::sleep(Duration::from_millis(1));
+        drop(semaphore.try_get(1).unwrap());
+
+        drop(semaphore.try_get(3).unwrap());
+        assert_eq!(semaphore.tracked_keys(), 2);
+
+        let keys: Vec<_> = lock_ignoring_poison(&semaphore.semaphores)
+            .keys()
+            .copied()
+            .collect();
+        assert!(keys.contains(&1) && keys.contains(&3));
+    }
+
+    #[test]
+    fn test_max_keys_never_evicts_held_keys() {
+        let semaphore = KeyedSemaphore::new_with_eviction((), 1, EvictionPolicy::MaxKeys(1));
+
+        let _held = semaphore.try_get(1).unwrap();
+        let _other = semaphore.try_get(2).unwrap();
+        assert_eq!(semaphore.tracked_keys(), 2);
+    }
+}
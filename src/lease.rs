@@ -0,0 +1,198 @@
+//! A semaphore whose permits expire after a ttl, see [`LeasedSemaphore`]
+
+use crate::{lock_ignoring_poison, raw, SemaphoreError};
+use core::{
+    ops::Deref,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use std::{sync::Mutex, time::Instant};
+
+/// Allows up to `max` references to the data, where every permit is a lease that expires after its ttl
+///
+/// An expired lease is reclaimed the next time the semaphore is accessed, even if its guard is still alive,
+/// so a hung holder can't keep a permit forever. The guard of a reclaimed lease still derefs to the data,
+/// but its permit may already belong to someone else, and dropping it doesn't release anything
+pub struct LeasedSemaphore<T: ?Sized> {
+    raw: raw::Semaphore,
+    /// The id and expiry of every lease whose permit hasn't been handed back yet, `None` never expires
+    leases: Mutex<Vec<(u64, Option<Instant>)>>,
+    next_id: AtomicU64,
+    data: T,
+}
+
+impl<T: ?Sized> LeasedSemaphore<T> {
+    /// Attempt to lease a permit that expires after `ttl`
+    ///
+    /// Expired leases are reclaimed first. A `ttl` too large to add to [`Instant::now`] never expires.
+    /// This function will never block
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if every permit is leased,
+    /// or [`SemaphoreError::Poisoned`] if the semaphore is poisoned
+    pub fn try_lease(&self, ttl: Duration) -> Result<LeaseGuard<'_, T>, SemaphoreError> {
+        self.reclaim_expired();
+        let expires_at = Instant::now().checked_add(ttl);
+        self.raw.try_get()?.detach();
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        lock_ignoring_poison(&self.leases).push((id, expires_at));
+        Ok(LeaseGuard {
+            semaphore: self,
+            id,
+            expires_at,
+        })
+    }
+
+    /// Get how many more leases can be taken right now, reclaiming the expired ones first
+    #[must_use]
+    pub fn available(&self, ordering: Ordering) -> usize {
+        self.reclaim_expired();
+        self.raw.available(ordering)
+    }
+
+    /// Get the number of leases that haven't expired or been dropped, reclaiming the expired ones first
+    #[must_use]
+    pub fn count(&self, ordering: Ordering) -> usize {
+        self.reclaim_expired();
+        self.raw.count(ordering)
+    }
+
+    /// Get a mutable reference to the data in the semaphore
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Hand back the permits of every lease that has expired
+    fn reclaim_expired(&self) {
+        let now = Instant::now();
+        let expired = {
+            let mut leases = lock_ignoring_poison(&self.leases);
+            let before = leases.len();
+            leases.retain(|(_, expires_at)| expires_at.is_none_or(|expires_at| expires_at > now));
+            before - leases.len()
+        };
+        // Released after unlocking, so callbacks woken by the release can lease again
+        if expired > 0 {
+            self.raw.release_many(expired);
+        }
+    }
+}
+
+impl<T> LeasedSemaphore<T> {
+    /// Create a new semaphore with no leases
+    pub fn new(value: T, max: usize) -> Self {
+        LeasedSemaphore {
+            raw: raw::Semaphore::new(max),
+            leases: Mutex::new(Vec::new()),
+            next_id: AtomicU64::new(0),
+            data: value,
+        }
+    }
+
+    /// Move the value out of the semaphore
+    pub fn into_inner(self) -> T {
+        self.data
+    }
+}
+
+/// A lease on the data in a [`LeasedSemaphore`]
+/// Releases its permit when dropped, unless the lease expired and was already reclaimed
+#[must_use = "if unused, the lease will immediatly be released"]
+pub struct LeaseGuard<'guard, T: ?Sized> {
+    semaphore: &'guard LeasedSemaphore<T>,
+    id: u64,
+    expires_at: Option<Instant>,
+}
+
+impl<T: ?Sized> LeaseGuard<'_, T> {
+    /// Get when the lease expires and its permit can be reclaimed, `None` if it never expires
+    #[must_use]
+    pub fn expires_at(&self) -> Option<Instant> {
+        self.expires_at
+    }
+
+    /// Get how long is left until the lease expires, zero if it already has
+    /// and [`Duration::MAX`] if it never expires
+    #[must_use]
+    pub fn remaining(&self) -> Duration {
+        self.expires_at.map_or(Duration::MAX, |expires_at| {
+            expires_at.saturating_duration_since(Instant::now())
+        })
+    }
+
+    /// Returns true once the lease has expired
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| Instant::now() >= expires_at)
+    }
+}
+
+impl<T: ?Sized> Deref for LeaseGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.semaphore.data
+    }
+}
+
+impl<T: ?Sized> Drop for LeaseGuard<'_, T> {
+    fn drop(&mut self) {
+        // Only a lease that is still listed holds a permit, a reclaimed one was released already
+        let held = {
+            let mut leases = lock_ignoring_poison(&self.semaphore.leases);
+            leases
+                .iter()
+                .position(|(id, _)| *id == self.id)
+                .map(|index| leases.swap_remove(index))
+                .is_some()
+        };
+        if held {
+            self.semaphore.raw.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expired_lease_is_reclaimed_once() {
+        let semaphore = LeasedSemaphore::new("data", 2);
+
+        let stuck = semaphore.try_lease(Duration::from_millis(10)).unwrap();
+        let live = semaphore.try_lease(Duration::from_mins(1)).unwrap();
+        assert_eq!(*stuck, "data");
+        assert!(semaphore.try_lease(Duration::from_mins(1)).is_err());
+        assert!(live.expires_at() > stuck.expires_at());
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(stuck.is_expired());
+        assert_eq!(stuck.remaining(), Duration::ZERO);
+        assert_eq!(semaphore.available(Ordering::SeqCst), 1);
+        let replacement = semaphore.try_lease(Duration::from_mins(1)).unwrap();
+
+        // The reclaimed lease must not release the permit its replacement now holds
+        drop(stuck);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 2);
+        drop((live, replacement));
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_huge_ttl_never_expires() {
+        let semaphore = LeasedSemaphore::new((), 1);
+
+        let lease = semaphore.try_lease(Duration::MAX).unwrap();
+        assert_eq!(lease.expires_at(), None);
+        assert_eq!(lease.remaining(), Duration::MAX);
+        assert!(!lease.is_expired());
+        assert_eq!(semaphore.available(Ordering::SeqCst), 0);
+
+        drop(lease);
+        assert_eq!(semaphore.available(Ordering::SeqCst), 1);
+    }
+}
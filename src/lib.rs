@@ -1,4 +1,5 @@
-#![cfg_attr(any(feature = "nightly", doc), feature(negative_impls))]
+#![cfg_attr(any(feature = "nightly", docsrs), feature(negative_impls))]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
 #![deny(clippy::all)]
 #![warn(clippy::pedantic)]
 
@@ -6,26 +7,230 @@
 
 pub mod raw;
 
+pub use raw::{Parker, SemaphoreSnapshot, WaitStrategy, WakeOrder};
+
+pub mod constant;
+
+pub use constant::ConstSemaphore;
+
+#[cfg(feature = "std")]
+pub mod clock;
+
+#[cfg(feature = "wrapper")]
+pub mod fixed;
+
+#[cfg(all(feature = "std", feature = "wrapper"))]
+mod global;
+
+#[cfg(all(feature = "std", feature = "wrapper"))]
+pub mod hierarchy;
+
+#[cfg(feature = "std")]
+pub mod keyed;
+
+#[cfg(all(feature = "std", feature = "wrapper"))]
+pub mod lease;
+
+#[cfg(all(feature = "std", feature = "wrapper"))]
+pub mod queue;
+
+#[cfg(all(feature = "std", feature = "wrapper"))]
+pub mod reentrant;
+
+#[cfg(all(feature = "std", feature = "wrapper"))]
+pub mod registry;
+
+#[cfg(all(feature = "std", feature = "wrapper"))]
+pub mod shared;
+
+#[cfg(feature = "sharded")]
+pub mod sharded;
+
 #[cfg(feature = "wrapper")]
 pub mod wrapper;
 
 #[cfg(feature = "wrapper")]
 pub use wrapper::*;
 
-#[derive(Clone, Debug)]
+#[cfg(feature = "async")]
+pub mod stream;
+
+#[cfg(any(feature = "std", feature = "embassy"))]
+mod wait;
+
+/// Why a semaphore couldn't hand out a permit
+///
+/// Errors can be compared with `==`, but the enum is `#[non_exhaustive]`,
+/// so an exhaustive `match` outside this crate still needs a wildcard arm
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[non_exhaustive]
 pub enum SemaphoreError {
     /// The semaphore was already at the maximum amount of references
     AtMaxCount,
+    /// A guard was dropped while its thread was panicking
+    ///
+    /// Only returned by semaphores created with `new_poisoning`
+    Poisoned,
+    /// A blocking acquire had to wait after [`shutdown_all`] was called
+    ShuttingDown,
+    /// The semaphore was closed with `close`, it hands out no more permits
+    Closed,
+    /// Every acquire allowed by `new_with_total_cap` was used up
+    Exhausted,
+    /// `try_new` was given a max of 0, which no acquire could ever succeed against
+    InvalidMax,
 }
 
 impl core::fmt::Display for SemaphoreError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             SemaphoreError::AtMaxCount => write!(f, "Already at maximum count!"),
+            SemaphoreError::Poisoned => write!(f, "Semaphore is poisoned!"),
+            SemaphoreError::ShuttingDown => write!(f, "Semaphores are shutting down!"),
+            SemaphoreError::Closed => write!(f, "Semaphore is closed!"),
+            SemaphoreError::Exhausted => write!(f, "Semaphore has no acquires left!"),
+            SemaphoreError::InvalidMax => write!(f, "Semaphore max can't be 0!"),
         }
     }
 }
 
 #[cfg(feature = "std")]
 impl std::error::Error for SemaphoreError {}
+
+/// A full semaphore becomes [`std::io::ErrorKind::WouldBlock`], the other errors become `Other`.
+/// The error is kept as the source, so the message is the same
+#[cfg(feature = "std")]
+impl From<SemaphoreError> for std::io::Error {
+    fn from(err: SemaphoreError) -> Self {
+        let kind = match err {
+            SemaphoreError::AtMaxCount => std::io::ErrorKind::WouldBlock,
+            _ => std::io::ErrorKind::Other,
+        };
+        std::io::Error::new(kind, err)
+    }
+}
+
+/// Lock `mutex`, ignoring the poison left by a thread that panicked while holding it
+///
+/// The crate's mutexes guard nothing or lists and queues that are changed in single steps that can't panic
+/// halfway, like a push or a removal. A panic can't leave one in an inconsistent state, so poisoning can be ignored
+#[cfg(feature = "std")]
+pub(crate) fn lock_ignoring_poison<T: ?Sized>(
+    mutex: &std::sync::Mutex<T>,
+) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+/// Set by [`shutdown_all`], checked by the wait loop of every semaphore
+#[cfg(any(feature = "std", feature = "wrapper"))]
+static SHUTTING_DOWN: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+
+/// Make every blocking acquire in the process stop waiting, on every semaphore
+///
+/// Waiting acquires give up with [`SemaphoreError::ShuttingDown`] (or `None`, or a panic for the ones
+/// that can't fail) the next time they check, which is after their current wait: a parked thread
+/// notices within [`raw::Semaphore::PARK_TIMEOUT`]. Acquires that don't have to wait still succeed,
+/// as do non-blocking and async acquires. The flag stays set until [`reset_shutdown`] is called
+#[cfg(any(feature = "std", feature = "wrapper"))]
+pub fn shutdown_all() {
+    SHUTTING_DOWN.store(true, core::sync::atomic::Ordering::SeqCst);
+}
+
+/// Undo [`shutdown_all`], letting blocking acquires wait again
+#[cfg(any(feature = "std", feature = "wrapper"))]
+pub fn reset_shutdown() {
+    SHUTTING_DOWN.store(false, core::sync::atomic::Ordering::SeqCst);
+}
+
+/// Returns true between [`shutdown_all`] and [`reset_shutdown`]
+#[cfg(any(feature = "std", feature = "wrapper"))]
+#[must_use]
+pub fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(core::sync::atomic::Ordering::SeqCst)
+}
+
+/// Returned when acquiring from a poisoned semaphore
+///
+/// Like [`std::sync::PoisonError`], this still holds the guard so the data can be recovered
+#[cfg(feature = "std")]
+pub struct PoisonError<G> {
+    guard: G,
+}
+
+#[cfg(feature = "std")]
+impl<G> PoisonError<G> {
+    /// Wrap a guard acquired from a poisoned semaphore
+    pub fn new(guard: G) -> Self {
+        PoisonError { guard }
+    }
+
+    /// Consume the error and return the guard anyway
+    pub fn into_inner(self) -> G {
+        self.guard
+    }
+
+    /// Get a reference to the guard
+    pub fn get_ref(&self) -> &G {
+        &self.guard
+    }
+}
+
+#[cfg(feature = "std")]
+impl<G> core::fmt::Debug for PoisonError<G> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PoisonError").finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<G> core::fmt::Display for PoisonError<G> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        SemaphoreError::Poisoned.fmt(f)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<G> std::error::Error for PoisonError<G> {}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "std", feature = "wrapper"))]
+    #[test]
+    fn test_errors_convert_into_anyhow() {
+        fn acquire(semaphore: &crate::Semaphore<()>) -> anyhow::Result<()> {
+            let _guard = semaphore.try_get()?;
+            Ok(())
+        }
+
+        let semaphore = crate::Semaphore::new((), 1);
+        assert!(acquire(&semaphore).is_ok());
+
+        let _guard = semaphore.try_get().unwrap();
+        let err = acquire(&semaphore).unwrap_err();
+        assert_eq!(err.to_string(), "Already at maximum count!");
+        assert_eq!(err.chain().count(), 1);
+        assert!(err.downcast_ref::<crate::SemaphoreError>().is_some());
+    }
+
+    #[cfg(all(feature = "std", feature = "wrapper"))]
+    #[test]
+    fn test_errors_convert_into_io_errors() {
+        fn acquire(semaphore: &crate::Semaphore<()>) -> std::io::Result<()> {
+            let _guard = semaphore.try_get()?;
+            Ok(())
+        }
+
+        let semaphore = crate::Semaphore::new((), 1);
+        let _guard = semaphore.try_get().unwrap();
+        let err = acquire(&semaphore).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+        assert_eq!(err.to_string(), "Already at maximum count!");
+
+        let err = std::io::Error::from(crate::SemaphoreError::Poisoned);
+        assert_eq!(err.kind(), std::io::ErrorKind::Other);
+        assert_eq!(err.to_string(), "Semaphore is poisoned!");
+    }
+}
@@ -0,0 +1,215 @@
+//! A bounded FIFO queue with semaphore backpressure, see [`BoundedQueue`]
+
+use crate::{lock_ignoring_poison, raw, SemaphoreError};
+use core::time::Duration;
+use std::{collections::VecDeque, sync::Mutex, time::Instant};
+
+/// A FIFO queue that holds at most `max` items, using a [`raw::Semaphore`] for backpressure
+///
+/// Pushing takes a permit before the item is enqueued and popping releases it after the item is dequeued,
+/// so producers block (or fail) while the queue is full instead of growing it without bound
+pub struct BoundedQueue<T> {
+    capacity: raw::Semaphore,
+    items: Mutex<VecDeque<T>>,
+}
+
+impl<T> BoundedQueue<T> {
+    /// Create an empty queue that holds at most `max` items
+    #[must_use]
+    pub fn new(max: usize) -> Self {
+        BoundedQueue {
+            capacity: raw::Semaphore::new(max),
+            // Grows as items are pushed, `max` can be far more than will ever be queued at once
+            items: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Add `item` to the back of the queue, blocking while it is full
+    /// # Panics
+    /// This function will panic if `max` == 0 because that will cause an infinite loop,
+    /// or if it can't wait with `no_block` or after [`shutdown_all`][`crate::shutdown_all`]
+    pub fn push(&self, mut item: T) {
+        assert_ne!(
+            self.capacity.max(),
+            0,
+            "Calling 'BoundedQueue::push' on a queue with a max of 0 will loop forever!"
+        );
+        loop {
+            match self.try_push(item) {
+                Ok(()) => return,
+                Err(err) => {
+                    assert!(
+                        self.capacity.wait_while(|| true),
+                        "BoundedQueue::push failed: {}",
+                        self.capacity.gave_up_error()
+                    );
+                    item = err.into_inner();
+                }
+            }
+        }
+    }
+
+    /// Add `item` to the back of the queue if it isn't full
+    ///
+    /// This function will never block on the capacity, but does briefly lock the queue
+    /// # Errors
+    /// This function will return the item with [`SemaphoreError::AtMaxCount`] if the queue is full
+    pub fn try_push(&self, item: T) -> Result<(), PushError<T>> {
+        match self.capacity.try_get() {
+            Ok(permit) => {
+                lock_ignoring_poison(&self.items).push_back(item);
+                // Handed back by `pop` once the item is dequeued
+                permit.detach();
+                Ok(())
+            }
+            Err(error) => Err(PushError { item, error }),
+        }
+    }
+
+    /// Like [`BoundedQueue::push`], but gives up if the queue is still full after `timeout`
+    /// # Errors
+    /// This function will return the item with [`SemaphoreError::AtMaxCount`] if it timed out
+    pub fn push_timeout(&self, mut item: T, timeout: Duration) -> Result<(), PushError<T>> {
+        // A timeout too large for an `Instant` waits without a deadline
+        let deadline = Instant::now().checked_add(timeout);
+        loop {
+            match self.try_push(item) {
+                Ok(()) => return Ok(()),
+                Err(err) => {
+                    if !self
+                        .capacity
+                        .wait_while(|| deadline.is_none_or(|deadline| Instant::now() < deadline))
+                    {
+                        return Err(err);
+                    }
+                    item = err.into_inner();
+                }
+            }
+        }
+    }
+
+    /// Remove the item at the front of the queue, freeing its place for a producer
+    pub fn pop(&self) -> Option<T> {
+        let item = lock_ignoring_poison(&self.items).pop_front()?;
+        self.capacity.release();
+        Some(item)
+    }
+
+    /// Get the number of items in the queue
+    #[must_use]
+    pub fn len(&self) -> usize {
+        lock_ignoring_poison(&self.items).len()
+    }
+
+    /// Returns true if the queue holds no items
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        lock_ignoring_poison(&self.items).is_empty()
+    }
+
+    /// Get the maximum number of items the queue can hold
+    #[must_use]
+    pub fn max(&self) -> usize {
+        self.capacity.max()
+    }
+}
+
+/// Returned when an item couldn't be pushed, holding the item so it isn't lost
+pub struct PushError<T> {
+    item: T,
+    error: SemaphoreError,
+}
+
+impl<T> PushError<T> {
+    /// Consume the error and return the item that wasn't pushed
+    pub fn into_inner(self) -> T {
+        self.item
+    }
+
+    /// Get why the item wasn't pushed
+    pub fn error(&self) -> &SemaphoreError {
+        &self.error
+    }
+}
+
+impl<T> core::fmt::Debug for PushError<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PushError")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> core::fmt::Display for PushError<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<T> std::error::Error for PushError<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(feature = "no_block"))]
+    #[test]
+    fn test_depth_never_exceeds_max() {
+        use core::sync::atomic::{AtomicBool, Ordering};
+
+        let queue = BoundedQueue::new(4);
+        let done = AtomicBool::new(false);
+
+        let received = std::thread::scope(|s| {
+            let producers: Vec<_> = (0..4)
+                .map(|producer| {
+                    let queue = &queue;
+                    s.spawn(move || (0..100).for_each(|i| queue.push(producer * 100 + i)))
+                })
+                .collect();
+            let consumer = s.spawn(|| {
+                let mut received = Vec::new();
+                while !done.load(Ordering::SeqCst) || !queue.is_empty() {
+                    assert!(queue.len() <= queue.max());
+                    received.extend(queue.pop());
+                }
+                received
+            });
+
+            for producer in producers {
+                producer.join().unwrap();
+            }
+            done.store(true, Ordering::SeqCst);
+            consumer.join().unwrap()
+        });
+
+        assert_eq!(received.len(), 400);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_full_queue_returns_item() {
+        let queue = BoundedQueue::new(1);
+        queue.push("first");
+
+        let err = queue.try_push("second").unwrap_err();
+        assert_eq!(*err.error(), SemaphoreError::AtMaxCount);
+        assert_eq!(err.into_inner(), "second");
+        let err = queue
+            .push_timeout("third", Duration::from_millis(10))
+            .unwrap_err();
+        assert_eq!(err.into_inner(), "third");
+
+        assert_eq!(queue.pop(), Some("first"));
+        assert!(queue.try_push("second").is_ok());
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn test_huge_bounds_allocate_lazily() {
+        let queue = BoundedQueue::new(usize::MAX);
+        assert!(queue.push_timeout(1, Duration::MAX).is_ok());
+        assert_eq!(queue.pop(), Some(1));
+        assert!(queue.is_empty());
+    }
+}
@@ -1,112 +1,3321 @@
 use core::{
-    marker::PhantomData,
     sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
 };
 
+use core::sync::atomic::AtomicBool;
+#[cfg(any(feature = "async", feature = "embassy"))]
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+#[cfg(any(feature = "async", feature = "embassy"))]
+use crate::wait::{WaitEntry, WakerQueue};
+
+/// How a blocking acquire waits while the semaphore is at its max
+///
+/// The strategy can be changed at any time with [`Semaphore::set_wait_strategy`],
+/// waiters pick up the new strategy on their next iteration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WaitStrategy {
+    /// Busy-wait with [`core::hint::spin_loop`], doubling the spins between checks up to 64
+    Spin,
+    /// Give up the rest of the time slice with [`std::thread::yield_now`], spins on `no_std`
+    Yield,
+    /// Sleep between checks with [`std::thread::sleep`], spins on `no_std`
+    ///
+    /// The duration is stored with microsecond precision
+    Sleep(Duration),
+    /// Sleep like `Sleep`, starting at `start` and doubling after every check up to `max`
+    ///
+    /// Each blocking wait starts over at `start`, so the delay resets once a permit was seen.
+    /// The durations are stored with microsecond precision
+    Exponential {
+        /// The first sleep of every wait
+        start: Duration,
+        /// The longest a single sleep can get
+        max: Duration,
+    },
+    /// Spin and then yield with [`crossbeam_utils::Backoff::snooze`]
+    #[cfg(feature = "crossbeam")]
+    Snooze,
+    /// Park the thread with [`std::thread::park_timeout`] until a guard is dropped
+    ///
+    /// Dropping a guard unparks the thread that has waited the longest, so there is no latency floor like with `Sleep`.
+    /// The park times out after [`Semaphore::PARK_TIMEOUT`] in case a wakeup went to a thread that gave up
+    #[cfg(feature = "std")]
+    Park,
+}
+
+impl Default for WaitStrategy {
+    /// `Snooze` with the `crossbeam` feature, otherwise `Park` on `std` and `Spin` on `no_std`
+    fn default() -> Self {
+        WaitStrategy::DEFAULT
+    }
+}
+
+impl WaitStrategy {
+    const SPIN: usize = 0;
+    const YIELD: usize = 1;
+    const SNOOZE: usize = 2;
+    const PARK: usize = 3;
+    const SLEEP: usize = 4;
+
+    #[cfg(feature = "crossbeam")]
+    const DEFAULT: Self = WaitStrategy::Snooze;
+    #[cfg(all(not(feature = "crossbeam"), feature = "std"))]
+    const DEFAULT: Self = WaitStrategy::Park;
+    #[cfg(all(not(feature = "crossbeam"), not(feature = "std")))]
+    const DEFAULT: Self = WaitStrategy::Spin;
+
+    /// Pack the strategy into a `usize`, sleeps are stored as `SLEEP + 2 * microseconds`
+    ///
+    /// The low bit is set for `Exponential`, whose cap is stored apart, see [`WaitStrategy::encode_cap`]
+    const fn encode(self) -> usize {
+        const fn micros(duration: Duration) -> usize {
+            let micros = duration.as_micros();
+            let most = (usize::MAX - WaitStrategy::SLEEP) / 2;
+            if micros > most as u128 {
+                most
+            } else {
+                micros as usize
+            }
+        }
+        match self {
+            WaitStrategy::Spin => Self::SPIN,
+            WaitStrategy::Yield => Self::YIELD,
+            #[cfg(feature = "crossbeam")]
+            WaitStrategy::Snooze => Self::SNOOZE,
+            #[cfg(feature = "std")]
+            WaitStrategy::Park => Self::PARK,
+            WaitStrategy::Sleep(duration) => Self::SLEEP + micros(duration) * 2,
+            WaitStrategy::Exponential { start, .. } => Self::SLEEP + micros(start) * 2 + 1,
+        }
+    }
+
+    /// The cap of an `Exponential` strategy in microseconds, 0 for the others
+    fn encode_cap(self) -> usize {
+        match self {
+            WaitStrategy::Exponential { max, .. } => {
+                usize::try_from(max.as_micros()).unwrap_or(usize::MAX)
+            }
+            _ => 0,
+        }
+    }
+
+    fn decode(encoded: usize, cap: usize) -> Self {
+        match encoded {
+            Self::SPIN => WaitStrategy::Spin,
+            Self::YIELD => WaitStrategy::Yield,
+            #[cfg(feature = "crossbeam")]
+            Self::SNOOZE => WaitStrategy::Snooze,
+            #[cfg(not(feature = "crossbeam"))]
+            Self::SNOOZE => WaitStrategy::Spin,
+            #[cfg(feature = "std")]
+            Self::PARK => WaitStrategy::Park,
+            #[cfg(not(feature = "std"))]
+            Self::PARK => WaitStrategy::Spin,
+            sleep => {
+                let micros = Duration::from_micros(((sleep - Self::SLEEP) / 2) as u64);
+                if sleep & 1 == Self::SLEEP & 1 {
+                    WaitStrategy::Sleep(micros)
+                } else {
+                    WaitStrategy::Exponential {
+                        start: micros,
+                        max: Duration::from_micros(cap as u64),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Which waiter gets a released permit first
+///
+/// Applies to threads parked with [`WaitStrategy::Park`] and to tasks waiting in [`Semaphore::acquire`] with `async`.
+/// embassy only keeps a single waker, so the order has no effect there
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WakeOrder {
+    /// The waiter that has waited the longest goes first, so every waiter eventually gets a permit
+    #[default]
+    Fifo,
+    /// The waiter that started waiting most recently goes first, keeping recently active threads and tasks warm
+    ///
+    /// Under constant contention the oldest waiters can starve, since newer waiters keep overtaking them
+    Lifo,
+}
+
+/// A platform's way to block a thread until it is woken, for semaphores created with [`Semaphore::new_with_parker`]
+///
+/// Meant for `no_std` targets where the only built-in wait is spinning, e.g. `wfe`/`sev` on ARM.
+/// An `unpark` that happens before `park` must make that `park` return right away,
+/// otherwise a release between the waiter's check and its `park` is missed
+pub trait Parker: Sync {
+    /// Block the current thread until [`Parker::unpark`] is called, it may also return early
+    fn park(&self);
+
+    /// Wake the threads blocked in [`Parker::park`], called every time permits are released
+    fn unpark(&self);
+}
+
 #[cfg(not(feature = "nightly"))]
 #[doc(hidden)]
 type PhantomUnsend = core::marker::PhantomData<*mut ()>; // Pointers are never send
 
+/// The metrics a semaphore created with [`Semaphore::new_with_metrics`] reports to
+#[cfg(feature = "metrics")]
+struct Metrics {
+    count: metrics::Gauge,
+    available: metrics::Gauge,
+    acquires: metrics::Counter,
+}
+
+/// Where every live guard of a [`Semaphore`] was acquired, with the `backtrace` feature
+///
+/// Reports every backtrace still listed when it is dropped, those are permits that were leaked
+#[cfg(feature = "backtrace")]
+struct Backtraces(std::sync::Mutex<Vec<std::sync::Arc<std::backtrace::Backtrace>>>);
+
+#[cfg(feature = "backtrace")]
+impl Backtraces {
+    const fn new() -> Self {
+        Backtraces(std::sync::Mutex::new(Vec::new()))
+    }
+
+    /// Stop listing `backtrace`, after the guard that captured it released its permits
+    fn remove(&self, backtrace: &std::sync::Arc<std::backtrace::Backtrace>) {
+        let mut held = crate::lock_ignoring_poison(&self.0);
+        if let Some(index) = held
+            .iter()
+            .position(|listed| std::sync::Arc::ptr_eq(listed, backtrace))
+        {
+            held.swap_remove(index);
+        }
+    }
+}
+
+#[cfg(feature = "backtrace")]
+impl Drop for Backtraces {
+    fn drop(&mut self) {
+        let held = self
+            .0
+            .get_mut()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        for backtrace in held.iter() {
+            std::eprintln!(
+                "semaphorus: a semaphore was dropped while a permit acquired here was never released:\n{backtrace}"
+            );
+        }
+    }
+}
+
+/// The count of a [`Semaphore`], given a cache line of its own with `split_cache_lines`
+///
+/// Every acquire and release writes the count, so keeping it apart from `max` and the settings
+/// stops their readers from invalidating it. 128 bytes covers CPUs that prefetch lines in pairs
+///
+/// The acquires, the releases, and the checks a waiter makes before it sleeps all use `SeqCst`,
+/// whatever ordering a caller passes to [`Semaphore::count`]. `Acquire` and `Release` would be enough
+/// to order the data behind a permit, but a waiter registers in a queue and then checks the count,
+/// while a release changes the count and then skips the queue if it looks empty. Only `SeqCst`
+/// on both sides stops each one missing the other's write
+#[cfg_attr(feature = "split_cache_lines", repr(align(128)))]
+pub(crate) struct Count(AtomicUsize);
+
+impl Count {
+    pub(crate) const fn new(count: usize) -> Self {
+        Count(AtomicUsize::new(count))
+    }
+}
+
+impl core::ops::Deref for Count {
+    type Target = AtomicUsize;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Panic in debug builds if `ordering` can't be used to load the count, naming the method it was passed to
+///
+/// The load itself panics too, but with a message that doesn't say which call was wrong
+#[inline]
+#[track_caller]
+fn debug_assert_load(ordering: Ordering, method: &str) {
+    debug_assert!(
+        matches!(
+            ordering,
+            Ordering::Relaxed | Ordering::Acquire | Ordering::SeqCst
+        ),
+        "'Semaphore::{method}' was given Ordering::{ordering:?}, but it loads the count so only Relaxed, Acquire or SeqCst are valid"
+    );
+}
+
+/// The count and max of a [`Semaphore`] read together, see [`Semaphore::snapshot`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SemaphoreSnapshot {
+    /// The number of permits held
+    pub count: usize,
+    /// The maximum number of permits
+    pub max: usize,
+}
+
+impl SemaphoreSnapshot {
+    /// Get how many more permits could have been taken, 0 if `max` was lowered below `count`
+    #[must_use]
+    pub fn available(&self) -> usize {
+        self.max.saturating_sub(self.count)
+    }
+
+    /// Returns true if no permit could have been taken
+    #[must_use]
+    pub fn is_full(&self) -> bool {
+        self.count >= self.max
+    }
+}
+
+/// Rank an ordering by what its loads guarantee, for comparing the two orderings of a compare-and-swap
+#[inline]
+fn strength(ordering: Ordering) -> u8 {
+    match ordering {
+        Ordering::SeqCst => 2,
+        Ordering::Acquire | Ordering::AcqRel => 1,
+        _ => 0,
+    }
+}
+
 /// A counter that has a maximum value
 pub struct Semaphore {
-    count: AtomicUsize,
-    pub max: usize,
+    pub(crate) count: Count,
+    /// Set by [`Semaphore::close`]
+    closed: AtomicBool,
+    /// The highest count seen right after an acquire, see [`Semaphore::peak_count`]
+    peak: AtomicUsize,
+    /// The number of successful acquires, see [`Semaphore::total_acquired`]
+    acquires: AtomicUsize,
+    /// How many more acquires are allowed, `Some` if the semaphore was created with [`Semaphore::new_with_total_cap`]
+    remaining_total: Option<AtomicUsize>,
+    /// Changed at runtime with [`Semaphore::set_max`] and [`Semaphore::add_permits`]
+    max: AtomicUsize,
+    /// `Some` if the semaphore was created with [`Semaphore::new_poisoning`]
+    #[cfg(feature = "std")]
+    poison: Option<AtomicBool>,
+    /// The current [`WaitStrategy`], packed with [`WaitStrategy::encode`]
+    wait_strategy: AtomicUsize,
+    /// The cap of an exponential [`WaitStrategy`], packed with [`WaitStrategy::encode_cap`]
+    wait_cap: AtomicUsize,
+    /// Set if the current [`WakeOrder`] is [`WakeOrder::Lifo`]
+    lifo: AtomicBool,
+    /// `Some` if the semaphore was created with [`Semaphore::new_with_parker`]
+    parker: Option<&'static dyn Parker>,
+    /// How long a blocking acquire can wait before it logs a warning, in microseconds
+    #[cfg(feature = "log")]
+    slow_wait_threshold: AtomicUsize,
+    /// The labels of the guards handed out by [`Semaphore::try_get_labeled`]
+    #[cfg(feature = "debug_holders")]
+    holders: std::sync::Mutex<Vec<&'static str>>,
+    /// Where the live guards were acquired
+    #[cfg(feature = "backtrace")]
+    backtraces: Backtraces,
+    /// A bitmap of the slots in use, `Some` if the semaphore was created with [`Semaphore::new_indexed`]
+    slots: Option<AtomicUsize>,
+    /// Tasks waiting for a permit to be released
+    #[cfg(any(feature = "async", feature = "embassy"))]
+    wakers: WakerQueue,
+    /// Threads blocked with [`WaitStrategy::Park`]
+    #[cfg(feature = "std")]
+    parked: crate::wait::ParkedThreads,
+    /// Callbacks registered with [`Semaphore::on_available`]
+    #[cfg(feature = "std")]
+    callbacks: crate::wait::Callbacks,
+    /// Set with [`Semaphore::on_change`]
+    #[cfg(feature = "std")]
+    on_change: Option<Box<dyn Fn(usize) + Send + Sync>>,
+    /// `Some` if the semaphore was created with [`Semaphore::new_with_metrics`]
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+    /// Held by the thread reserving permits for a blocking multi-permit acquire
+    #[cfg(all(feature = "std", feature = "wrapper"))]
+    reservation: std::sync::Mutex<()>,
+    /// Set while that thread reserves, so other acquires leave the freed permits alone
+    #[cfg(all(feature = "std", feature = "wrapper"))]
+    reserving: AtomicBool,
+    /// `Some` if the semaphore was created with [`Semaphore::new_fair`]
+    #[cfg(feature = "std")]
+    fair: Option<crate::wait::FairQueue>,
+}
+
+/// A panic can't leave the semaphore half updated: the count only changes through single atomic operations,
+/// and the internal locks ignore poisoning, so a semaphore is still correct after `catch_unwind`.
+/// The parker, callbacks and metrics handles it stores are only called, nothing they can see is torn.
+/// Noticing panics in guard holders is opt-in with [`Semaphore::new_poisoning`]
+impl core::panic::UnwindSafe for Semaphore {}
+
+impl core::panic::RefUnwindSafe for Semaphore {}
+
+/// Shows a relaxed snapshot of the count next to the max
+impl core::fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Semaphore")
+            .field("count", &self.count(Ordering::Relaxed))
+            .field("max", &self.max())
+            .finish()
+    }
 }
 
 /// A guard for a Semaphore
 /// Increments the count on creation
 /// Decrements it on Drop
+///
+/// Dropping a guard only wakes the waiters of its own semaphore, so guards from several
+/// semaphores can be nested and dropped in any order
 #[must_use]
 pub struct SemaphoreGuard<'guard> {
     semaphore: &'guard Semaphore,
+    /// How many permits the guard holds, only more than 1 for [`Semaphore::try_get_many`]
+    permits: usize,
+    /// The slot handed out by [`Semaphore::try_get_indexed`]
+    slot: Option<usize>,
+    /// The label given to [`Semaphore::try_get_labeled`]
+    #[cfg(feature = "debug_holders")]
+    label: Option<&'static str>,
+    /// Where the guard was acquired, listed by [`Semaphore::holders_with_backtraces`]
+    #[cfg(feature = "backtrace")]
+    backtrace: std::sync::Arc<std::backtrace::Backtrace>,
+    /// The span given to [`Semaphore::try_get_instrumented`], exited after the permit is released
+    #[cfg(feature = "tracing")]
+    span: Option<tracing::span::EnteredSpan>,
+    /// Taken from [`NEXT_GUARD_ID`] on acquisition
+    #[cfg(feature = "guard_ids")]
+    id: u64,
     #[cfg(not(feature = "nightly"))]
     _unsend: PhantomUnsend,
 }
 
-impl<'guard> Drop for SemaphoreGuard<'guard> {
+/// The id handed to the next guard, shared by every semaphore so ids are unique within the process
+#[cfg(feature = "guard_ids")]
+static NEXT_GUARD_ID: core::sync::atomic::AtomicU64 = core::sync::atomic::AtomicU64::new(0);
+
+impl core::fmt::Debug for SemaphoreGuard<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SemaphoreGuard")
+            .field("permits", &self.permits)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for SemaphoreGuard<'_> {
+    #[inline]
     fn drop(&mut self) {
-        self.semaphore.count.fetch_sub(1, Ordering::SeqCst);
+        if let (Some(slot), Some(slots)) = (self.slot, &self.semaphore.slots) {
+            slots.fetch_and(!(1 << slot), Ordering::SeqCst);
+        }
+        #[cfg(feature = "debug_holders")]
+        if let Some(label) = self.label {
+            let mut holders = crate::lock_ignoring_poison(&self.semaphore.holders);
+            if let Some(index) = holders.iter().position(|held| *held == label) {
+                holders.swap_remove(index);
+            }
+        }
+        #[cfg(feature = "backtrace")]
+        self.semaphore.backtraces.remove(&self.backtrace);
+        self.semaphore.release_many(self.permits);
     }
 }
 
 impl<'guard> SemaphoreGuard<'guard> {
-    fn new(semaphore: &'guard Semaphore) -> Self {
-        semaphore.count.fetch_add(1, Ordering::SeqCst);
+    /// Create a guard for a count that has already been incremented
+    #[inline]
+    fn acquired(semaphore: &'guard Semaphore) -> Self {
+        #[cfg(feature = "backtrace")]
+        let backtrace = {
+            let backtrace = std::sync::Arc::new(std::backtrace::Backtrace::force_capture());
+            crate::lock_ignoring_poison(&semaphore.backtraces.0)
+                .push(std::sync::Arc::clone(&backtrace));
+            backtrace
+        };
         SemaphoreGuard {
             semaphore,
+            permits: 1,
+            slot: None,
+            #[cfg(feature = "debug_holders")]
+            label: None,
+            #[cfg(feature = "backtrace")]
+            backtrace,
+            #[cfg(feature = "tracing")]
+            span: None,
+            #[cfg(feature = "guard_ids")]
+            id: NEXT_GUARD_ID.fetch_add(1, Ordering::Relaxed),
             #[cfg(not(feature = "nightly"))]
-            _unsend: PhantomData,
+            _unsend: core::marker::PhantomData,
+        }
+    }
+
+    /// Create a guard for `permits` that have already been added to the count
+    fn acquired_many(semaphore: &'guard Semaphore, permits: usize) -> Self {
+        let mut guard = SemaphoreGuard::acquired(semaphore);
+        guard.permits = permits;
+        guard
+    }
+
+    /// Get how many permits this guard holds
+    #[must_use]
+    pub fn permits(&self) -> usize {
+        self.permits
+    }
+
+    /// Get the semaphore this guard was taken from
+    #[must_use]
+    pub fn semaphore(&self) -> &'guard Semaphore {
+        self.semaphore
+    }
+
+    /// Hand one of the guard's permits back early, keeping the rest until it is dropped
+    ///
+    /// Wakes a waiter like dropping a single-permit guard would. A guard that holds no permits is left as is
+    pub fn release_one(&mut self) {
+        self.release_many(1);
+    }
+
+    /// Hand `permits` of the guard's permits back early, keeping the rest until it is dropped
+    ///
+    /// Asking for more than the guard holds releases all of them, which leaves a guard that holds none.
+    /// Wakes a waiter for every released permit like dropping the guard would
+    pub fn release_many(&mut self, permits: usize) {
+        let permits = permits.min(self.permits);
+        if permits > 0 {
+            self.permits -= permits;
+            self.semaphore.release_many(permits);
         }
     }
+
+    /// Try to take every other permit of the semaphore, so this guard holds all `max` of them
+    ///
+    /// Only succeeds if nobody else holds a permit, the count goes from this guard's permits to `max`
+    /// in one compare-and-swap. Never blocks, so two guards promoting at once both fail instead of
+    /// waiting on each other forever. Like any acquire it uses up one of the total acquires, if the semaphore has a cap
+    /// # Errors
+    /// Returns the guard unchanged if any other permit is held, or where [`Semaphore::try_get`] would fail:
+    /// the semaphore is poisoned, closed or exhausted, or fair waiters or a blocking multi-permit acquire are queued
+    pub fn try_promote(mut self) -> Result<Self, Self> {
+        let semaphore = self.semaphore;
+        #[cfg(feature = "std")]
+        if semaphore.is_poisoned() {
+            return Err(self);
+        }
+        if semaphore.is_closed() || semaphore.is_reserving() {
+            return Err(self);
+        }
+        let max = semaphore.max();
+        if semaphore
+            .count
+            .compare_exchange(self.permits, max, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return Err(self);
+        }
+        let extra = max - self.permits;
+        if let Some(remaining) = &semaphore.remaining_total {
+            if remaining
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |remaining| {
+                    remaining.checked_sub(1)
+                })
+                .is_err()
+            {
+                semaphore.release_many(extra);
+                return Err(self);
+            }
+        }
+        self.permits = max;
+        semaphore.peak.fetch_max(max, Ordering::Relaxed);
+        Ok(self)
+    }
+
+    /// Release all but one of the permits this guard holds, waking waiters for them
+    ///
+    /// The extra permits are released in one subtraction and the guard keeps holding its last one,
+    /// so another guard can never take every permit in between. Readers can join right after
+    pub fn downgrade(mut self) -> Self {
+        let extra = self.permits.saturating_sub(1);
+        if extra > 0 {
+            self.permits -= extra;
+            self.semaphore.release_many(extra);
+        }
+        self
+    }
+
+    /// Get the process-unique id assigned when the permit was acquired
+    ///
+    /// Logging it on acquire and on release ties the two lines together
+    #[cfg(feature = "guard_ids")]
+    #[must_use]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Drop the guard without releasing its permits, permanently lowering how many are available
+    ///
+    /// The permits stay counted as held until [`Semaphore::add_permits`] makes up for them, useful for
+    /// taking a broken resource out of a pool. Unlike [`core::mem::forget`] this isn't reported as a leak
+    pub fn forget(self) {
+        // The permit is still held, but no longer by a guard that knows where it came from
+        #[cfg(feature = "backtrace")]
+        self.semaphore.backtraces.remove(&self.backtrace);
+        core::mem::forget(self);
+    }
+
+    /// Consume the guard without decrementing the count
+    ///
+    /// The permit must be handed back later with [`Semaphore::release`]
+    #[cfg(any(feature = "std", feature = "embassy"))]
+    pub(crate) fn detach(self) {
+        self.forget();
+    }
+}
+
+/// A permit from [`Semaphore::try_get_manual`] that is not released when dropped
+///
+/// Dropping it leaks the permit, it must be passed to [`Semaphore::release_manual`] instead.
+/// Debug builds with `std` print a warning when one is dropped
+#[cfg(feature = "manual_release")]
+#[must_use = "the permit is only released by passing the guard to 'Semaphore::release_manual'"]
+pub struct ManualGuard<'guard> {
+    semaphore: &'guard Semaphore,
 }
 
-#[cfg(any(feature = "nightly", doc))]
-impl<'guard> !Send for SemaphoreGuard<'guard> {}
+#[cfg(all(feature = "manual_release", debug_assertions))]
+impl Drop for ManualGuard<'_> {
+    fn drop(&mut self) {
+        #[cfg(feature = "std")]
+        if !std::thread::panicking() {
+            std::eprintln!(
+                "semaphorus: a ManualGuard was dropped without 'Semaphore::release_manual', its permit is leaked"
+            );
+        }
+    }
+}
+#[cfg(any(feature = "nightly", docsrs))]
+impl !Send for SemaphoreGuard<'_> {}
 
-unsafe impl<'guard> Sync for SemaphoreGuard<'guard> {}
+unsafe impl Sync for SemaphoreGuard<'_> {}
 
 impl Semaphore {
+    /// How many rounds of spins a wait doubles through, the last round spins `2^SPIN_LIMIT` times
+    #[cfg(any(feature = "std", feature = "wrapper"))]
+    const SPIN_LIMIT: u32 = 6;
+
+    /// The longest a thread waits with [`WaitStrategy::Park`] before checking the count again without being unparked
+    #[cfg(feature = "std")]
+    pub const PARK_TIMEOUT: Duration = Duration::from_millis(50);
+
+    /// # Panics
+    /// Debug builds panic if `ordering` is `Release` or `AcqRel`, which aren't valid for a load
     #[must_use]
+    #[inline]
+    #[track_caller]
     pub fn count(&self, ordering: Ordering) -> usize {
+        debug_assert_load(ordering, "count");
         self.count.load(ordering)
     }
 
     #[must_use]
-    pub fn new(max: usize) -> Self {
+    pub const fn new(max: usize) -> Self {
+        Semaphore {
+            max: AtomicUsize::new(max),
+            count: Count::new(0),
+            closed: AtomicBool::new(false),
+            peak: AtomicUsize::new(0),
+            acquires: AtomicUsize::new(0),
+            remaining_total: None,
+            #[cfg(feature = "std")]
+            poison: None,
+            slots: None,
+            wait_strategy: AtomicUsize::new(WaitStrategy::DEFAULT.encode()),
+            wait_cap: AtomicUsize::new(0),
+            lifo: AtomicBool::new(false),
+            parker: None,
+            #[cfg(feature = "log")]
+            slow_wait_threshold: AtomicUsize::new(1_000_000),
+            #[cfg(feature = "debug_holders")]
+            holders: std::sync::Mutex::new(Vec::new()),
+            #[cfg(feature = "backtrace")]
+            backtraces: Backtraces::new(),
+            #[cfg(any(feature = "async", feature = "embassy"))]
+            wakers: WakerQueue::new(),
+            #[cfg(feature = "std")]
+            parked: crate::wait::ParkedThreads::new(),
+            #[cfg(feature = "std")]
+            callbacks: crate::wait::Callbacks::new(),
+            #[cfg(feature = "std")]
+            on_change: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            #[cfg(all(feature = "std", feature = "wrapper"))]
+            reservation: std::sync::Mutex::new(()),
+            #[cfg(all(feature = "std", feature = "wrapper"))]
+            reserving: AtomicBool::new(false),
+            #[cfg(feature = "std")]
+            fair: None,
+        }
+    }
+
+    /// Create a semaphore that hands permits to blocked threads in the order they started waiting
+    ///
+    /// While threads are queued, every other acquire treats the semaphore as full, so a newcomer can't
+    /// take a freed permit ahead of a thread that has waited longer. That rules out starvation, but costs a
+    /// lock and a handoff on every contended acquire, and a permit stays idle until the front thread wakes up.
+    /// Only the blocking `get`, `get_many` and `get_exactly` of [`Semaphore`][`crate::Semaphore`] and the timed
+    /// [`Semaphore::try_get_for`] and [`Semaphore::try_get_until`] join the queue, a timed acquire that gives up
+    /// leaves its place
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn new_fair(max: usize) -> Self {
         Semaphore {
-            max,
-            count: AtomicUsize::new(0),
+            fair: Some(crate::wait::FairQueue::new()),
+            ..Semaphore::new(max)
         }
     }
 
+    /// Create a semaphore whose blocking acquires wait with `parker` instead of the [`WaitStrategy`]
+    ///
+    /// A wait spins a few doubling rounds before it parks, so a permit released right away doesn't
+    /// cost a park. Releasing permits calls [`Parker::unpark`]. Acquires with a timeout keep using the wait strategy,
+    /// since [`Parker::park`] can't be given one
     #[must_use]
-    pub fn at_max(&self, ordering: Ordering) -> bool {
-        self.count.load(ordering) >= self.max
+    pub const fn new_with_parker(max: usize, parker: &'static dyn Parker) -> Self {
+        let mut semaphore = Semaphore::new(max);
+        semaphore.parker = Some(parker);
+        semaphore
     }
 
-    /// Try to increment the count and return a Guard
+    /// Create a semaphore that starts with `initial` permits taken, for moving over a pool that already has resources checked out
     ///
-    /// Never blocks
-    /// # Errors
-    /// Will error if the count is at max already
+    /// No guard holds the initial permits, hand them back with [`Semaphore::release_initial`] as those resources come back
+    /// # Panics
+    /// This function will panic if `initial` > `max`
+    #[must_use]
+    pub const fn with_initial(max: usize, initial: usize) -> Self {
+        assert!(
+            initial <= max,
+            "Calling 'Semaphore::with_initial' with an initial count above the max"
+        );
+        let mut semaphore = Semaphore::new(max);
+        semaphore.count = Count::new(initial);
+        semaphore
+    }
 
-    pub fn try_get(&self) -> Result<SemaphoreGuard, crate::SemaphoreError> {
-        if self.at_max(Ordering::SeqCst) {
-            Err(crate::SemaphoreError::AtMaxCount)
-        } else {
-            Ok(SemaphoreGuard::new(self))
+    /// Hand back `permits` that no guard holds, like those taken by [`Semaphore::with_initial`]
+    /// # Panics
+    /// This function will panic if `permits` is more than the current count
+    pub fn release_initial(&self, permits: usize) {
+        assert!(
+            permits <= self.count(Ordering::SeqCst),
+            "Calling 'Semaphore::release_initial' with more permits than are taken"
+        );
+        if permits > 0 {
+            self.release_many(permits);
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Start the count at `count` permits that no guard holds, used by [`SemaphoreBuilder`][`crate::SemaphoreBuilder`]
+    #[cfg(feature = "wrapper")]
+    pub(crate) fn with_initial_count(mut self, count: usize) -> Self {
+        self.count = Count::new(count);
+        self
+    }
 
-    #[test]
-    fn test_maximum_count_works() {
-        let semaphore = Semaphore::new(4);
+    /// Create a semaphore that allows at most `total` acquires over its whole life, on top of `max` at once
+    ///
+    /// Every successful acquire uses up one of the `total`, a multi-permit acquire too, and releasing
+    /// never gives it back. Once it is used up every acquire fails with
+    /// [`SemaphoreError::Exhausted`][`crate::SemaphoreError::Exhausted`]
+    #[must_use]
+    pub const fn new_with_total_cap(max: usize, total: usize) -> Self {
+        let mut semaphore = Semaphore::new(max);
+        semaphore.remaining_total = Some(AtomicUsize::new(total));
+        semaphore
+    }
 
-        let (g1, g2, g3, g4) = (
-            semaphore.try_get(),
-            semaphore.try_get(),
-            semaphore.try_get(),
-            semaphore.try_get(),
+    /// Get how many more acquires are allowed, `None` if there is no cap on the total
+    #[must_use]
+    pub fn remaining_total(&self) -> Option<usize> {
+        self.remaining_total
+            .as_ref()
+            .map(|remaining| remaining.load(Ordering::SeqCst))
+    }
+
+    /// Create a semaphore that becomes poisoned if a guard is dropped while panicking
+    ///
+    /// Poisoning is opt-in, semaphores created with [`Semaphore::new`] never poison
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn new_poisoning(max: usize) -> Self {
+        Semaphore {
+            poison: Some(AtomicBool::new(false)),
+            ..Semaphore::new(max)
+        }
+    }
+
+    /// Create a semaphore that reports to the `metrics` facade on every acquire and release
+    ///
+    /// Sets the gauges `{prefix}.count` and `{prefix}.available` and increments the counter `{prefix}.acquires`.
+    /// The metrics are registered here, so the recorder has to be installed before the semaphore is created
+    #[cfg(feature = "metrics")]
+    #[must_use]
+    pub fn new_with_metrics(max: usize, prefix: &str) -> Self {
+        Semaphore {
+            metrics: Some(Metrics {
+                count: metrics::gauge!(format!("{prefix}.count")),
+                available: metrics::gauge!(format!("{prefix}.available")),
+                acquires: metrics::counter!(format!("{prefix}.acquires")),
+            }),
+            ..Semaphore::new(max)
+        }
+    }
+
+    /// Create a semaphore whose queues of waiting threads and tasks allocate from `alloc`
+    ///
+    /// Requires the `allocator_api` feature and a nightly compiler. Only the waiter queues use `alloc`,
+    /// the callbacks from [`Semaphore::on_available`] and the `debug_holders` labels still use the global allocator
+    #[cfg(feature = "allocator_api")]
+    #[must_use]
+    pub fn new_in(max: usize, alloc: &'static (dyn core::alloc::Allocator + Sync)) -> Self {
+        Semaphore {
+            #[cfg(feature = "async")]
+            wakers: WakerQueue::new_in(alloc),
+            parked: crate::wait::ParkedThreads::new_in(alloc),
+            ..Semaphore::new(max)
+        }
+    }
+
+    /// Create a semaphore that hands out a unique slot index in `0..max` with each guard
+    ///
+    /// The slots are tracked in a bitmap, so `max` can't be larger than [`usize::BITS`]
+    /// # Panics
+    /// Panics if `max` > [`usize::BITS`]
+    #[must_use]
+    pub fn new_indexed(max: usize) -> Self {
+        assert!(
+            max <= usize::BITS as usize,
+            "An indexed semaphore can have at most {} slots",
+            usize::BITS
         );
 
-        assert_eq!(
-            (g1.is_ok(), g2.is_ok(), g3.is_ok(), g4.is_ok()),
-            (true, true, true, true)
+        Semaphore {
+            slots: Some(AtomicUsize::new(0)),
+            ..Semaphore::new(max)
+        }
+    }
+
+    /// Create a semaphore with a max of [`usize::MAX`], for when it's only used for counting
+    ///
+    /// Acquiring from an unbounded semaphore skips the max check, it only fails with
+    /// [`SemaphoreError::AtMaxCount`][`crate::SemaphoreError::AtMaxCount`] if [`usize::MAX`] guards
+    /// are somehow alive at the same time, instead of letting the count wrap around
+    #[must_use]
+    pub fn unbounded() -> Self {
+        Semaphore::new(usize::MAX)
+    }
+
+    /// Get how many more permits can be taken right now
+    ///
+    /// This is only a snapshot, other threads may take or release permits right after
+    /// # Panics
+    /// Debug builds panic if `ordering` is `Release` or `AcqRel`, which aren't valid for a load
+    #[must_use]
+    #[inline]
+    #[track_caller]
+    pub fn available(&self, ordering: Ordering) -> usize {
+        debug_assert_load(ordering, "available");
+        self.max().saturating_sub(self.count(ordering))
+    }
+
+    /// Set the count back to 0, for reusing the semaphore in the next phase of a job
+    ///
+    /// `&mut self` proves no guard is alive, so this is a plain write. Permits that were leaked
+    /// with [`SemaphoreGuard::forget`] or [`core::mem::forget`] are freed as well, and stop being listed as holders
+    pub fn reset(&mut self) {
+        *self.get_mut_count() = 0;
+        #[cfg(feature = "debug_holders")]
+        self.holders
+            .get_mut()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clear();
+        #[cfg(feature = "backtrace")]
+        self.backtraces
+            .0
+            .get_mut()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clear();
+    }
+
+    /// Get the count for reading or writing it without atomics, see [`Semaphore::reset`]
+    ///
+    /// Setting it above `max` leaves the semaphore full until enough permits are released
+    pub fn get_mut_count(&mut self) -> &mut usize {
+        self.count.0.get_mut()
+    }
+
+    /// Read the count and the max as one view, for monitoring
+    ///
+    /// The count is read again after the max and the reads are retried if it changed, so the two
+    /// were both current at some instant unless the count keeps changing. After a few retries it settles
+    /// for the last reads, so under heavy contention this is best effort like [`Semaphore::available`]
+    #[must_use]
+    pub fn snapshot(&self) -> SemaphoreSnapshot {
+        let mut count = self.count(Ordering::SeqCst);
+        for _ in 0..4 {
+            let max = self.max();
+            let again = self.count(Ordering::SeqCst);
+            if again == count {
+                return SemaphoreSnapshot { count, max };
+            }
+            count = again;
+        }
+        SemaphoreSnapshot {
+            count,
+            max: self.max(),
+        }
+    }
+
+    /// Get the maximum number of permits that can be held at once
+    #[must_use]
+    #[inline]
+    pub fn max(&self) -> usize {
+        self.max.load(Ordering::SeqCst)
+    }
+
+    /// Get the highest number of permits held at once since the semaphore was created or [`Semaphore::reset_metrics`]
+    ///
+    /// The count is sampled right after every acquire with [`Ordering::Relaxed`], so under contention the peak
+    /// can be off by the acquires and releases that raced with the sample
+    #[must_use]
+    pub fn peak_count(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    /// Get how many acquires succeeded since the semaphore was created or [`Semaphore::reset_metrics`]
+    ///
+    /// A multi-permit acquire counts once. The counter wraps around after [`usize::MAX`] acquires
+    #[must_use]
+    pub fn total_acquired(&self) -> usize {
+        self.acquires.load(Ordering::Relaxed)
+    }
+
+    /// Zero [`Semaphore::total_acquired`] and start [`Semaphore::peak_count`] over from the current count
+    pub fn reset_metrics(&self) {
+        self.acquires.store(0, Ordering::Relaxed);
+        self.peak
+            .store(self.count.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+
+    /// Change the maximum number of permits, waking waiters if it grew
+    ///
+    /// Shrinking below the current count doesn't touch the guards that are already held,
+    /// no new permits are handed out until enough of them are dropped.
+    /// Acquires racing with the change may still be checked against the old max
+    /// # Panics
+    /// Panics if the semaphore was created with [`Semaphore::new_indexed`] and `new_max` > [`usize::BITS`]
+    pub fn set_max(&self, new_max: usize) {
+        self.assert_slots_fit(new_max);
+        let old_max = self.max.swap(new_max, Ordering::SeqCst);
+        self.wake_grown(new_max.saturating_sub(old_max));
+    }
+
+    /// Raise the maximum number of permits by `permits`, saturating at [`usize::MAX`], and wake waiters for them
+    /// # Panics
+    /// Panics if the semaphore was created with [`Semaphore::new_indexed`] and the max would pass [`usize::BITS`]
+    pub fn add_permits(&self, permits: usize) {
+        let old_max = self
+            .max
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |max| {
+                let max = max.saturating_add(permits);
+                self.assert_slots_fit(max);
+                Some(max)
+            })
+            .unwrap_or_else(|max| max);
+        self.wake_grown(old_max.saturating_add(permits) - old_max);
+    }
+
+    /// Lower the maximum number of permits by `permits`, stopping at 0
+    ///
+    /// Like shrinking with [`Semaphore::set_max`], guards above the new max stay held
+    /// and no new permits are handed out until enough of them are dropped
+    pub fn remove_permits(&self, permits: usize) {
+        self.max
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |max| {
+                Some(max.saturating_sub(permits))
+            })
+            .unwrap_or_else(|max| max);
+    }
+
+    /// Panic if an indexed semaphore's bitmap can't track `max` slots
+    fn assert_slots_fit(&self, max: usize) {
+        assert!(
+            self.slots.is_none() || max <= usize::BITS as usize,
+            "An indexed semaphore can have at most {} slots",
+            usize::BITS
         );
+    }
 
-        let g5 = semaphore.try_get();
+    /// Wake the waiters that `grown` new permits are enough for
+    #[cfg_attr(
+        not(any(feature = "std", feature = "async", feature = "embassy")),
+        allow(clippy::unused_self)
+    )]
+    #[cfg_attr(not(feature = "std"), allow(unused_variables))]
+    fn wake_grown(&self, grown: usize) {
+        #[cfg(any(feature = "async", feature = "embassy"))]
+        self.wake_available();
+        #[cfg(feature = "std")]
+        self.parked.unpark_many(grown, self.wake_order());
+        #[cfg(feature = "std")]
+        self.callbacks.run(grown);
+    }
 
-        assert!(g5.is_err());
+    /// Returns true if the max is [`usize::MAX`], so acquiring never has to check the count
+    #[must_use]
+    #[inline]
+    pub fn is_unbounded(&self) -> bool {
+        self.max() == usize::MAX
+    }
 
-        drop(g1);
+    /// # Panics
+    /// Debug builds panic if `ordering` is `Release` or `AcqRel`, which aren't valid for a load
+    #[must_use]
+    #[inline]
+    #[track_caller]
+    pub fn at_max(&self, ordering: Ordering) -> bool {
+        debug_assert_load(ordering, "at_max");
+        self.count.load(ordering) >= self.max()
+    }
 
-        let g6 = semaphore.try_get();
+    /// Get the strategy used by blocking acquires while waiting
+    #[must_use]
+    pub fn wait_strategy(&self) -> WaitStrategy {
+        WaitStrategy::decode(
+            self.wait_strategy.load(Ordering::Relaxed),
+            self.wait_cap.load(Ordering::Relaxed),
+        )
+    }
 
-        assert!(g6.is_ok());
+    /// Change the strategy used by blocking acquires while waiting
+    ///
+    /// The strategy is only a hint for how to wait and doesn't protect any data,
+    /// so it is stored with [`Ordering::Relaxed`]. Threads that are already waiting
+    /// switch to the new strategy after their current wait (e.g. sleep) finishes.
+    /// A waiter that reads the strategy while it changes may use the old cap for one sleep
+    pub fn set_wait_strategy(&self, strategy: WaitStrategy) {
+        self.wait_cap
+            .store(strategy.encode_cap(), Ordering::Relaxed);
+        self.wait_strategy
+            .store(strategy.encode(), Ordering::Relaxed);
+    }
+
+    /// Get which waiter is woken first when a permit is released
+    #[must_use]
+    pub fn wake_order(&self) -> WakeOrder {
+        if self.lifo.load(Ordering::Relaxed) {
+            WakeOrder::Lifo
+        } else {
+            WakeOrder::Fifo
+        }
+    }
+
+    /// Change which waiter is woken first when a permit is released, [`WakeOrder::Fifo`] by default
+    ///
+    /// Like the [`WaitStrategy`] the order is only a hint and is stored with [`Ordering::Relaxed`]
+    pub fn set_wake_order(&self, order: WakeOrder) {
+        self.lifo.store(order == WakeOrder::Lifo, Ordering::Relaxed);
+    }
+
+    /// Block the thread until the count is below max, using the current [`WaitStrategy`]
+    ///
+    /// `keep_waiting` is checked before every wait, returns `false` if it gave up before a permit was available
+    #[cfg(feature = "wrapper")]
+    pub(crate) fn wait_while(&self, keep_waiting: impl FnMut() -> bool) -> bool {
+        #[cfg(feature = "std")]
+        return self.wait_while_sleeping(keep_waiting, std::thread::sleep);
+        #[cfg(not(feature = "std"))]
+        return self.wait_while_sleeping(keep_waiting, |_| core::hint::spin_loop());
+    }
+
+    /// Like [`Semaphore::wait_while`], but [`WaitStrategy::Sleep`] calls `sleep` so the clock can be replaced
+    #[cfg(feature = "wrapper")]
+    pub(crate) fn wait_while_sleeping(
+        &self,
+        keep_waiting: impl FnMut() -> bool,
+        sleep: impl FnMut(Duration),
+    ) -> bool {
+        self.wait_while_blocked(
+            || self.at_max(Ordering::SeqCst) || self.is_reserving(),
+            keep_waiting,
+            sleep,
+            || None,
+        )
+    }
+
+    /// Like [`Semaphore::wait_while`], but gives up at `deadline`, waiting forever if it is `None`
+    ///
+    /// A single wait never lasts past the deadline, so spurious wakeups and long sleeps or park
+    /// timeouts can't make the total wait overshoot it by more than a few checks
+    #[cfg(feature = "std")]
+    fn wait_until(&self, deadline: Option<std::time::Instant>) -> bool {
+        self.wait_while_blocked(
+            || self.at_max(Ordering::SeqCst) || self.is_reserving(),
+            || deadline.is_none_or(|deadline| std::time::Instant::now() < deadline),
+            std::thread::sleep,
+            || {
+                deadline
+                    .map(|deadline| deadline.saturating_duration_since(std::time::Instant::now()))
+            },
+        )
+    }
+
+    /// The wait loop behind [`Semaphore::wait_while_sleeping`], waiting for as long as `blocked` returns true
+    ///
+    /// Sleeps and parks are cut short to the time `remaining` returns, if any.
+    /// With `no_block`, once [`shutdown_all`][`crate::shutdown_all`] is called or once the semaphore is closed,
+    /// it returns `false` instead of waiting
+    #[cfg(any(feature = "std", feature = "wrapper"))]
+    fn wait_while_blocked(
+        &self,
+        blocked: impl Fn() -> bool,
+        mut keep_waiting: impl FnMut() -> bool,
+        mut sleep: impl FnMut(Duration),
+        remaining: impl Fn() -> Option<Duration>,
+    ) -> bool {
+        #[cfg(feature = "crossbeam")]
+        let backoff = crossbeam_utils::Backoff::new();
+        #[cfg(feature = "log")]
+        let (started, mut warned) = (std::time::Instant::now(), false);
+        // The last sleep of an exponential strategy, starting over with every wait
+        let mut delay = None::<Duration>;
+        // The round of spins, see `spin_round`
+        let mut round = 0;
+        // Entered once the wait actually has to wait, so it covers how long the thread was blocked
+        #[cfg(feature = "tracing")]
+        let mut span = None;
+
+        while blocked() {
+            #[cfg(feature = "tracing")]
+            span.get_or_insert_with(|| {
+                tracing::trace_span!(
+                    target: "semaphorus",
+                    "wait",
+                    count = self.count(Ordering::Relaxed),
+                    max = self.max()
+                )
+                .entered()
+            });
+            if cfg!(feature = "no_block")
+                || crate::is_shutting_down()
+                || self.is_closed()
+                || !keep_waiting()
+            {
+                return false;
+            }
+            #[cfg(feature = "log")]
+            if !warned && started.elapsed() >= self.slow_wait_threshold() {
+                log::warn!(
+                    "Semaphore::get has waited longer than {:?} for a permit ({}/{} in use)",
+                    self.slow_wait_threshold(),
+                    self.count(Ordering::Relaxed),
+                    self.max()
+                );
+                warned = true;
+            }
+            if let (Some(parker), None) = (self.parker, remaining()) {
+                // Only spinning forever could livelock a single core, so the parker takes over
+                if round < Self::SPIN_LIMIT {
+                    Self::spin_round(&mut round);
+                } else {
+                    parker.park();
+                }
+                continue;
+            }
+            match self.wait_strategy() {
+                WaitStrategy::Spin => Self::spin_round(&mut round),
+                #[cfg(feature = "std")]
+                WaitStrategy::Yield => std::thread::yield_now(),
+                WaitStrategy::Sleep(duration) => {
+                    sleep(remaining().map_or(duration, |remaining| duration.min(remaining)));
+                }
+                WaitStrategy::Exponential { start, max } => {
+                    let duration = delay
+                        .map_or(start, |delay| delay.saturating_mul(2))
+                        .min(max);
+                    delay = Some(duration);
+                    sleep(remaining().map_or(duration, |remaining| duration.min(remaining)));
+                }
+                #[cfg(not(feature = "std"))]
+                WaitStrategy::Yield => Self::spin_round(&mut round),
+                #[cfg(feature = "crossbeam")]
+                WaitStrategy::Snooze => backoff.snooze(),
+                #[cfg(feature = "std")]
+                WaitStrategy::Park => self.parked.park_timeout(
+                    remaining().map_or(Self::PARK_TIMEOUT, |remaining| {
+                        remaining.min(Self::PARK_TIMEOUT)
+                    }),
+                    || !blocked(),
+                ),
+            }
+        }
+        true
+    }
+
+    /// Spin `2^round` times, then move on to the next round, staying at the last one
+    #[cfg(any(feature = "std", feature = "wrapper"))]
+    fn spin_round(round: &mut u32) {
+        for _ in 0..1_u32 << *round {
+            core::hint::spin_loop();
+        }
+        *round = (*round + 1).min(Self::SPIN_LIMIT);
+    }
+
+    /// Wrap `permits` that were just added to the count in a guard, using up one of the total acquires
+    ///
+    /// The guard is created before anything else runs, so if reporting the acquire panics
+    /// the unwind drops the guard and the permits aren't leaked. The same drop hands the permits
+    /// back if there are no acquires left, so an exhausted semaphore never keeps a permit
+    #[inline]
+    fn acquired_guard(&self, permits: usize) -> Result<SemaphoreGuard<'_>, crate::SemaphoreError> {
+        let guard = SemaphoreGuard::acquired_many(self, permits);
+        if let Some(remaining) = &self.remaining_total {
+            remaining
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |remaining| {
+                    remaining.checked_sub(1)
+                })
+                .map_err(|_| crate::SemaphoreError::Exhausted)?;
+        }
+        self.peak
+            .fetch_max(self.count.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.acquires.fetch_add(1, Ordering::Relaxed);
+        #[cfg(test)]
+        interleave::reached(interleave::Point::AfterAcquire);
+        #[cfg(feature = "metrics")]
+        self.report_metrics(1);
+        #[cfg(feature = "std")]
+        self.report_change(self.count.load(Ordering::SeqCst));
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            target: "semaphorus",
+            permits,
+            count = self.count(Ordering::Relaxed),
+            max = self.max(),
+            "acquired"
+        );
+        Ok(guard)
+    }
+
+    /// Update the gauges after the count changed, and add `acquires` to the acquire counter
+    #[cfg(feature = "metrics")]
+    #[allow(clippy::cast_precision_loss)] // Gauges are floats, counts that large don't need to be exact
+    fn report_metrics(&self, acquires: u64) {
+        if let Some(metrics) = &self.metrics {
+            let count = self.count(Ordering::Relaxed);
+            metrics.count.set(count as f64);
+            metrics
+                .available
+                .set(self.max().saturating_sub(count) as f64);
+            metrics.acquires.increment(acquires);
+        }
+    }
+
+    /// Why the wait loop gave up without `keep_waiting` telling it to
+    #[cfg(any(feature = "std", feature = "wrapper"))]
+    pub(crate) fn gave_up_error(&self) -> crate::SemaphoreError {
+        if self.is_closed() {
+            crate::SemaphoreError::Closed
+        } else if crate::is_shutting_down() {
+            crate::SemaphoreError::ShuttingDown
+        } else {
+            crate::SemaphoreError::AtMaxCount
+        }
+    }
+
+    /// Get how long a blocking acquire can wait before it logs a warning
+    #[cfg(feature = "log")]
+    #[must_use]
+    pub fn slow_wait_threshold(&self) -> Duration {
+        Duration::from_micros(self.slow_wait_threshold.load(Ordering::Relaxed) as u64)
+    }
+
+    /// Change how long a blocking acquire can wait before it logs a warning, defaults to 1 second
+    ///
+    /// The threshold is stored with microsecond precision
+    #[cfg(feature = "log")]
+    pub fn set_slow_wait_threshold(&self, threshold: Duration) {
+        self.slow_wait_threshold.store(
+            usize::try_from(threshold.as_micros()).unwrap_or(usize::MAX),
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Returns true if a guard was dropped while its thread was panicking
+    ///
+    /// Always false for semaphores that weren't created with [`Semaphore::new_poisoning`]
+    #[cfg(feature = "std")]
+    #[must_use]
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        self.poison
+            .as_ref()
+            .is_some_and(|poison| poison.load(Ordering::SeqCst))
+    }
+
+    /// Clear the poisoned state so [`Semaphore::try_get`] succeeds again
+    #[cfg(feature = "std")]
+    pub fn clear_poison(&self) {
+        if let Some(poison) = &self.poison {
+            poison.store(false, Ordering::SeqCst);
+        }
+    }
+
+    /// Stop handing out permits, every acquire fails with [`SemaphoreError::Closed`][`crate::SemaphoreError::Closed`] from now on
+    ///
+    /// Blocked threads and tasks are woken so they fail too. Guards that are already held stay valid
+    /// and release their permits as usual, so [`Semaphore::wait_for_idle`] can wait for them. A semaphore can't be reopened
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        #[cfg(any(feature = "async", feature = "embassy"))]
+        self.wakers.wake_available(usize::MAX, self.wake_order());
+        #[cfg(feature = "std")]
+        self.parked.unpark_many(usize::MAX, self.wake_order());
+        #[cfg(feature = "std")]
+        if let Some(fair) = &self.fair {
+            fair.unpark_front();
+        }
+        if let Some(parker) = self.parker {
+            parker.unpark();
+        }
+    }
+
+    /// Get the number of threads and tasks currently waiting for a permit
+    ///
+    /// Counts the threads blocked with [`WaitStrategy::Park`] or in the queue of [`Semaphore::new_fair`],
+    /// and with `async` the waiting tasks. Threads waiting with another strategy aren't tracked anywhere,
+    /// so they are missing. The parts are read one after the other, so the result is only a snapshot
+    /// that may be out of date by the time it is returned
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn waiter_count(&self) -> usize {
+        // Fair waiters park too, so only count them once
+        let threads = match &self.fair {
+            Some(fair) => fair.len().max(self.parked.len()),
+            None => self.parked.len(),
+        };
+        #[cfg(feature = "async")]
+        return threads + self.wakers.len();
+        #[cfg(not(feature = "async"))]
+        return threads;
+    }
+
+    /// Returns true once [`Semaphore::close`] was called
+    #[must_use]
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Block until every permit has been released, returns `false` if it gave up first
+    ///
+    /// New acquires can still take permits while this waits unless the semaphore is closed.
+    /// Like the blocking acquires it gives up with `no_block` or after [`shutdown_all`][`crate::shutdown_all`],
+    /// but not because the semaphore is closed
+    #[cfg(feature = "std")]
+    pub fn wait_for_idle(&self) -> bool {
+        while self.count(Ordering::SeqCst) != 0 {
+            if cfg!(feature = "no_block") || crate::is_shutting_down() {
+                return false;
+            }
+            // Releases unpark parked threads, the timeout covers a wakeup that went to an acquire instead
+            self.parked
+                .park_timeout(Self::PARK_TIMEOUT, || self.count(Ordering::SeqCst) == 0);
+        }
+        true
+    }
+
+    /// Block until a permit is free without taking it, returns `false` if it gave up first
+    ///
+    /// Waits with the [`WaitStrategy`] and is woken by releases like a blocking acquire, so the caller
+    /// can decide whether to acquire here or somewhere else. Nothing is reserved, another thread may take
+    /// the permit first. Gives up with `no_block`, after [`shutdown_all`][`crate::shutdown_all`], or once the semaphore is closed
+    #[cfg(feature = "std")]
+    pub fn wait_for_available(&self) -> bool {
+        self.wait_for_available_until(None)
+    }
+
+    /// Like [`Semaphore::wait_for_available`], but also gives up once `timeout` has passed
+    #[cfg(feature = "std")]
+    pub fn wait_for_available_timeout(&self, timeout: Duration) -> bool {
+        // Like `try_get_for`, a timeout too large for an `Instant` waits without a deadline
+        self.wait_for_available_until(std::time::Instant::now().checked_add(timeout))
+    }
+
+    #[cfg(feature = "std")]
+    fn wait_for_available_until(&self, deadline: Option<std::time::Instant>) -> bool {
+        let available = self.wait_until(deadline);
+        if available {
+            // The release that woke this thread meant to hand a permit over, pass the wakeup on
+            // so a thread waiting to acquire doesn't sit out a park timeout
+            self.parked.unpark_one(self.wake_order());
+        }
+        available
+    }
+
+    /// Wait for the count to drop below max without blocking the thread, then increment it and return a Guard
+    ///
+    /// Dropping the future before it completes gives up its place without consuming a permit
+    /// # Errors
+    /// The future resolves to [`SemaphoreError::Closed`][`crate::SemaphoreError::Closed`] if the semaphore is closed
+    /// while it waits, or to any other error [`Semaphore::try_get`] returns besides `AtMaxCount`
+    #[cfg(any(feature = "async", feature = "embassy"))]
+    pub fn acquire(&self) -> Acquire<'_> {
+        self.acquire_many(1)
+    }
+
+    /// Like [`Semaphore::acquire`], but waits until `permits` can be taken at once
+    ///
+    /// Waiters are woken in order and only once enough permits are free for them,
+    /// so a large request isn't overtaken by smaller ones that queued after it
+    #[cfg(any(feature = "async", feature = "embassy"))]
+    pub fn acquire_many(&self, permits: usize) -> Acquire<'_> {
+        Acquire {
+            semaphore: self,
+            entry: WaitEntry::default(),
+            permits,
+            done: false,
+        }
+    }
+
+    /// Try to increment the count and return a Guard
+    ///
+    /// Never blocks
+    /// # Errors
+    /// Will error if the count is at max already, or if the semaphore is poisoned
+    #[inline]
+    pub fn try_get(&self) -> Result<SemaphoreGuard<'_>, crate::SemaphoreError> {
+        #[cfg(feature = "std")]
+        if self.is_poisoned() {
+            return Err(crate::SemaphoreError::Poisoned);
+        }
+        self.try_get_unpoisoned()
+    }
+
+    /// Like [`Semaphore::try_get`], but also returns the count right after this acquire
+    ///
+    /// The count is the value the compare-and-swap wrote, so it includes this permit and isn't
+    /// a separate read that other threads could change in between. Never blocks
+    /// # Errors
+    /// Will error if the count is at max already, or if the semaphore is poisoned
+    pub fn try_get_with_count(&self) -> Result<(SemaphoreGuard<'_>, usize), crate::SemaphoreError> {
+        #[cfg(feature = "std")]
+        if self.is_poisoned() {
+            return Err(crate::SemaphoreError::Poisoned);
+        }
+        let count = self.increment(Ordering::SeqCst, Ordering::SeqCst)?;
+        Ok((self.acquired_guard(1)?, count))
+    }
+
+    /// Like [`Semaphore::try_get`], but the compare-and-swap that takes the permit uses `success` and `failure`
+    ///
+    /// [`Semaphore::try_get`] uses `SeqCst` for both. `Acquire` is enough for `success` to see everything done
+    /// before the permit was last released, releases always use `SeqCst`. Blocking acquires keep using `SeqCst`
+    /// so they can't miss a wakeup, the orderings here only apply to this attempt. Never blocks
+    /// # Errors
+    /// Will error if the count is at max already, or if the semaphore is poisoned
+    /// # Panics
+    /// Debug builds panic if `failure` isn't a valid load ordering or is stronger than `success`
+    #[inline]
+    #[track_caller]
+    pub fn try_get_ordered(
+        &self,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<SemaphoreGuard<'_>, crate::SemaphoreError> {
+        debug_assert_load(failure, "try_get_ordered");
+        debug_assert!(
+            strength(failure) <= strength(success),
+            "'Semaphore::try_get_ordered' was given a failure ordering of {failure:?}, which is stronger than the success ordering of {success:?}"
+        );
+        #[cfg(feature = "std")]
+        if self.is_poisoned() {
+            return Err(crate::SemaphoreError::Poisoned);
+        }
+        self.try_get_unpoisoned_ordered(success, failure)
+    }
+
+    /// Retry [`Semaphore::try_get`] with a [`core::hint::spin_loop`] in between, up to `max_spins` times
+    ///
+    /// This bounds the effort spent on acquiring without needing a clock, for `no_std` targets without `Instant`.
+    /// The budget is a number of spins, how long it lasts depends on the CPU
+    /// # Errors
+    /// Will error if the count is still at max once the budget is spent, or if the semaphore is poisoned
+    pub fn try_get_budget(
+        &self,
+        max_spins: u32,
+    ) -> Result<SemaphoreGuard<'_>, crate::SemaphoreError> {
+        let mut spins = 0;
+        loop {
+            match self.try_get() {
+                Err(crate::SemaphoreError::AtMaxCount) if spins < max_spins => {
+                    spins += 1;
+                    core::hint::spin_loop();
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Like [`Semaphore::try_get`], but waits up to `timeout` for a permit if the semaphore is at its max
+    ///
+    /// The deadline is fixed when the call starts, so every wait is cut short to the time that is left
+    /// and the whole call returns shortly after `timeout` even with a long [`WaitStrategy::Sleep`].
+    /// A timeout of zero makes a single attempt like [`Semaphore::try_get`]
+    /// # Errors
+    /// Will error with [`SemaphoreError::AtMaxCount`][`crate::SemaphoreError::AtMaxCount`] if it timed out,
+    /// with `ShuttingDown` if it had to wait after [`shutdown_all`][`crate::shutdown_all`], or if the semaphore is poisoned
+    #[cfg(feature = "std")]
+    pub fn try_get_for(
+        &self,
+        timeout: Duration,
+    ) -> Result<SemaphoreGuard<'_>, crate::SemaphoreError> {
+        // A timeout too large to represent as an `Instant` never expires
+        self.try_get_before(std::time::Instant::now().checked_add(timeout))
+    }
+
+    /// Like [`Semaphore::try_get_for`], but waits until `deadline` instead of for a duration
+    ///
+    /// Handy when several acquisitions share one deadline. A deadline that has already passed
+    /// makes a single attempt like [`Semaphore::try_get`], without waiting
+    /// # Errors
+    /// Will error with [`SemaphoreError::AtMaxCount`][`crate::SemaphoreError::AtMaxCount`] if the deadline passed,
+    /// with `ShuttingDown` if it had to wait after [`shutdown_all`][`crate::shutdown_all`], or if the semaphore is poisoned
+    #[cfg(feature = "std")]
+    pub fn try_get_until(
+        &self,
+        deadline: std::time::Instant,
+    ) -> Result<SemaphoreGuard<'_>, crate::SemaphoreError> {
+        self.try_get_before(Some(deadline))
+    }
+
+    /// The loop behind [`Semaphore::try_get_for`] and [`Semaphore::try_get_until`], retrying until `deadline` passes
+    #[cfg(feature = "std")]
+    fn try_get_before(
+        &self,
+        deadline: Option<std::time::Instant>,
+    ) -> Result<SemaphoreGuard<'_>, crate::SemaphoreError> {
+        if self.is_fair() {
+            return self.get_fair(deadline);
+        }
+        loop {
+            match self.try_get() {
+                Err(crate::SemaphoreError::AtMaxCount) => {
+                    if !self.wait_until(deadline) {
+                        let timed_out =
+                            deadline.is_some_and(|deadline| std::time::Instant::now() >= deadline);
+                        return Err(if timed_out {
+                            crate::SemaphoreError::AtMaxCount
+                        } else {
+                            self.gave_up_error()
+                        });
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Try to increment the count and return a Guard along with a slot index that no other guard holds
+    ///
+    /// The index is in `0..max` and is freed when the guard is dropped, so it can be used to
+    /// pick an element out of a fixed array of resources.
+    /// Never blocks
+    /// # Errors
+    /// Will error if the count is at max already, or if the semaphore is poisoned
+    /// # Panics
+    /// Panics if the semaphore wasn't created with [`Semaphore::new_indexed`]
+    pub fn try_get_indexed(&self) -> Result<(SemaphoreGuard<'_>, usize), crate::SemaphoreError> {
+        let slots = self
+            .slots
+            .as_ref()
+            .expect("'Semaphore::try_get_indexed' requires a semaphore made with 'new_indexed'");
+        let mut guard = self.try_get()?;
+
+        // Every guard holding a slot also holds a permit, so while we hold one there is a free slot
+        let taken = slots
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |taken| {
+                let slot = taken.trailing_ones() as usize;
+                (slot < self.max()).then_some(taken | (1 << slot))
+            })
+            .map_err(|_| crate::SemaphoreError::AtMaxCount)?;
+
+        let slot = taken.trailing_ones() as usize;
+        guard.slot = Some(slot);
+        Ok((guard, slot))
+    }
+
+    /// Try to add `permits` to the count at once, returning a single guard that holds all of them
+    ///
+    /// The permits are taken in one compare-and-swap, so either all of them are reserved or none are.
+    /// Asking for 0 returns a guard that holds nothing, asking for more than `max` always fails.
+    /// Never blocks
+    /// # Errors
+    /// Will error if fewer than `permits` are free, or if the semaphore is poisoned
+    pub fn try_get_many(
+        &self,
+        permits: usize,
+    ) -> Result<SemaphoreGuard<'_>, crate::SemaphoreError> {
+        #[cfg(feature = "std")]
+        if self.is_poisoned() {
+            return Err(crate::SemaphoreError::Poisoned);
+        }
+        if self.is_closed() {
+            return Err(crate::SemaphoreError::Closed);
+        }
+        if self.is_reserving() {
+            return Err(crate::SemaphoreError::AtMaxCount);
+        }
+        self.count
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                count
+                    .checked_add(permits)
+                    .filter(|count| *count <= self.max())
+            })
+            .map_err(|_| crate::SemaphoreError::AtMaxCount)?;
+
+        self.acquired_guard(permits)
+    }
+
+    /// Try to add one to the count, but only if `min_free_after` permits are still free afterwards
+    ///
+    /// Checks `count + 1 + min_free_after <= max` in the same compare-and-swap that takes the permit,
+    /// so the headroom stays available to higher-priority callers using plain [`Semaphore::try_get`].
+    /// Never blocks
+    /// # Errors
+    /// Will error if taking a permit would leave fewer than `min_free_after` free, or if the semaphore is poisoned
+    pub fn try_get_leaving(
+        &self,
+        min_free_after: usize,
+    ) -> Result<SemaphoreGuard<'_>, crate::SemaphoreError> {
+        #[cfg(feature = "std")]
+        if self.is_poisoned() {
+            return Err(crate::SemaphoreError::Poisoned);
+        }
+        if self.is_closed() {
+            return Err(crate::SemaphoreError::Closed);
+        }
+        if self.is_reserving() {
+            return Err(crate::SemaphoreError::AtMaxCount);
+        }
+        self.count
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                let count = count.checked_add(1)?;
+                count
+                    .checked_add(min_free_after)
+                    .filter(|needed| *needed <= self.max())
+                    .map(|_| count)
+            })
+            .map_err(|_| crate::SemaphoreError::AtMaxCount)?;
+
+        self.acquired_guard(1)
+    }
+
+    /// Block until `permits` have been reserved one at a time as they are freed
+    ///
+    /// While reserving, every other acquire treats the semaphore as full so the freed permits
+    /// can't be taken by anyone else. Only one thread reserves at a time, the others queue up
+    /// on `reservation`, so two large waiters can't each hold part of what the other needs.
+    /// On a fair semaphore the reserving thread also joins the fair queue and only collects
+    /// permits once it is at the front, so it can't jump ahead of threads that waited longer
+    #[cfg(all(feature = "std", feature = "wrapper"))]
+    pub(crate) fn reserve_many(
+        &self,
+        permits: usize,
+    ) -> Result<SemaphoreGuard<'_>, crate::SemaphoreError> {
+        // Joining after taking `reservation` keeps a queued reserver from waiting on the lock while at the front
+        let _front = crate::lock_ignoring_poison(&self.reservation);
+        self.reserving.store(true, Ordering::SeqCst);
+        let fair = self.fair.as_ref();
+        if let Some(fair) = fair {
+            fair.join();
+        }
+
+        let mut reserved = 0;
+        let result = loop {
+            if self.is_poisoned() {
+                break Err(crate::SemaphoreError::Poisoned);
+            }
+            if self.is_closed() {
+                break Err(crate::SemaphoreError::Closed);
+            }
+            if reserved == permits {
+                break Ok(());
+            }
+            let is_front = || fair.is_none_or(crate::wait::FairQueue::is_front);
+            if is_front()
+                && self
+                    .count
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                        (count < self.max()).then_some(count + 1)
+                    })
+                    .is_ok()
+            {
+                reserved += 1;
+            } else if !self.wait_while_blocked(
+                || !is_front() || self.at_max(Ordering::SeqCst),
+                || true,
+                std::thread::sleep,
+                || None,
+            ) {
+                break Err(self.gave_up_error());
+            }
+        };
+
+        self.reserving.store(false, Ordering::SeqCst);
+        if let Some(fair) = fair {
+            self.leave_fair(fair);
+        }
+        match result {
+            Ok(()) => self.acquired_guard(permits),
+            Err(err) => {
+                self.release_many(reserved);
+                Err(err)
+            }
+        }
+    }
+
+    /// Returns true if the semaphore was created with [`Semaphore::new_fair`]
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn is_fair(&self) -> bool {
+        self.fair.is_some()
+    }
+
+    /// Block until the current thread is at the front of the fair queue and a permit is free, or `deadline` passes
+    ///
+    /// Falls back to [`Semaphore::try_get`] while nobody is queued, so uncontended acquires skip the queue.
+    /// A thread that times out leaves the queue before returning, so it never takes a permit after giving up
+    /// and the thread behind it moves up
+    #[cfg(feature = "std")]
+    pub(crate) fn get_fair(
+        &self,
+        deadline: Option<std::time::Instant>,
+    ) -> Result<SemaphoreGuard<'_>, crate::SemaphoreError> {
+        let fair = self
+            .fair
+            .as_ref()
+            .expect("'Semaphore::get_fair' requires a semaphore made with 'new_fair'");
+        match self.try_get() {
+            Err(crate::SemaphoreError::AtMaxCount) => {}
+            result => return result,
+        }
+
+        fair.join();
+        let result = loop {
+            if self.is_poisoned() {
+                break Err(crate::SemaphoreError::Poisoned);
+            }
+            if self.is_closed() {
+                break Err(crate::SemaphoreError::Closed);
+            }
+            // Only the front thread may take a permit, and it bypasses the queue check in `try_get`
+            if fair.is_front()
+                && self
+                    .count
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                        (count < self.max()).then_some(count + 1)
+                    })
+                    .is_ok()
+            {
+                break Ok(());
+            }
+            if !self.wait_while_blocked(
+                || !fair.is_front() || self.at_max(Ordering::SeqCst),
+                || deadline.is_none_or(|deadline| std::time::Instant::now() < deadline),
+                std::thread::sleep,
+                || {
+                    deadline.map(|deadline| {
+                        deadline.saturating_duration_since(std::time::Instant::now())
+                    })
+                },
+            ) {
+                let timed_out =
+                    deadline.is_some_and(|deadline| std::time::Instant::now() >= deadline);
+                break Err(if timed_out {
+                    crate::SemaphoreError::AtMaxCount
+                } else {
+                    self.gave_up_error()
+                });
+            }
+        };
+        self.leave_fair(fair);
+        result.and_then(|()| self.acquired_guard(1))
+    }
+
+    /// Take the current thread out of the fair queue, letting everyone else go again once it's empty
+    #[cfg(feature = "std")]
+    fn leave_fair(&self, fair: &crate::wait::FairQueue) {
+        fair.leave();
+
+        if fair.is_empty() {
+            // Acquires that stood aside for the queue can go again
+            #[cfg(feature = "async")]
+            self.wake_available();
+            self.parked
+                .unpark_many(self.available(Ordering::SeqCst), self.wake_order());
+        }
+    }
+
+    /// Returns true while a blocking multi-permit acquire is collecting permits or fair waiters are queued
+    #[cfg_attr(not(feature = "std"), allow(clippy::unused_self))]
+    #[inline]
+    fn is_reserving(&self) -> bool {
+        #[cfg(all(feature = "std", feature = "wrapper"))]
+        if self.reserving.load(Ordering::SeqCst) {
+            return true;
+        }
+        #[cfg(feature = "std")]
+        return self.fair.as_ref().is_some_and(|fair| !fair.is_empty());
+        #[cfg(not(feature = "std"))]
+        return false;
+    }
+
+    /// Like [`Semaphore::try_get`], but records `label` as the holder of the permit
+    ///
+    /// With the `debug_holders` feature the labels of the live guards are listed by [`Semaphore::holders`],
+    /// without it the label is ignored and this is the same as [`Semaphore::try_get`].
+    /// Never blocks
+    /// # Errors
+    /// Will error if the count is at max already, or if the semaphore is poisoned
+    #[cfg_attr(not(feature = "debug_holders"), allow(unused_variables))]
+    pub fn try_get_labeled(
+        &self,
+        label: &'static str,
+    ) -> Result<SemaphoreGuard<'_>, crate::SemaphoreError> {
+        #[cfg_attr(not(feature = "debug_holders"), allow(unused_mut))]
+        let mut guard = self.try_get()?;
+        #[cfg(feature = "debug_holders")]
+        {
+            crate::lock_ignoring_poison(&self.holders).push(label);
+            guard.label = Some(label);
+        }
+        Ok(guard)
+    }
+
+    /// Like [`Semaphore::try_get`], but enters `span` until the guard is dropped
+    ///
+    /// Everything logged while the permit is held is attributed to the span,
+    /// and the span's lifetime in a trace shows how long the permit was held.
+    /// Never blocks
+    /// # Errors
+    /// Will error if the count is at max already, or if the semaphore is poisoned
+    #[cfg(feature = "tracing")]
+    pub fn try_get_instrumented(
+        &self,
+        span: tracing::Span,
+    ) -> Result<SemaphoreGuard<'_>, crate::SemaphoreError> {
+        let mut guard = self.try_get()?;
+        guard.span = Some(span.entered());
+        Ok(guard)
+    }
+
+    /// Get the labels of the guards from [`Semaphore::try_get_labeled`] that are still alive
+    ///
+    /// A label is listed once for every guard holding it, guards from the other methods aren't listed
+    #[cfg(feature = "debug_holders")]
+    #[must_use]
+    pub fn holders(&self) -> Vec<&'static str> {
+        crate::lock_ignoring_poison(&self.holders).clone()
+    }
+
+    /// Get where every live guard was acquired, oldest first unless some were dropped out of order
+    ///
+    /// Permits held without a guard, like the ones behind owned guards, aren't listed.
+    /// A guard that was leaked with [`core::mem::forget`] stays listed, and is printed when the semaphore is dropped
+    #[cfg(feature = "backtrace")]
+    #[must_use]
+    pub fn holders_with_backtraces(&self) -> Vec<std::sync::Arc<std::backtrace::Backtrace>> {
+        crate::lock_ignoring_poison(&self.backtraces.0).clone()
+    }
+
+    /// Like [`Semaphore::try_get`], but still hands out the guard if the semaphore is poisoned
+    ///
+    /// Never blocks
+    /// # Errors
+    /// Will error if the count is at max already
+    #[cfg(feature = "std")]
+    pub fn try_get_poisoned(
+        &self,
+    ) -> Result<
+        Result<SemaphoreGuard<'_>, crate::PoisonError<SemaphoreGuard<'_>>>,
+        crate::SemaphoreError,
+    > {
+        let guard = self.try_get_unpoisoned()?;
+        if self.is_poisoned() {
+            Ok(Err(crate::PoisonError::new(guard)))
+        } else {
+            Ok(Ok(guard))
+        }
+    }
+
+    /// Like [`Semaphore::try_get`], but the permit is only released by [`Semaphore::release_manual`]
+    ///
+    /// Dropping a [`ManualGuard`] does nothing, so a loop taking many short-lived permits can
+    /// hand them all back with one decrement and one round of wakeups instead of one per guard.
+    /// This is a footgun: a guard that is dropped instead of released keeps its permit forever,
+    /// and nothing is released if the thread panics before the batch is handed back.
+    /// Never blocks
+    /// # Errors
+    /// Will error if the count is at max already, or if the semaphore is poisoned
+    #[cfg(feature = "manual_release")]
+    pub fn try_get_manual(&self) -> Result<ManualGuard<'_>, crate::SemaphoreError> {
+        // The manual guard takes over the permit, so the raw guard isn't listed as a holder or a leak
+        self.try_get()?.forget();
+        Ok(ManualGuard { semaphore: self })
+    }
+
+    /// Release every permit in `guards` at once, waking a waiter for each of them
+    /// # Panics
+    /// Debug builds panic if a guard came from another semaphore
+    #[cfg(feature = "manual_release")]
+    pub fn release_manual<'guard>(&self, guards: impl IntoIterator<Item = ManualGuard<'guard>>) {
+        let mut permits = 0;
+        for guard in guards {
+            debug_assert!(
+                core::ptr::eq(guard.semaphore, self),
+                "'Semaphore::release_manual' was given a guard from another semaphore"
+            );
+            core::mem::forget(guard);
+            permits += 1;
+        }
+        if permits > 0 {
+            self.release_many(permits);
+        }
+    }
+
+    /// Decrement the count, poisoning the semaphore if the current thread is panicking
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(crate) fn release(&self) {
+        self.release_many(1);
+    }
+
+    /// Take `permits` off the count and wake a waiter for each of them
+    #[inline]
+    #[cfg_attr(not(feature = "std"), allow(unused_variables))]
+    pub(crate) fn release_many(&self, permits: usize) {
+        #[cfg(feature = "std")]
+        if let Some(poison) = &self.poison {
+            if std::thread::panicking() {
+                poison.store(true, Ordering::SeqCst);
+            }
+        }
+        let previous = self.count.fetch_sub(permits, Ordering::SeqCst);
+        debug_assert!(
+            previous >= permits,
+            "Released {permits} permits with only {previous} held, a permit was released twice"
+        );
+        let count = previous.wrapping_sub(permits);
+        if let Some(parker) = self.parker {
+            parker.unpark();
+        }
+        #[cfg(feature = "metrics")]
+        self.report_metrics(0);
+        #[cfg(feature = "std")]
+        self.report_change(count);
+        #[cfg(feature = "tracing")]
+        tracing::trace!(target: "semaphorus", permits, count, max = self.max(), "released");
+        #[cfg(any(feature = "async", feature = "embassy"))]
+        self.wake_available();
+        #[cfg(feature = "std")]
+        self.parked.unpark_many(permits, self.wake_order());
+        #[cfg(feature = "std")]
+        if let Some(fair) = &self.fair {
+            fair.unpark_front();
+        }
+        #[cfg(feature = "std")]
+        self.callbacks.run(permits);
+    }
+
+    /// Call `callback` once a permit is free, without blocking or needing an async runtime
+    ///
+    /// The callback runs once, on the thread that releases the permit, so it should be quick, like scheduling
+    /// work on an event loop. If a permit is already free it runs right away on the current thread.
+    /// It isn't handed a permit, by the time it runs another thread may have taken it, so it should
+    /// [`Semaphore::try_get`] and register again if that fails
+    #[cfg(feature = "std")]
+    pub fn on_available(&self, callback: Box<dyn FnOnce() + Send>) {
+        self.callbacks.push(callback);
+        // Registered before checking, so a permit released in between still runs it
+        self.callbacks.run(self.available(Ordering::SeqCst));
+    }
+
+    /// Call `callback` with the new count after every acquire and every release, replacing the previous one
+    ///
+    /// Meant for pushing a gauge without polling. It runs on the thread that changed the count, outside of any lock,
+    /// so it can use the semaphore. The count after an acquire is read right after it, so with concurrent acquires
+    /// and releases the calls can be out of order. Taking `&mut self` means it is set up before the semaphore is shared
+    #[cfg(feature = "std")]
+    pub fn on_change(&mut self, callback: Box<dyn Fn(usize) + Send + Sync>) {
+        self.on_change = Some(callback);
+    }
+
+    /// Pass `count` to the callback from [`Semaphore::on_change`], if there is one
+    #[cfg(feature = "std")]
+    fn report_change(&self, count: usize) {
+        if let Some(on_change) = &self.on_change {
+            on_change(count);
+        }
+    }
+
+    /// Try to increment the count, registering the task to be woken when a permit is released if it can't
+    ///
+    /// On `Ready(Ok(()))` the count has been incremented and must be handed back with [`Semaphore::release`]
+    #[cfg(any(feature = "async", feature = "embassy"))]
+    pub(crate) fn poll_acquire(
+        &self,
+        cx: &mut Context<'_>,
+        entry: &mut WaitEntry,
+        permits: usize,
+    ) -> Poll<Result<(), crate::SemaphoreError>> {
+        let result = match self.try_get_many(permits) {
+            Err(crate::SemaphoreError::AtMaxCount) => {
+                self.wakers.register(entry, cx.waker(), permits);
+                // A permit may have been released before the waker was registered
+                self.try_get_many(permits)
+            }
+            result => result,
+        };
+
+        match result {
+            Ok(guard) => {
+                guard.detach();
+                self.wakers.remove(entry);
+                Poll::Ready(Ok(()))
+            }
+            Err(crate::SemaphoreError::AtMaxCount) => Poll::Pending,
+            Err(err) => {
+                self.cancel_acquire(entry);
+                Poll::Ready(Err(err))
+            }
+        }
+    }
+
+    /// Stop waiting for a permit, passing on any wakeup `entry` received but didn't use
+    #[cfg(any(feature = "async", feature = "embassy"))]
+    pub(crate) fn cancel_acquire(&self, entry: &mut WaitEntry) {
+        if self.wakers.remove(entry) {
+            self.wake_available();
+        }
+    }
+
+    /// Wake the queued tasks that the currently free permits are enough for
+    #[cfg(any(feature = "async", feature = "embassy"))]
+    fn wake_available(&self) {
+        self.wakers
+            .wake_available(self.available(Ordering::SeqCst), self.wake_order());
+    }
+
+    #[inline]
+    fn try_get_unpoisoned(&self) -> Result<SemaphoreGuard<'_>, crate::SemaphoreError> {
+        self.try_get_unpoisoned_ordered(Ordering::SeqCst, Ordering::SeqCst)
+    }
+
+    #[inline]
+    fn try_get_unpoisoned_ordered(
+        &self,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<SemaphoreGuard<'_>, crate::SemaphoreError> {
+        self.increment(success, failure)
+            .and_then(|_| self.acquired_guard(1))
+    }
+
+    /// The compare-and-swaps of [`Semaphore::try_get`], with the orderings used for taking the permit and for failing to
+    ///
+    /// Returns the count right after the permit was added, as written by the compare-and-swap
+    #[inline]
+    fn increment(
+        &self,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<usize, crate::SemaphoreError> {
+        if self.is_closed() {
+            return Err(crate::SemaphoreError::Closed);
+        }
+        if self.is_unbounded() {
+            // The count can only reach usize::MAX if that many guards are alive at once,
+            // but refuse to wrap around to 0 if it somehow does
+            return match self
+                .count
+                .fetch_update(success, failure, |count| count.checked_add(1))
+            {
+                Ok(count) => Ok(count + 1),
+                Err(_) => Err(crate::SemaphoreError::AtMaxCount),
+            };
+        }
+        if self.max() == 1 && !self.is_reserving() {
+            // Mutex-like semaphores only ever go from 0 to 1, so a single CAS without a prior load
+            // decides it, and any failure means the permit is taken
+            #[cfg(test)]
+            interleave::reached(interleave::Point::BeforeSwap);
+            if self.count.compare_exchange(0, 1, success, failure).is_ok() {
+                return Ok(1);
+            }
+            #[cfg(feature = "defmt")]
+            defmt::debug!("semaphore at max count of 1");
+            return Err(crate::SemaphoreError::AtMaxCount);
+        }
+        // Checking the count and incrementing it in one CAS means two threads that both see
+        // `max - 1` can't both take the last permit, the slower one retries and sees `max`
+        let mut count = self.count.load(failure);
+        loop {
+            // A count at usize::MAX fails here instead of wrapping, even if a resize left it above the max
+            let next = match count.checked_add(1) {
+                Some(next) if next <= self.max() && !self.is_reserving() => next,
+                _ => {
+                    #[cfg(feature = "defmt")]
+                    defmt::debug!("semaphore at max count of {=usize}", self.max());
+                    return Err(crate::SemaphoreError::AtMaxCount);
+                }
+            };
+            #[cfg(test)]
+            interleave::reached(interleave::Point::BeforeSwap);
+            match self
+                .count
+                .compare_exchange_weak(count, next, success, failure)
+            {
+                Ok(_) => return Ok(next),
+                // Also reached on spurious failures, where `actual` is still `count`
+                Err(actual) => count = actual,
+            }
+        }
+    }
+}
+
+/// Test-only hooks run by the acquires at points where other code could interleave or panic
+///
+/// Lets a test deterministically act inside the window where another thread's acquire could interleave,
+/// or panic right after the count was incremented. The hook is per thread and compiled out outside of tests
+#[cfg(test)]
+pub(crate) mod interleave {
+    use std::{boxed::Box, cell::RefCell};
+
+    /// Where in an acquire the hook runs
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub(crate) enum Point {
+        /// In the `try_get` CAS loop, between loading the count and swapping it
+        BeforeSwap,
+        /// After the count was incremented and the guard created, before it is returned
+        AfterAcquire,
+    }
+
+    type Hook = (Point, Box<dyn FnMut()>);
+
+    std::thread_local! {
+        static HOOK: RefCell<Option<Hook>> = const { RefCell::new(None) };
+    }
+
+    /// Run `hook` every time the current thread reaches `point`, until [`clear`] is called
+    pub(crate) fn set(point: Point, hook: impl FnMut() + 'static) {
+        HOOK.set(Some((point, Box::new(hook))));
+    }
+
+    pub(crate) fn clear() {
+        HOOK.set(None);
+    }
+
+    /// Run the hook if one is set for `point`, it is taken out while it runs so acquiring from inside it doesn't recurse
+    pub(super) fn reached(point: Point) {
+        let Some((hooked, mut hook)) = HOOK.take() else {
+            return;
+        };
+        if hooked == point {
+            hook();
+        }
+        HOOK.with_borrow_mut(|slot| {
+            if slot.is_none() {
+                *slot = Some((hooked, hook));
+            }
+        });
+    }
+}
+
+/// The future returned by [`Semaphore::acquire`]
+#[cfg(any(feature = "async", feature = "embassy"))]
+#[must_use = "futures do nothing unless polled"]
+pub struct Acquire<'guard> {
+    semaphore: &'guard Semaphore,
+    entry: WaitEntry,
+    permits: usize,
+    done: bool,
+}
+
+#[cfg(any(feature = "async", feature = "embassy"))]
+impl<'guard> Future for Acquire<'guard> {
+    type Output = Result<SemaphoreGuard<'guard>, crate::SemaphoreError>;
+
+    /// # Panics
+    /// Panics if polled after completion
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        assert!(!self.done, "Acquire polled after completion");
+        let this = &mut *self;
+
+        match this
+            .semaphore
+            .poll_acquire(cx, &mut this.entry, this.permits)
+        {
+            Poll::Ready(result) => {
+                // A failed poll already gave up its place in the queue
+                this.done = true;
+                Poll::Ready(
+                    result.map(|()| SemaphoreGuard::acquired_many(this.semaphore, this.permits)),
+                )
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(any(feature = "async", feature = "embassy"))]
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.semaphore.cancel_acquire(&mut self.entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_promote_fails_where_try_get_would() {
+        // The promotion would be a second acquire, the cap only allows one
+        let semaphore = Semaphore::new_with_total_cap(3, 1);
+        let guard = semaphore.try_get().unwrap();
+        let guard = guard.try_promote().unwrap_err();
+        assert_eq!(guard.permits(), 1);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+        drop(guard);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+
+        let semaphore = Semaphore::new(3);
+        let guard = semaphore.try_get().unwrap();
+        semaphore.close();
+        let guard = guard.try_promote().unwrap_err();
+        assert_eq!(guard.permits(), 1);
+        drop(guard);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_maximum_count_works() {
+        let semaphore = Semaphore::new(4);
+
+        let (g1, g2, g3, g4) = (
+            semaphore.try_get(),
+            semaphore.try_get(),
+            semaphore.try_get(),
+            semaphore.try_get(),
+        );
+
+        assert_eq!(
+            (g1.is_ok(), g2.is_ok(), g3.is_ok(), g4.is_ok()),
+            (true, true, true, true)
+        );
+
+        let g5 = semaphore.try_get();
+
+        assert!(g5.is_err());
+
+        drop(g1);
+
+        let g6 = semaphore.try_get();
+
+        assert!(g6.is_ok());
+    }
+
+    #[test]
+    fn test_ordered_try_get_keeps_the_limit() {
+        for (success, failure) in [
+            (Ordering::SeqCst, Ordering::SeqCst),
+            (Ordering::Acquire, Ordering::Relaxed),
+            (Ordering::AcqRel, Ordering::Acquire),
+            (Ordering::Relaxed, Ordering::Relaxed),
+        ] {
+            let semaphore = Semaphore::new(3);
+            let guards: Vec<_> = (0..3)
+                .map(|_| semaphore.try_get_ordered(success, failure).unwrap())
+                .collect();
+            assert!(semaphore.try_get_ordered(success, failure).is_err());
+            assert!(semaphore.try_get().is_err());
+
+            drop(guards);
+            assert!(semaphore.try_get_ordered(success, failure).is_ok());
+            assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "which is stronger than the success ordering")]
+    fn test_try_get_ordered_rejects_stronger_failure() {
+        let _ = Semaphore::new(1).try_get_ordered(Ordering::Acquire, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_reset_frees_leaked_permits() {
+        let mut semaphore = Semaphore::new(2);
+        semaphore.try_get().unwrap().forget();
+        semaphore.try_get().unwrap().forget();
+        assert!(semaphore.try_get().is_err());
+
+        semaphore.reset();
+        assert_eq!(*semaphore.get_mut_count(), 0);
+        assert!(semaphore.try_get().is_ok());
+
+        *semaphore.get_mut_count() = 1;
+        assert_eq!(semaphore.available(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_with_initial_starts_taken() {
+        let semaphore = Semaphore::with_initial(2, 2);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 2);
+        assert!(semaphore.try_get().is_err());
+
+        semaphore.release_initial(1);
+        let _guard = semaphore.try_get().unwrap();
+        assert!(semaphore.try_get().is_err());
+    }
+
+    #[test]
+    fn test_count_at_the_top_errors_instead_of_wrapping() {
+        let semaphore = Semaphore::with_initial(usize::MAX, usize::MAX - 1);
+        let guard = semaphore.try_get().unwrap();
+        assert_eq!(semaphore.count(Ordering::SeqCst), usize::MAX);
+        assert_eq!(
+            semaphore.try_get().unwrap_err(),
+            crate::SemaphoreError::AtMaxCount
+        );
+        drop(guard);
+
+        let semaphore = Semaphore::with_initial(usize::MAX - 1, usize::MAX - 2);
+        let _guard = semaphore.try_get().unwrap();
+        assert!(semaphore.try_get().is_err());
+        assert_eq!(semaphore.count(Ordering::SeqCst), usize::MAX - 1);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "a permit was released twice")]
+    fn test_double_release_is_caught() {
+        let semaphore = Semaphore::new(2);
+        semaphore.release_many(1);
+    }
+
+    #[test]
+    fn test_try_get_with_count_reports_the_new_count() {
+        let semaphore = Semaphore::new(3);
+        let guards: Vec<_> = (1..=3)
+            .map(|expected| {
+                let (guard, count) = semaphore.try_get_with_count().unwrap();
+                assert_eq!(count, expected);
+                guard
+            })
+            .collect();
+        assert!(semaphore.try_get_with_count().is_err());
+
+        drop(guards);
+        // The mutex-like and unbounded paths report it too
+        assert_eq!(Semaphore::new(1).try_get_with_count().unwrap().1, 1);
+        let unbounded = Semaphore::unbounded();
+        let _first = unbounded.try_get().unwrap();
+        assert_eq!(unbounded.try_get_with_count().unwrap().1, 2);
+    }
+
+    #[test]
+    fn test_snapshot_adds_up() {
+        let semaphore = Semaphore::new(3);
+        let _guard = semaphore.try_get().unwrap();
+
+        let snapshot = semaphore.snapshot();
+        assert_eq!(snapshot, SemaphoreSnapshot { count: 1, max: 3 });
+        assert_eq!(snapshot.available() + snapshot.count, snapshot.max);
+        assert!(!snapshot.is_full());
+
+        semaphore.set_max(1);
+        assert!(semaphore.snapshot().is_full());
+        assert_eq!(semaphore.snapshot().available(), 0);
+    }
+
+    #[test]
+    fn test_unbounded_never_at_max() {
+        let semaphore = Semaphore::unbounded();
+        assert!(semaphore.is_unbounded());
+
+        let guards: [_; 64] = core::array::from_fn(|_| semaphore.try_get().unwrap());
+        assert_eq!(semaphore.count(Ordering::SeqCst), 64);
+        assert!(!semaphore.at_max(Ordering::SeqCst));
+
+        drop(guards);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_max_of_one_allows_one_guard() {
+        let semaphore = Semaphore::new(1);
+
+        let guard = semaphore.try_get().unwrap();
+        assert!(semaphore.at_max(Ordering::SeqCst));
+        assert_eq!(
+            semaphore.try_get().unwrap_err(),
+            crate::SemaphoreError::AtMaxCount
+        );
+
+        drop(guard);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+        assert!(semaphore.try_get().is_ok());
+    }
+
+    #[cfg(feature = "guard_ids")]
+    #[test]
+    fn test_guard_ids_are_unique() {
+        let (first, second) = (Semaphore::new(2), Semaphore::new(1));
+
+        let ids = [
+            first.try_get().unwrap().id(),
+            first.try_get().unwrap().id(),
+            second.try_get().unwrap().id(),
+        ];
+        assert!(ids[0] != ids[1] && ids[1] != ids[2] && ids[0] != ids[2]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_on_available_runs_on_release() {
+        use std::sync::{mpsc, Arc, Barrier};
+
+        let semaphore = Semaphore::new(1);
+        let runs = Arc::new(AtomicUsize::new(0));
+        let (held, release) = (Barrier::new(2), Barrier::new(2));
+
+        std::thread::scope(|s| {
+            let releaser = s.spawn(|| {
+                let guard = semaphore.try_get().unwrap();
+                held.wait();
+                release.wait();
+                drop(guard);
+                std::thread::current().id()
+            });
+            held.wait();
+
+            let (sender, receiver) = mpsc::channel();
+            let counter = Arc::clone(&runs);
+            semaphore.on_available(Box::new(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                sender.send(std::thread::current().id()).unwrap();
+            }));
+            assert_eq!(runs.load(Ordering::SeqCst), 0);
+
+            release.wait();
+            assert_eq!(receiver.recv().unwrap(), releaser.join().unwrap());
+        });
+
+        // Only runs once, and right away when a permit is already free
+        drop(semaphore.try_get().unwrap());
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        let counter = Arc::clone(&runs);
+        semaphore.on_available(Box::new(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics_follow_transitions() {
+        use metrics_util::{
+            debugging::{DebugValue, DebuggingRecorder},
+            CompositeKey, MetricKind,
+        };
+
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let available = metrics::with_local_recorder(&recorder, || {
+            let semaphore = Semaphore::new_with_metrics(3, "pool");
+            let guard = semaphore.try_get().unwrap();
+            let many = semaphore.try_get_many(2).unwrap();
+            let available = semaphore.available(Ordering::SeqCst);
+            drop((guard, many));
+            available
+        });
+        assert_eq!(available, 0);
+
+        let mut snapshot = snapshotter.snapshot().into_hashmap();
+        let mut value = |kind, name| {
+            let key = CompositeKey::new(kind, metrics::Key::from_static_name(name));
+            snapshot.remove(&key).map(|(_, _, value)| value)
+        };
+        assert_eq!(
+            value(MetricKind::Gauge, "pool.count"),
+            Some(DebugValue::Gauge(0.0.into()))
+        );
+        assert_eq!(
+            value(MetricKind::Gauge, "pool.available"),
+            Some(DebugValue::Gauge(3.0.into()))
+        );
+        assert_eq!(
+            value(MetricKind::Counter, "pool.acquires"),
+            Some(DebugValue::Counter(2))
+        );
+    }
+
+    #[test]
+    fn test_try_get_budget_gives_up() {
+        let semaphore = Semaphore::new(1);
+
+        let guard = semaphore.try_get_budget(0).unwrap();
+        assert_eq!(
+            semaphore.try_get_budget(100).unwrap_err(),
+            crate::SemaphoreError::AtMaxCount
+        );
+
+        drop(guard);
+        assert!(semaphore.try_get_budget(0).is_ok());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_on_change_sees_every_count() {
+        use std::sync::{Arc, Mutex};
+
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let mut semaphore = Semaphore::new(3);
+        let recorded = Arc::clone(&changes);
+        semaphore.on_change(Box::new(move |count| recorded.lock().unwrap().push(count)));
+
+        let first = semaphore.try_get().unwrap();
+        let many = semaphore.try_get_many(2).unwrap();
+        assert!(semaphore.try_get().is_err());
+        drop(first);
+        assert!(semaphore.try_get().is_ok());
+        drop(many);
+
+        assert_eq!(*changes.lock().unwrap(), [1, 3, 2, 3, 2, 0]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_try_get_budget_catches_a_release_while_spinning() {
+        let semaphore = Semaphore::new(1);
+        let guard = semaphore.try_get().unwrap();
+
+        std::thread::scope(|s| {
+            let spinner = s.spawn(|| semaphore.try_get_budget(u32::MAX).map(drop));
+            std::thread::sleep(Duration::from_millis(10));
+            drop(guard);
+            assert!(spinner.join().unwrap().is_ok());
+        });
+    }
+
+    #[test]
+    fn test_debug_shows_count_and_max() {
+        let semaphore = Semaphore::new(8);
+        let guard = semaphore.try_get_many(3).unwrap();
+
+        assert_eq!(format!("{semaphore:?}"), "Semaphore { count: 3, max: 8 }");
+        assert_eq!(format!("{guard:?}"), "SemaphoreGuard { permits: 3, .. }");
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "'Semaphore::count' was given Ordering::Release")]
+    fn test_count_rejects_release_ordering() {
+        let _ = Semaphore::new(1).count(Ordering::Release);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "'Semaphore::at_max' was given Ordering::AcqRel")]
+    fn test_at_max_rejects_acq_rel_ordering() {
+        let _ = Semaphore::new(1).at_max(Ordering::AcqRel);
+    }
+
+    #[cfg(feature = "manual_release")]
+    #[test]
+    fn test_manual_guards_release_in_one_batch() {
+        let semaphore = Semaphore::new(3);
+
+        let guards: Vec<_> = (0..3)
+            .map(|_| semaphore.try_get_manual().unwrap())
+            .collect();
+        assert!(semaphore.try_get_manual().is_err());
+        assert_eq!(semaphore.count(Ordering::SeqCst), 3);
+
+        semaphore.release_manual(guards);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+        semaphore.release_manual([]);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[cfg(all(feature = "manual_release", feature = "backtrace"))]
+    #[test]
+    fn test_manual_guards_leave_no_backtraces() {
+        let semaphore = Semaphore::new(2);
+
+        let guard = semaphore.try_get_manual().unwrap();
+        assert!(semaphore.holders_with_backtraces().is_empty());
+        semaphore.release_manual([guard]);
+        assert!(semaphore.holders_with_backtraces().is_empty());
+    }
+
+    #[test]
+    fn test_try_get_leaving_keeps_headroom() {
+        let semaphore = Semaphore::new(3);
+
+        let first = semaphore.try_get_leaving(1).unwrap();
+        let _second = semaphore.try_get_leaving(1).unwrap();
+        assert!(semaphore.try_get_leaving(1).is_err());
+        // The headroom is still there for a plain acquire
+        let _priority = semaphore.try_get().unwrap();
+        assert!(semaphore.try_get_leaving(0).is_err());
+
+        drop(first);
+        assert!(semaphore.try_get_leaving(0).is_ok());
+        assert!(semaphore.try_get_leaving(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_contended_try_get_never_exceeds_max() {
+        let semaphore = Semaphore::new(3);
+        let peak = AtomicUsize::new(0);
+
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..10_000 {
+                        if let Ok(guard) = semaphore.try_get() {
+                            peak.fetch_max(semaphore.count(Ordering::SeqCst), Ordering::SeqCst);
+                            drop(guard);
+                        }
+                    }
+                });
+            }
+        });
+
+        assert!(peak.load(Ordering::SeqCst) <= 3);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_mixed_single_and_many_never_exceed_max() {
+        let semaphore = Semaphore::new(5);
+        let peak = AtomicUsize::new(0);
+
+        std::thread::scope(|s| {
+            for permits in 1..=4 {
+                let (semaphore, peak) = (&semaphore, &peak);
+                s.spawn(move || {
+                    for _ in 0..10_000 {
+                        let guard = if permits == 1 {
+                            semaphore.try_get()
+                        } else {
+                            semaphore.try_get_many(permits)
+                        };
+                        if let Ok(guard) = guard {
+                            assert_eq!(guard.permits(), permits);
+                            peak.fetch_max(semaphore.count(Ordering::SeqCst), Ordering::SeqCst);
+                        }
+                    }
+                });
+            }
+        });
+
+        assert!(peak.load(Ordering::SeqCst) <= 5);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+
+        let nothing = semaphore.try_get_many(0).unwrap();
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+        drop(nothing);
+        assert!(semaphore.try_get_many(6).is_err());
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_acquire_in_the_window_is_not_overrun() {
+        static SEMAPHORE: Semaphore = Semaphore::new(1);
+
+        interleave::set(interleave::Point::BeforeSwap, || {
+            // Take the last permit between this thread's load and its swap
+            if let Ok(guard) = SEMAPHORE.try_get() {
+                core::mem::forget(guard);
+            }
+        });
+        let raced = SEMAPHORE.try_get();
+        interleave::clear();
+
+        assert_eq!(raced.unwrap_err(), crate::SemaphoreError::AtMaxCount);
+        assert_eq!(SEMAPHORE.count(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_panic_after_increment_does_not_leak() {
+        let semaphore = Semaphore::new(2);
+        let many = Semaphore::new(2);
+
+        let catch = |acquire: &dyn Fn()| {
+            interleave::set(interleave::Point::AfterAcquire, || panic!("injected"));
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(acquire))
+        };
+        let single = catch(&|| drop(semaphore.try_get()));
+        let multiple = catch(&|| drop(many.try_get_many(2)));
+        interleave::clear();
+
+        assert!(single.is_err() && multiple.is_err());
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+        assert_eq!(many.count(Ordering::SeqCst), 0);
+        assert!(many.try_get_many(2).is_ok());
+    }
+
+    #[test]
+    fn test_unbounded_rejects_overflow() {
+        let semaphore = Semaphore::new(usize::MAX);
+        assert!(semaphore.is_unbounded());
+        semaphore.count.store(usize::MAX - 1, Ordering::SeqCst);
+
+        let last = semaphore.try_get().unwrap();
+        assert!(semaphore.at_max(Ordering::SeqCst));
+        assert_eq!(
+            semaphore.try_get().unwrap_err(),
+            crate::SemaphoreError::AtMaxCount
+        );
+        assert_eq!(semaphore.count(Ordering::SeqCst), usize::MAX);
+
+        drop(last);
+        assert_eq!(semaphore.count(Ordering::SeqCst), usize::MAX - 1);
+    }
+
+    #[test]
+    fn test_wait_strategy_round_trips() {
+        let semaphore = Semaphore::new(1);
+        assert_eq!(semaphore.wait_strategy(), WaitStrategy::default());
+
+        for strategy in [
+            WaitStrategy::Spin,
+            WaitStrategy::Yield,
+            WaitStrategy::Sleep(Duration::ZERO),
+            WaitStrategy::Sleep(Duration::from_millis(5)),
+            WaitStrategy::Exponential {
+                start: Duration::from_micros(10),
+                max: Duration::from_millis(20),
+            },
+            WaitStrategy::Sleep(Duration::from_secs(90)),
+        ] {
+            semaphore.set_wait_strategy(strategy);
+            assert_eq!(semaphore.wait_strategy(), strategy);
+        }
+
+        #[cfg(feature = "std")]
+        {
+            semaphore.set_wait_strategy(WaitStrategy::Park);
+            assert_eq!(semaphore.wait_strategy(), WaitStrategy::Park);
+        }
+    }
+
+    #[cfg(all(feature = "wrapper", not(feature = "no_block")))]
+    #[test]
+    fn test_exponential_sleeps_double_up_to_the_cap() {
+        let sleeps_while_blocked = |strategy, checks| {
+            let semaphore = Semaphore::new(1);
+            semaphore.set_wait_strategy(strategy);
+            let checked = core::cell::Cell::new(0);
+            let mut sleeps = Vec::new();
+            // Run the wait twice to see the delay start over
+            for _ in 0..2 {
+                checked.set(0);
+                assert!(semaphore.wait_while_blocked(
+                    || {
+                        checked.set(checked.get() + 1);
+                        checked.get() <= checks
+                    },
+                    || true,
+                    |duration| sleeps.push(duration),
+                    || None,
+                ));
+            }
+            sleeps
+        };
+
+        assert!(sleeps_while_blocked(WaitStrategy::Spin, 5).is_empty());
+
+        let ms = Duration::from_millis;
+        let sleeps = sleeps_while_blocked(
+            WaitStrategy::Exponential {
+                start: ms(1),
+                max: ms(10),
+            },
+            6,
+        );
+        let first_wait = [ms(1), ms(2), ms(4), ms(8), ms(10), ms(10)];
+        assert_eq!(sleeps, [first_wait, first_wait].concat());
+    }
+
+    #[cfg(all(feature = "wrapper", not(feature = "no_block")))]
+    #[test]
+    fn test_spin_acquires_once_the_holder_releases() {
+        let semaphore = Semaphore::new(1);
+        semaphore.try_get().unwrap().forget();
+        semaphore.set_wait_strategy(WaitStrategy::Spin);
+
+        // Stands in for another core, releasing the permit after a few checks
+        let mut checks = 0;
+        assert!(semaphore.wait_while(|| {
+            checks += 1;
+            if checks == 10 {
+                semaphore.release_many(1);
+            }
+            true
+        }));
+        assert_eq!(checks, 10);
+        assert!(semaphore.try_get().is_ok());
+    }
+
+    #[test]
+    fn test_any_parker_keeps_the_semaphore_unwind_safe() {
+        fn assert_unwind_safe<T: core::panic::UnwindSafe + core::panic::RefUnwindSafe>(_: &T) {}
+
+        /// Not `RefUnwindSafe`, a `dyn Fn` could capture anything
+        struct Callback(&'static (dyn Fn() + Sync));
+
+        impl Parker for Callback {
+            fn park(&self) {
+                (self.0)();
+            }
+
+            fn unpark(&self) {}
+        }
+
+        static PARKER: Callback = Callback(&|| {});
+        assert_unwind_safe(&Semaphore::new_with_parker(1, &PARKER));
+    }
+
+    #[cfg(all(feature = "wrapper", not(feature = "no_block")))]
+    #[test]
+    fn test_parker_replaces_the_wait_strategy() {
+        struct Recorder {
+            parks: AtomicUsize,
+            unparks: AtomicUsize,
+            token: AtomicBool,
+        }
+
+        impl Parker for Recorder {
+            fn park(&self) {
+                self.parks.fetch_add(1, Ordering::SeqCst);
+                while !self.token.swap(false, Ordering::SeqCst) {
+                    core::hint::spin_loop();
+                }
+            }
+
+            fn unpark(&self) {
+                self.unparks.fetch_add(1, Ordering::SeqCst);
+                self.token.store(true, Ordering::SeqCst);
+            }
+        }
+
+        static PARKER: Recorder = Recorder {
+            parks: AtomicUsize::new(0),
+            unparks: AtomicUsize::new(0),
+            token: AtomicBool::new(false),
+        };
+        static SEMAPHORE: Semaphore = Semaphore::new_with_parker(1, &PARKER);
+
+        let guard = SEMAPHORE.try_get().unwrap();
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| assert!(SEMAPHORE.wait_while(|| true)));
+            std::thread::sleep(Duration::from_millis(10));
+            assert_eq!(PARKER.parks.load(Ordering::SeqCst), 1);
+            drop(guard);
+            waiter.join().unwrap();
+        });
+        assert_eq!(PARKER.unparks.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_peak_and_total_follow_acquires() {
+        let semaphore = Semaphore::new(4);
+
+        let first = semaphore.try_get().unwrap();
+        let many = semaphore.try_get_many(2).unwrap();
+        assert_eq!(semaphore.peak_count(), 3);
+        drop(many);
+        let second = semaphore.try_get().unwrap();
+        assert!(semaphore.try_get_many(4).is_err());
+        // A lower count afterwards doesn't lower the peak, failed acquires aren't counted
+        assert_eq!((semaphore.peak_count(), semaphore.total_acquired()), (3, 3));
+
+        semaphore.reset_metrics();
+        assert_eq!((semaphore.peak_count(), semaphore.total_acquired()), (2, 0));
+        drop((first, second));
+        drop(semaphore.try_get().unwrap());
+        assert_eq!((semaphore.peak_count(), semaphore.total_acquired()), (2, 1));
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn test_slow_wait_threshold_round_trips() {
+        let semaphore = Semaphore::new(1);
+        assert_eq!(semaphore.slow_wait_threshold(), Duration::from_secs(1));
+
+        semaphore.set_slow_wait_threshold(Duration::from_millis(250));
+        assert_eq!(semaphore.slow_wait_threshold(), Duration::from_millis(250));
+    }
+
+    #[cfg(feature = "backtrace")]
+    #[test]
+    fn test_leaked_guard_reports_where_it_was_acquired() {
+        let semaphore = Semaphore::new(2);
+        let held = semaphore.try_get().unwrap();
+        core::mem::forget(semaphore.try_get().unwrap());
+
+        drop(held);
+        let backtraces = semaphore.holders_with_backtraces();
+        assert_eq!(backtraces.len(), 1);
+        assert!(backtraces[0]
+            .to_string()
+            .contains("test_leaked_guard_reports_where_it_was_acquired"));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_timed_try_gets_give_up_at_max() {
+        let semaphore = Semaphore::new(1);
+        let guard = semaphore.try_get_for(Duration::ZERO).unwrap();
+
+        let err = semaphore.try_get_for(Duration::from_millis(10)).err();
+        assert_eq!(err, Some(crate::SemaphoreError::AtMaxCount));
+        let err = semaphore.try_get_until(std::time::Instant::now()).err();
+        assert_eq!(err, Some(crate::SemaphoreError::AtMaxCount));
+
+        drop(guard);
+        assert!(semaphore.try_get_until(std::time::Instant::now()).is_ok());
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_wait_for_available_takes_no_permit() {
+        let semaphore = Semaphore::new(1);
+        let guard = semaphore.try_get().unwrap();
+        assert!(!semaphore.wait_for_available_timeout(Duration::from_millis(10)));
+
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| semaphore.wait_for_available());
+            std::thread::sleep(Duration::from_millis(10));
+            drop(guard);
+            assert!(waiter.join().unwrap());
+        });
+
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+        assert!(semaphore.wait_for_available_timeout(Duration::MAX));
+        assert!(semaphore.try_get().is_ok());
+    }
+
+    #[cfg(feature = "debug_holders")]
+    #[test]
+    fn test_labeled_guards_are_listed_as_holders() {
+        let semaphore = Semaphore::new(4);
+
+        let queries = [
+            semaphore.try_get_labeled("db_query").unwrap(),
+            semaphore.try_get_labeled("db_query").unwrap(),
+        ];
+        let fill = semaphore.try_get_labeled("cache_fill").unwrap();
+        let _unlabeled = semaphore.try_get().unwrap();
+
+        let mut holders = semaphore.holders();
+        holders.sort_unstable();
+        assert_eq!(holders, ["cache_fill", "db_query", "db_query"]);
+
+        drop(fill);
+        drop(queries);
+        assert!(semaphore.holders().is_empty());
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_instrumented_guard_keeps_span_entered() {
+        use tracing::{span, subscriber, Event, Metadata, Subscriber};
+
+        static ENTERED: AtomicUsize = AtomicUsize::new(0);
+
+        /// Counts how many spans are currently entered
+        struct CountEntered;
+
+        impl Subscriber for CountEntered {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+                span::Id::from_u64(1)
+            }
+            fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+            fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+            fn event(&self, _: &Event<'_>) {}
+            fn enter(&self, _: &span::Id) {
+                ENTERED.fetch_add(1, Ordering::SeqCst);
+            }
+            fn exit(&self, _: &span::Id) {
+                ENTERED.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+
+        subscriber::with_default(CountEntered, || {
+            let semaphore = Semaphore::new(1);
+
+            let guard = semaphore
+                .try_get_instrumented(tracing::info_span!("db_query"))
+                .unwrap();
+            assert_eq!(ENTERED.load(Ordering::SeqCst), 1);
+            assert!(semaphore
+                .try_get_instrumented(tracing::info_span!("db_query"))
+                .is_err());
+            assert_eq!(ENTERED.load(Ordering::SeqCst), 1);
+
+            drop(guard);
+            assert_eq!(ENTERED.load(Ordering::SeqCst), 0);
+        });
+    }
+
+    #[cfg(all(feature = "tracing", not(feature = "no_block")))]
+    #[test]
+    fn test_blocked_wait_records_a_span() {
+        use std::sync::Mutex;
+        use tracing::{dispatcher, span, Dispatch, Event, Metadata, Subscriber};
+
+        static RECORDED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        /// Records the name of every span and the message of every event from this crate
+        struct Record;
+
+        impl Subscriber for Record {
+            fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+                metadata.target() == "semaphorus"
+            }
+            fn new_span(&self, attributes: &span::Attributes<'_>) -> span::Id {
+                RECORDED
+                    .lock()
+                    .unwrap()
+                    .push(format!("span {}", attributes.metadata().name()));
+                span::Id::from_u64(1)
+            }
+            fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+            fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+            fn event(&self, event: &Event<'_>) {
+                struct Message(String);
+                impl tracing::field::Visit for Message {
+                    fn record_debug(
+                        &mut self,
+                        field: &tracing::field::Field,
+                        value: &dyn core::fmt::Debug,
+                    ) {
+                        if field.name() == "message" {
+                            self.0 = format!("{value:?}");
+                        }
+                    }
+                }
+                let mut message = Message(String::new());
+                event.record(&mut message);
+                RECORDED.lock().unwrap().push(message.0);
+            }
+            fn enter(&self, _: &span::Id) {}
+            fn exit(&self, _: &span::Id) {}
+        }
+
+        // Both threads share one dispatch, with a single scoped dispatcher tracing caches whether
+        // a callsite is enabled from the first thread that hits it
+        let dispatch = Dispatch::new(Record);
+        let semaphore = Semaphore::new(1);
+        dispatcher::with_default(&dispatch, || {
+            let guard = semaphore.try_get().unwrap();
+            std::thread::scope(|s| {
+                let waiter = s.spawn(|| {
+                    dispatcher::with_default(&dispatch, || {
+                        assert!(semaphore.wait_while(|| true));
+                        drop(semaphore.try_get().unwrap());
+                    });
+                });
+                std::thread::sleep(Duration::from_millis(10));
+                drop(guard);
+                waiter.join().unwrap();
+            });
+        });
+
+        assert_eq!(
+            *RECORDED.lock().unwrap(),
+            ["acquired", "span wait", "released", "acquired", "released"]
+        );
+    }
+
+    #[test]
+    fn test_indexed_slots_are_unique() {
+        let semaphore = Semaphore::new_indexed(3);
+
+        let (g1, s1) = semaphore.try_get_indexed().unwrap();
+        let (_g2, s2) = semaphore.try_get_indexed().unwrap();
+        let (_g3, s3) = semaphore.try_get_indexed().unwrap();
+        assert_eq!((s1, s2, s3), (0, 1, 2));
+        assert!(semaphore.try_get_indexed().is_err());
+
+        drop(g1);
+        let (_g4, s4) = semaphore.try_get_indexed().unwrap();
+        assert_eq!(s4, 0);
+    }
+
+    #[test]
+    fn test_indexed_allows_usize_bits_slots() {
+        let semaphore = Semaphore::new_indexed(usize::BITS as usize);
+        let guards: [_; usize::BITS as usize] = core::array::from_fn(|i| {
+            let (guard, slot) = semaphore.try_get_indexed().unwrap();
+            assert_eq!(slot, i);
+            guard
+        });
+        assert!(semaphore.try_get_indexed().is_err());
+        drop(guards);
+    }
+
+    #[cfg(any(feature = "async", feature = "embassy"))]
+    #[test]
+    fn test_acquire_waits_for_release() {
+        let semaphore = Semaphore::new(1);
+        let held = semaphore.try_get().unwrap();
+
+        std::thread::scope(|s| {
+            let waiter =
+                s.spawn(|| drop(futures::executor::block_on(semaphore.acquire()).unwrap()));
+
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            assert!(!waiter.is_finished());
+
+            drop(held);
+            waiter.join().unwrap();
+        });
+
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_close_fails_pending_acquire() {
+        let semaphore = Semaphore::new(1);
+        let held = semaphore.try_get().unwrap();
+
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| futures::executor::block_on(semaphore.acquire()).err());
+
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            assert!(!waiter.is_finished());
+
+            semaphore.close();
+            assert_eq!(waiter.join().unwrap(), Some(crate::SemaphoreError::Closed));
+        });
+
+        assert_eq!(semaphore.waiter_count(), 0);
+        drop(held);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_weighted_waiters_are_woken_when_they_fit() {
+        use futures::task::{waker, ArcWake};
+        use std::sync::Arc;
+
+        #[derive(Default)]
+        struct CountWakes(AtomicUsize);
+
+        impl ArcWake for CountWakes {
+            fn wake_by_ref(arc_self: &Arc<Self>) {
+                arc_self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        fn poll<'a>(
+            acquire: &mut Acquire<'a>,
+            counter: &Arc<CountWakes>,
+        ) -> Option<SemaphoreGuard<'a>> {
+            let waker = waker(Arc::clone(counter));
+            match Pin::new(acquire).poll(&mut Context::from_waker(&waker)) {
+                Poll::Ready(result) => Some(result.unwrap()),
+                Poll::Pending => None,
+            }
+        }
+
+        let semaphore = Semaphore::new(3);
+        let held: [_; 3] = core::array::from_fn(|_| semaphore.try_get().unwrap());
+        let (three_wakes, one_wakes) = (Arc::default(), Arc::default());
+        let mut three = semaphore.acquire_many(3);
+        let mut one = semaphore.acquire();
+        assert!(poll(&mut three, &three_wakes).is_none());
+        assert!(poll(&mut one, &one_wakes).is_none());
+
+        let [first, second, third] = held;
+        drop(first);
+        assert_eq!(three_wakes.0.load(Ordering::SeqCst), 0);
+        assert_eq!(one_wakes.0.load(Ordering::SeqCst), 0);
+
+        drop((second, third));
+        assert_eq!(three_wakes.0.load(Ordering::SeqCst), 1);
+        assert_eq!(one_wakes.0.load(Ordering::SeqCst), 0);
+        let guard = poll(&mut three, &three_wakes).unwrap();
+        assert_eq!(guard.permits(), 3);
+
+        drop(guard);
+        assert_eq!(one_wakes.0.load(Ordering::SeqCst), 1);
+        assert_eq!(poll(&mut one, &one_wakes).unwrap().permits(), 1);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_dropped_waiters_take_no_permits() {
+        use futures::task::{waker, ArcWake};
+        use std::sync::Arc;
+
+        #[derive(Default)]
+        struct Woken(AtomicBool);
+
+        impl ArcWake for Woken {
+            fn wake_by_ref(arc_self: &Arc<Self>) {
+                arc_self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        fn register(acquire: &mut Acquire<'_>) -> Arc<Woken> {
+            let woken = Arc::default();
+            let waker = waker(Arc::clone(&woken));
+            assert!(Pin::new(acquire)
+                .poll(&mut Context::from_waker(&waker))
+                .is_pending());
+            woken
+        }
+
+        let semaphore = Semaphore::new(1);
+        let held = semaphore.try_get().unwrap();
+        let (mut first, mut second, mut third) = (
+            semaphore.acquire(),
+            semaphore.acquire(),
+            semaphore.acquire(),
+        );
+        let first_woken = register(&mut first);
+        let second_woken = register(&mut second);
+        let third_woken = register(&mut third);
+
+        // A pending waiter that is dropped leaves the queue without touching the count
+        drop(second);
+        drop(held);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+        assert!(first_woken.0.load(Ordering::SeqCst));
+
+        // A woken waiter that is dropped hands its wakeup on, skipping the one that left
+        drop(first);
+        assert!(!second_woken.0.load(Ordering::SeqCst));
+        assert!(third_woken.0.load(Ordering::SeqCst));
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+        drop(third);
+        assert!(semaphore.try_get_many(1).is_ok());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_lifo_wakes_newest_waiter_first() {
+        use futures::task::{waker, ArcWake};
+        use std::sync::Arc;
+
+        #[derive(Default)]
+        struct Woken(AtomicBool);
+
+        impl ArcWake for Woken {
+            fn wake_by_ref(arc_self: &Arc<Self>) {
+                arc_self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        fn register(acquire: &mut Acquire<'_>) -> Arc<Woken> {
+            let woken = Arc::default();
+            let waker = waker(Arc::clone(&woken));
+            assert!(Pin::new(acquire)
+                .poll(&mut Context::from_waker(&waker))
+                .is_pending());
+            woken
+        }
+
+        for (order, expected) in [
+            (WakeOrder::Fifo, [true, false]),
+            (WakeOrder::Lifo, [false, true]),
+        ] {
+            let semaphore = Semaphore::new(1);
+            semaphore.set_wake_order(order);
+            assert_eq!(semaphore.wake_order(), order);
+
+            let held = semaphore.try_get().unwrap();
+            let (mut oldest, mut newest) = (semaphore.acquire(), semaphore.acquire());
+            let (oldest_woken, newest_woken) = (register(&mut oldest), register(&mut newest));
+
+            drop(held);
+            let woken = [&oldest_woken, &newest_woken].map(|woken| woken.0.load(Ordering::SeqCst));
+            assert_eq!(woken, expected, "{order:?}");
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_panicking_holder_poisons() {
+        let semaphore = Semaphore::new_poisoning(2);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _guard = semaphore.try_get().unwrap();
+            panic!("poison the semaphore");
+        }));
+
+        assert!(result.is_err());
+        assert!(semaphore.is_poisoned());
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+        assert_eq!(
+            semaphore.try_get().unwrap_err(),
+            crate::SemaphoreError::Poisoned
+        );
+
+        let recovered = semaphore
+            .try_get_poisoned()
+            .unwrap()
+            .unwrap_or_else(crate::PoisonError::into_inner);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+        drop(recovered);
+
+        semaphore.clear_poison();
+        assert!(semaphore.try_get().is_ok());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_default_semaphore_never_poisons() {
+        let semaphore = Semaphore::new(1);
+
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _guard = semaphore.try_get().unwrap();
+            panic!("this should not poison");
+        }));
+
+        assert!(!semaphore.is_poisoned());
+        assert!(semaphore.try_get().is_ok());
     }
 }
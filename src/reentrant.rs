@@ -0,0 +1,228 @@
+//! A semaphore that lets a thread re-acquire the permit it already holds, see [`ReentrantSemaphore`]
+
+use crate::{raw, SemaphoreError};
+use core::{
+    cell::RefCell,
+    marker::PhantomData,
+    ops::Deref,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+std::thread_local! {
+    /// The id of every [`ReentrantSemaphore`] the current thread holds a permit for, and how many guards it holds
+    static HELD: RefCell<Vec<(u64, usize)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Hands out the ids of [`ReentrantSemaphore`]s, which are never reused
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Allows up to `max` threads to reference the data, where a thread that already holds a permit
+/// can acquire again without taking another one
+///
+/// Reentrancy only applies to the thread that took the permit, so a recursive function can
+/// re-enter the guarded region, but it does nothing for guards handed to or requested by other threads.
+/// The permit is released once every guard the thread got from the semaphore has been dropped
+pub struct ReentrantSemaphore<T: ?Sized> {
+    raw: raw::Semaphore,
+    /// Identifies the semaphore in [`HELD`], unlike its address it can't be taken over by a later semaphore
+    /// after a forgotten guard left an entry behind
+    id: u64,
+    data: T,
+}
+
+impl<T: ?Sized> ReentrantSemaphore<T> {
+    /// Attempt to get the value in the semaphore, reusing the current thread's permit if it holds one
+    ///
+    /// This function will never block
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if the thread holds no permit and the semaphore is at its max,
+    /// or [`SemaphoreError::Poisoned`] if the semaphore is poisoned
+    pub fn try_get(&self) -> Result<ReentrantGuard<'_, T>, SemaphoreError> {
+        if !self.reenter() {
+            self.raw.try_get()?.detach();
+            HELD.with_borrow_mut(|held| held.push((self.id, 1)));
+        }
+        Ok(ReentrantGuard {
+            semaphore: self,
+            _unsend: PhantomData,
+        })
+    }
+
+    /// Get the value in the semaphore, blocking until a permit is available if the current thread holds none
+    /// # Panics
+    /// This function will panic if `max` == 0, if the semaphore is poisoned,
+    /// or if the thread holds no permit and has to wait with `no_block` or after [`shutdown_all`][`crate::shutdown_all`]
+    pub fn get(&self) -> ReentrantGuard<'_, T> {
+        assert_ne!(
+            self.raw.max(),
+            0,
+            "Calling 'ReentrantSemaphore::get' on a semaphore with a max of 0 will loop forever!"
+        );
+        loop {
+            match self.try_get() {
+                Ok(guard) => return guard,
+                Err(SemaphoreError::AtMaxCount) => assert!(
+                    self.raw.wait_while(|| true),
+                    "ReentrantSemaphore::get failed: {}",
+                    self.raw.gave_up_error()
+                ),
+                Err(err) => panic!("ReentrantSemaphore::get failed: {err}"),
+            }
+        }
+    }
+
+    /// Get the current number of permits held, a thread holding several guards counts once
+    #[must_use]
+    pub fn count(&self, ordering: Ordering) -> usize {
+        self.raw.count(ordering)
+    }
+
+    /// Get how many guards the current thread holds from this semaphore
+    #[must_use]
+    pub fn held_by_current_thread(&self) -> usize {
+        HELD.with_borrow(|held| {
+            held.iter()
+                .find(|(entry, _)| *entry == self.id)
+                .map_or(0, |(_, depth)| *depth)
+        })
+    }
+
+    /// Get a mutable reference to the data in the semaphore
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Add a guard to the current thread's hold, returns `false` if it holds no permit yet
+    fn reenter(&self) -> bool {
+        HELD.with_borrow_mut(|held| {
+            held.iter_mut()
+                .find(|(entry, _)| *entry == self.id)
+                .map(|(_, depth)| *depth += 1)
+                .is_some()
+        })
+    }
+}
+
+impl<T> ReentrantSemaphore<T> {
+    /// Create a new semaphore with 0 counted references
+    pub fn new(value: T, max: usize) -> Self {
+        ReentrantSemaphore {
+            raw: raw::Semaphore::new(max),
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            data: value,
+        }
+    }
+
+    /// Move the value out of the semaphore
+    pub fn into_inner(self) -> T {
+        self.data
+    }
+}
+
+/// A reference to the data in a [`ReentrantSemaphore`]
+/// Releases the thread's permit when it is the last guard the thread holds
+#[must_use = "if unused, the guard will immediatly unlock"]
+pub struct ReentrantGuard<'guard, T: ?Sized> {
+    semaphore: &'guard ReentrantSemaphore<T>,
+    /// The hold count lives in a thread local, so the guard must be dropped on the thread that got it
+    _unsend: PhantomData<*const ()>,
+}
+
+impl<T: ?Sized> Deref for ReentrantGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.semaphore.data
+    }
+}
+
+// SAFETY: sharing the guard only hands out `&T`, only dropping it touches the thread local
+unsafe impl<T: ?Sized + Sync> Sync for ReentrantGuard<'_, T> {}
+
+impl<T: ?Sized> Drop for ReentrantGuard<'_, T> {
+    fn drop(&mut self) {
+        let id = self.semaphore.id;
+        let released = HELD.with_borrow_mut(|held| {
+            let index = held.iter().position(|(entry, _)| *entry == id)?;
+            held[index].1 -= 1;
+            (held[index].1 == 0).then(|| held.swap_remove(index))
+        });
+        if released.is_some() {
+            self.semaphore.raw.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recurse(semaphore: &ReentrantSemaphore<usize>, depth: usize) -> usize {
+        let guard = semaphore.try_get().unwrap();
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+        if depth == 0 {
+            *guard
+        } else {
+            *guard + recurse(semaphore, depth - 1)
+        }
+    }
+
+    #[test]
+    fn test_same_thread_reenters_without_permits() {
+        let semaphore = ReentrantSemaphore::new(1, 1);
+
+        assert_eq!(recurse(&semaphore, 4), 5);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+
+        let outer = semaphore.get();
+        let inner = semaphore.try_get().unwrap();
+        assert_eq!(semaphore.held_by_current_thread(), 2);
+        std::thread::scope(|s| {
+            s.spawn(|| assert!(semaphore.try_get().is_err()));
+        });
+
+        // Dropping out of order still keeps the permit until the last guard is gone
+        drop(outer);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+        drop(inner);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+        assert_eq!(semaphore.held_by_current_thread(), 0);
+    }
+
+    #[test]
+    fn test_forgotten_guard_doesnt_let_a_new_semaphore_reenter() {
+        let mut semaphore = ReentrantSemaphore::new(1, 1);
+        core::mem::forget(semaphore.try_get().unwrap());
+
+        // The new semaphore lives at the same address as the one whose guard was forgotten
+        semaphore = ReentrantSemaphore::new(2, 1);
+        assert_eq!(semaphore.held_by_current_thread(), 0);
+        let guard = semaphore.try_get().unwrap();
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+        drop(guard);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_get_at_max_reenters_instead_of_waiting() {
+        let semaphore = std::sync::Arc::new(ReentrantSemaphore::new(7, 1));
+        let (done, finished) = std::sync::mpsc::channel();
+
+        // On its own thread, so a deadlock fails the test instead of hanging it
+        let holder = std::sync::Arc::clone(&semaphore);
+        let thread = std::thread::spawn(move || {
+            let outer = holder.get();
+            assert_eq!(holder.count(Ordering::SeqCst), 1);
+            // A plain semaphore would wait here forever for the permit this thread holds
+            let inner = holder.get();
+            done.send(*outer + *inner).unwrap();
+        });
+
+        let sum = finished.recv_timeout(std::time::Duration::from_secs(5));
+        assert_eq!(sum, Ok(14), "the second get deadlocked");
+        thread.join().unwrap();
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+}
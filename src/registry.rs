@@ -0,0 +1,121 @@
+//! Totals across every registered semaphore, see [`SemaphoreRegistry`]
+
+use crate::{lock_ignoring_poison, Semaphore};
+use core::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, Weak};
+
+/// What the registry needs to read from a semaphore, whatever data it guards
+trait Capacity: Send + Sync {
+    fn count(&self) -> usize;
+    fn available(&self) -> usize;
+}
+
+impl<T: ?Sized + Send + Sync> Capacity for Semaphore<T> {
+    fn count(&self) -> usize {
+        Semaphore::count(self, Ordering::SeqCst)
+    }
+
+    fn available(&self) -> usize {
+        Semaphore::available(self, Ordering::SeqCst)
+    }
+}
+
+/// Reports the permits held and available across every semaphore created with [`Semaphore::new_registered`]
+///
+/// Only weak references are kept, a semaphore leaves the registry once its last `Arc` is dropped.
+/// The totals read each semaphore one after the other, so under contention they are a snapshot
+/// that never existed all at once
+#[derive(Default)]
+pub struct SemaphoreRegistry {
+    semaphores: Mutex<Vec<Weak<dyn Capacity>>>,
+}
+
+impl SemaphoreRegistry {
+    /// Create a registry with no semaphores in it
+    #[must_use]
+    pub const fn new() -> Self {
+        SemaphoreRegistry {
+            semaphores: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Get the number of permits held across every registered semaphore
+    #[must_use]
+    pub fn total_held(&self) -> usize {
+        self.live().iter().fold(0, |total, semaphore| {
+            total.saturating_add(semaphore.count())
+        })
+    }
+
+    /// Get the number of permits that can still be taken across every registered semaphore
+    ///
+    /// Saturates at `usize::MAX` if an unbounded semaphore is registered
+    #[must_use]
+    pub fn total_available(&self) -> usize {
+        self.live().iter().fold(0, |total, semaphore| {
+            total.saturating_add(semaphore.available())
+        })
+    }
+
+    /// Get the number of registered semaphores that are still alive
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.live().len()
+    }
+
+    /// Returns true if no registered semaphore is still alive
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn register(&self, semaphore: Weak<dyn Capacity>) {
+        lock_ignoring_poison(&self.semaphores).push(semaphore);
+    }
+
+    /// Upgrade every semaphore that is still alive, forgetting the ones that were dropped
+    fn live(&self) -> Vec<Arc<dyn Capacity>> {
+        let mut semaphores = lock_ignoring_poison(&self.semaphores);
+        let mut live = Vec::with_capacity(semaphores.len());
+        semaphores.retain(|semaphore| {
+            semaphore
+                .upgrade()
+                .map(|semaphore| live.push(semaphore))
+                .is_some()
+        });
+        live
+    }
+}
+
+impl<T: Send + Sync + 'static> Semaphore<T> {
+    /// Create a new shared semaphore that is counted by `registry`, see [`Semaphore::new`]
+    #[must_use]
+    pub fn new_registered(value: T, max: usize, registry: &SemaphoreRegistry) -> Arc<Self> {
+        let semaphore = Arc::new(Semaphore::new(value, max));
+        registry.register(Arc::<Self>::downgrade(&semaphore));
+        semaphore
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_totals_live_semaphores() {
+        let registry = SemaphoreRegistry::new();
+        let first = Semaphore::new_registered("first", 2, &registry);
+        let second = Semaphore::new_registered("second", 3, &registry);
+
+        let _guard = first.try_get().unwrap();
+        let many = second.try_get_many(2).unwrap();
+        assert_eq!(registry.total_held(), 3);
+        assert_eq!(registry.total_available(), 2);
+
+        drop(many);
+        drop(second);
+        assert_eq!(registry.len(), 1);
+        assert_eq!(registry.total_held(), 1);
+        assert_eq!(registry.total_available(), 1);
+    }
+}
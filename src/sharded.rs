@@ -0,0 +1,226 @@
+//! A semaphore with its count split across shards to reduce contention, see [`ShardedSemaphore`]
+
+use crate::SemaphoreError;
+use core::{
+    cell::Cell,
+    ops::Deref,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Hands every thread a shard to start from, in the order the threads first acquire
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+std::thread_local! {
+    /// The shard the current thread tries first, assigned on its first acquire
+    static PREFERRED: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// One part of the permits of a [`ShardedSemaphore`], on a cache line of its own
+#[repr(align(128))]
+struct Shard {
+    count: AtomicUsize,
+    max: usize,
+}
+
+/// Allows up to `max` references to the data, with the permits split across shards that each have their own count
+///
+/// Every thread starts with its own shard and only moves on to the others when that one is full,
+/// so threads on different cores mostly write different cache lines instead of one shared count.
+///
+/// The tradeoff is that the semaphore is only approximately a single limit: an acquire can fail
+/// while a permit is free if it was released in a shard the acquire had already checked,
+/// and there is no ordering at all between threads. It never hands out more than `max` permits
+pub struct ShardedSemaphore<T: ?Sized> {
+    shards: Box<[Shard]>,
+    data: T,
+}
+
+impl<T: ?Sized> ShardedSemaphore<T> {
+    /// Attempt to get the value in the semaphore, trying the current thread's shard before the others
+    ///
+    /// This function will never block
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if every shard was at its max when it was checked
+    pub fn try_get(&self) -> Result<ShardedGuard<'_, T>, SemaphoreError> {
+        let preferred = self.preferred_shard();
+        (0..self.shards.len())
+            .map(|offset| (preferred + offset) % self.shards.len())
+            .find(|&shard| {
+                let shard_ref = &self.shards[shard];
+                shard_ref
+                    .count
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                        (count < shard_ref.max).then_some(count + 1)
+                    })
+                    .is_ok()
+            })
+            .map(|shard| ShardedGuard {
+                semaphore: self,
+                shard,
+            })
+            .ok_or(SemaphoreError::AtMaxCount)
+    }
+
+    /// Get the number of permits held across all shards
+    ///
+    /// The shards are read one at a time, so the sum is a snapshot that may never have been exact
+    #[must_use]
+    pub fn count(&self, ordering: Ordering) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.count.load(ordering))
+            .sum()
+    }
+
+    /// Get how many more references can be taken, summed over the shards like [`ShardedSemaphore::count`]
+    #[must_use]
+    pub fn available(&self, ordering: Ordering) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.max.saturating_sub(shard.count.load(ordering)))
+            .sum()
+    }
+
+    /// Get the maximum number of references across all shards
+    #[must_use]
+    pub fn max(&self) -> usize {
+        self.shards.iter().map(|shard| shard.max).sum()
+    }
+
+    /// Get the number of shards the permits are split across
+    #[must_use]
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Get a mutable reference to the data in the semaphore
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    fn preferred_shard(&self) -> usize {
+        let preferred = PREFERRED.get().unwrap_or_else(|| {
+            let shard = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+            PREFERRED.set(Some(shard));
+            shard
+        });
+        preferred % self.shards.len()
+    }
+}
+
+impl<T> ShardedSemaphore<T> {
+    /// Create a new semaphore with one shard per unit of [`std::thread::available_parallelism`]
+    ///
+    /// There are never more shards than permits, so every shard has at least one permit
+    pub fn new(value: T, max: usize) -> Self {
+        let parallelism = std::thread::available_parallelism().map_or(1, usize::from);
+        ShardedSemaphore::with_shards(value, max, parallelism)
+    }
+
+    /// Create a new semaphore with `max` split as evenly as possible across `shards` shards
+    ///
+    /// There are never more shards than permits, so every shard has at least one permit
+    /// # Panics
+    /// This function will panic if `shards` == 0
+    pub fn with_shards(value: T, max: usize, shards: usize) -> Self {
+        assert_ne!(
+            shards, 0,
+            "Calling 'ShardedSemaphore::with_shards' with 0 shards leaves nowhere for the permits"
+        );
+        let shards = shards.min(max).max(1);
+        ShardedSemaphore {
+            shards: (0..shards)
+                .map(|shard| Shard {
+                    count: AtomicUsize::new(0),
+                    // The first `max % shards` shards take one of the leftover permits each
+                    max: max / shards + usize::from(shard < max % shards),
+                })
+                .collect(),
+            data: value,
+        }
+    }
+
+    /// Move the value out of the semaphore
+    pub fn into_inner(self) -> T {
+        self.data
+    }
+}
+
+/// A reference to the data in a [`ShardedSemaphore`]
+/// Releases the permit back to the shard it was taken from when it is dropped
+#[must_use = "if unused, the guard will immediatly unlock"]
+pub struct ShardedGuard<'guard, T: ?Sized> {
+    semaphore: &'guard ShardedSemaphore<T>,
+    shard: usize,
+}
+
+impl<T: ?Sized> ShardedGuard<'_, T> {
+    /// Get the index of the shard the permit was taken from
+    #[must_use]
+    pub fn shard(&self) -> usize {
+        self.shard
+    }
+}
+
+impl<T: ?Sized> Deref for ShardedGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.semaphore.data
+    }
+}
+
+impl<T: ?Sized> Drop for ShardedGuard<'_, T> {
+    fn drop(&mut self) {
+        self.semaphore.shards[self.shard]
+            .count
+            .fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_full_shard_steals_from_the_others() {
+        let semaphore = ShardedSemaphore::with_shards((), 5, 8);
+        assert_eq!((semaphore.shards(), semaphore.max()), (5, 5));
+
+        let semaphore = ShardedSemaphore::with_shards((), 5, 2);
+        assert_eq!(semaphore.max(), 5);
+        let guards: Vec<_> = (0..5).map(|_| semaphore.try_get().unwrap()).collect();
+        // Both shards were used even though the thread prefers one of them
+        assert!(guards.iter().any(|guard| guard.shard() == 0));
+        assert!(guards.iter().any(|guard| guard.shard() == 1));
+        assert_eq!(semaphore.available(Ordering::SeqCst), 0);
+        assert!(semaphore.try_get().is_err());
+
+        drop(guards);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+        assert_eq!(semaphore.available(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn test_contended_acquires_never_exceed_max() {
+        let semaphore = ShardedSemaphore::with_shards(AtomicUsize::new(0), 6, 3);
+
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        if let Ok(guard) = semaphore.try_get() {
+                            let held = guard.fetch_add(1, Ordering::SeqCst) + 1;
+                            assert!(held <= 6);
+                            guard.fetch_sub(1, Ordering::SeqCst);
+                        }
+                    }
+                });
+            }
+        });
+
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+}
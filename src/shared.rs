@@ -0,0 +1,229 @@
+//! A cloneable handle to a semaphore and its data, see [`SharedSemaphore`]
+
+use crate::{OwnedSemaphoreGuard, Semaphore, SemaphoreError, SemaphoreGuard};
+use core::sync::atomic::Ordering;
+use std::sync::{Arc, Weak};
+
+/// A handle to a semaphore and its data that can be cloned to share both
+///
+/// Every clone points at the same count and the same value, so guards taken through any of them
+/// count against one `max`. This saves wrapping a [`Semaphore`] in an [`Arc`] by hand,
+/// use [`Semaphore::try_get_owned`] when it is the guard that has to outlive a borrow
+pub struct SharedSemaphore<T: ?Sized> {
+    semaphore: Arc<Semaphore<T>>,
+}
+
+impl<T: ?Sized> SharedSemaphore<T> {
+    /// Attempt to get the value in the semaphore
+    ///
+    /// This function will never block
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if the current count is >= the maximum count,
+    /// or [`SemaphoreError::Poisoned`] if the semaphore is poisoned
+    pub fn try_get(&self) -> Result<SemaphoreGuard<'_, T>, SemaphoreError> {
+        self.semaphore.try_get()
+    }
+
+    /// Attempt to get a `'static` guard that keeps the semaphore alive, see [`Semaphore::try_get_owned`]
+    ///
+    /// The guard holds its own clone of the handle, so it can be moved into a spawned thread or task.
+    /// It is only `Send` when `T` is `Send + Sync`, as the data stays shared with the other handles.
+    /// This function will never block
+    ///
+    /// ```compile_fail
+    /// let semaphore = semaphorus::shared::SharedSemaphore::new(core::cell::Cell::new(0_u64), 2);
+    /// let guard = semaphore.try_get_owned().unwrap();
+    /// std::thread::spawn(move || guard.set(1));
+    /// ```
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if the current count is >= the maximum count,
+    /// or [`SemaphoreError::Poisoned`] if the semaphore is poisoned
+    pub fn try_get_owned(&self) -> Result<OwnedSemaphoreGuard<T>, SemaphoreError> {
+        self.semaphore.try_get_owned()
+    }
+
+    /// Block until a permit is available, like [`Semaphore::get`]
+    /// # Panics
+    /// This function will panic if `max` == 0, if the semaphore is poisoned,
+    /// or if it has to wait with `no_block` or after [`shutdown_all`][`crate::shutdown_all`]
+    pub fn get(&self) -> SemaphoreGuard<'_, T> {
+        self.semaphore.get()
+    }
+
+    /// Get the current number of references, shared by every clone
+    #[must_use]
+    pub fn count(&self, ordering: Ordering) -> usize {
+        self.semaphore.count(ordering)
+    }
+
+    /// Get how many more references can be taken right now
+    #[must_use]
+    pub fn available(&self, ordering: Ordering) -> usize {
+        self.semaphore.available(ordering)
+    }
+
+    /// Get the maximum number of references
+    #[must_use]
+    pub fn max(&self) -> usize {
+        self.semaphore.max()
+    }
+
+    /// Returns true if both handles share the same count and value
+    #[must_use]
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.semaphore, &other.semaphore)
+    }
+
+    /// Get a handle that doesn't keep the semaphore alive, see [`WeakSemaphore::upgrade`]
+    #[must_use]
+    pub fn downgrade(&self) -> WeakSemaphore<T> {
+        WeakSemaphore {
+            semaphore: Arc::downgrade(&self.semaphore),
+        }
+    }
+}
+
+impl<T> SharedSemaphore<T> {
+    /// Create a new semaphore with 0 counted references
+    pub fn new(value: T, max: usize) -> Self {
+        SharedSemaphore {
+            semaphore: Arc::new(Semaphore::new(value, max)),
+        }
+    }
+}
+
+impl<T: ?Sized> Clone for SharedSemaphore<T> {
+    fn clone(&self) -> Self {
+        SharedSemaphore {
+            semaphore: Arc::clone(&self.semaphore),
+        }
+    }
+}
+
+/// Handles are equal when they share the same semaphore, like [`SharedSemaphore::ptr_eq`], the data isn't compared
+impl<T: ?Sized> PartialEq for SharedSemaphore<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other)
+    }
+}
+
+impl<T: ?Sized> Eq for SharedSemaphore<T> {}
+
+/// Hashes the address of the shared semaphore, so it agrees with the identity based [`PartialEq`]
+impl<T: ?Sized> core::hash::Hash for SharedSemaphore<T> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.semaphore).cast::<()>().hash(state);
+    }
+}
+
+impl<T: ?Sized> core::fmt::Debug for SharedSemaphore<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SharedSemaphore")
+            .field("count", &self.count(Ordering::Relaxed))
+            .field("max", &self.max())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A handle to a [`SharedSemaphore`] that doesn't keep it alive, made by [`SharedSemaphore::downgrade`]
+///
+/// Meant for registries that outlive what they point at. Guards borrow a [`SharedSemaphore`],
+/// so a guard keeps the handle it came from and with it the semaphore alive
+pub struct WeakSemaphore<T: ?Sized> {
+    semaphore: Weak<Semaphore<T>>,
+}
+
+impl<T: ?Sized> WeakSemaphore<T> {
+    /// Get a [`SharedSemaphore`] back, returns `None` once every strong handle was dropped
+    #[must_use]
+    pub fn upgrade(&self) -> Option<SharedSemaphore<T>> {
+        Some(SharedSemaphore {
+            semaphore: self.semaphore.upgrade()?,
+        })
+    }
+}
+
+impl<T: ?Sized> Clone for WeakSemaphore<T> {
+    fn clone(&self) -> Self {
+        WeakSemaphore {
+            semaphore: Weak::clone(&self.semaphore),
+        }
+    }
+}
+
+impl<T: ?Sized> core::fmt::Debug for WeakSemaphore<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("WeakSemaphore").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_one_count() {
+        let semaphore = SharedSemaphore::new(5, 2);
+        let clone = semaphore.clone();
+        assert!(semaphore.ptr_eq(&clone));
+
+        let first = semaphore.try_get().unwrap();
+        let second = clone.try_get().unwrap();
+        assert_eq!(*first + *second, 10);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 2);
+        assert_eq!(clone.try_get().unwrap_err(), SemaphoreError::AtMaxCount);
+
+        drop((first, second));
+        let other = semaphore.clone();
+        std::thread::spawn(move || drop(other.try_get().unwrap()))
+            .join()
+            .unwrap();
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_weak_handle_upgrades_while_a_strong_one_exists() {
+        let semaphore = SharedSemaphore::new("plugin", 1);
+        let clone = semaphore.clone();
+        let weak = semaphore.downgrade();
+
+        let upgraded = weak.upgrade().unwrap();
+        assert!(upgraded.ptr_eq(&semaphore));
+        let guard = upgraded.try_get().unwrap();
+        assert_eq!(*guard, "plugin");
+        assert!(clone.try_get().is_err());
+        drop(guard);
+        drop((upgraded, semaphore));
+
+        assert!(weak.upgrade().is_some());
+        drop(clone);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // The count is interior mutable, but the hash only uses the address
+    fn test_handles_hash_by_identity() {
+        let semaphore = SharedSemaphore::new(1, 1);
+        let twin = SharedSemaphore::new(1, 1);
+
+        let handles: std::collections::HashSet<_> =
+            [semaphore.clone(), twin.clone(), semaphore.clone(), twin]
+                .into_iter()
+                .collect();
+        assert_eq!(handles.len(), 2);
+        assert!(handles.contains(&semaphore));
+        assert_ne!(semaphore, SharedSemaphore::new(1, 1));
+    }
+
+    #[test]
+    fn test_owned_guard_releases_on_another_thread() {
+        let semaphore = SharedSemaphore::new(String::from("task"), 1);
+
+        let owned = semaphore.try_get_owned().unwrap();
+        assert!(semaphore.try_get().is_err());
+        let len = std::thread::spawn(move || owned.len()).join().unwrap();
+
+        assert_eq!(len, 4);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,385 @@
+//! Bounds the concurrency of `futures` streams with a [`Semaphore`]
+//!
+//! `StreamExt::buffered(n)` fixes the concurrency at `n`. Passing the futures through
+//! [`gate_stream`] first makes every future wait for a permit before it starts, so the
+//! concurrency can instead be changed at runtime through the semaphore. [`bounded_join`] does the same
+//! for an iterator of work without needing `StreamExt`, and [`permit_stream`] yields the permits themselves
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use futures::{executor::block_on, stream, StreamExt, TryStreamExt};
+//! use semaphorus::{stream::gate_stream, Semaphore};
+//!
+//! let semaphore = Arc::new(Semaphore::new((), 2));
+//! let doubled: Vec<_> = block_on(
+//!     gate_stream(semaphore, stream::iter(0..4).map(|i| async move { i * 2 }))
+//!         .buffered(usize::MAX)
+//!         .try_collect(),
+//! )
+//! .unwrap();
+//! assert_eq!(doubled, [0, 2, 4, 6]);
+//! ```
+
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+use std::sync::Arc;
+
+use futures_core::Stream;
+
+use crate::{AcquireOwned, OwnedSemaphoreGuard, Semaphore, SemaphoreError};
+
+/// Make every future yielded by `stream` hold a permit from `semaphore` while it runs
+///
+/// The permit is acquired the first time the future is polled and released as soon as it completes.
+/// If the permit can't be acquired, like when the semaphore is closed, the future resolves to the error
+/// without running
+pub fn gate_stream<S>(semaphore: Arc<Semaphore<()>>, stream: S) -> GateStream<S>
+where
+    S: Stream,
+    S::Item: Future,
+{
+    GateStream { semaphore, stream }
+}
+
+/// The stream returned by [`gate_stream`]
+#[must_use = "streams do nothing unless polled"]
+pub struct GateStream<S> {
+    semaphore: Arc<Semaphore<()>>,
+    stream: S,
+}
+
+impl<S> Stream for GateStream<S>
+where
+    S: Stream,
+    S::Item: Future,
+{
+    type Item = Gated<S::Item>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // SAFETY: `stream` is structurally pinned and never moved out of `self`
+        let this = unsafe { self.get_unchecked_mut() };
+        // SAFETY: `this` came from a pinned `self`, so `stream` stays at this address until it is dropped
+        let stream = unsafe { Pin::new_unchecked(&mut this.stream) };
+
+        Poll::Ready(ready!(stream.poll_next(cx)).map(|future| Gated {
+            acquire: Some(this.semaphore.acquire_owned()),
+            permit: None,
+            future,
+        }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+/// A future that waits for a permit before it starts running, yielded by [`GateStream`]
+#[must_use = "futures do nothing unless polled"]
+pub struct Gated<F> {
+    acquire: Option<AcquireOwned<()>>,
+    permit: Option<OwnedSemaphoreGuard<()>>,
+    future: F,
+}
+
+impl<F: Future> Future for Gated<F> {
+    type Output = Result<F::Output, SemaphoreError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: `future` is structurally pinned and never moved out of `self`,
+        // the other fields are `Unpin`
+        let this = unsafe { self.get_unchecked_mut() };
+
+        if let Some(acquire) = &mut this.acquire {
+            let result = ready!(Pin::new(acquire).poll(cx));
+            this.acquire = None;
+            this.permit = Some(result?);
+        }
+
+        // SAFETY: `this` came from a pinned `self` and `future` is never moved out of it,
+        // so it stays at this address until the `Gated` is dropped
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        let output = ready!(future.poll(cx));
+        this.permit = None;
+        Poll::Ready(Ok(output))
+    }
+}
+
+/// Yield a guard from `semaphore` every time a permit is available, until acquiring fails
+///
+/// Each guard is acquired with [`Semaphore::acquire_owned`], so zipping the stream with the work
+/// and running it through `StreamExt::buffer_unordered` bounds the concurrency to the free permits.
+/// Dropping the stream while it waits gives up its place without taking a permit. The stream ends
+/// once a permit can't be acquired, like when the semaphore is closed
+pub fn permit_stream<T: ?Sized>(semaphore: Arc<Semaphore<T>>) -> PermitStream<T> {
+    PermitStream {
+        semaphore,
+        acquire: None,
+    }
+}
+
+/// The stream returned by [`permit_stream`]
+#[must_use = "streams do nothing unless polled"]
+pub struct PermitStream<T: ?Sized> {
+    semaphore: Arc<Semaphore<T>>,
+    /// The wait for the next permit, kept between polls so its place in the queue isn't lost
+    acquire: Option<AcquireOwned<T>>,
+}
+
+impl<T: ?Sized> Stream for PermitStream<T> {
+    type Item = OwnedSemaphoreGuard<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let acquire = this
+            .acquire
+            .get_or_insert_with(|| this.semaphore.acquire_owned());
+        let result = ready!(Pin::new(acquire).poll(cx));
+        this.acquire = None;
+        Poll::Ready(result.ok())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::MAX, None)
+    }
+}
+
+/// Run `f` on every item of `items`, with at most as many futures running as `semaphore` has permits
+///
+/// A permit is taken before an item is turned into a future, and released as soon as the future completes,
+/// so items are only pulled from the iterator when there's room to run them. Outputs are yielded in the order
+/// the futures complete. If a permit can't be acquired, like when the semaphore is closed, no more items
+/// are started and the stream ends once the running futures complete
+pub fn bounded_join<I, F, Fut>(
+    semaphore: Arc<Semaphore<()>>,
+    items: I,
+    f: F,
+) -> BoundedJoin<I::IntoIter, F, Fut>
+where
+    I: IntoIterator,
+    F: FnMut(I::Item) -> Fut,
+    Fut: Future,
+{
+    BoundedJoin {
+        semaphore,
+        items: Some(items.into_iter()),
+        f,
+        acquire: None,
+        running: Vec::new(),
+    }
+}
+
+/// The stream returned by [`bounded_join`]
+#[must_use = "streams do nothing unless polled"]
+pub struct BoundedJoin<I, F, Fut> {
+    semaphore: Arc<Semaphore<()>>,
+    /// `None` once the iterator ran out
+    items: Option<I>,
+    f: F,
+    /// Waiting for the permit to start the next item
+    acquire: Option<AcquireOwned<()>>,
+    running: Vec<(OwnedSemaphoreGuard<()>, Pin<Box<Fut>>)>,
+}
+
+// The futures are boxed, nothing in the stream is structurally pinned
+impl<I, F, Fut> Unpin for BoundedJoin<I, F, Fut> {}
+
+impl<I, F, Fut> BoundedJoin<I, F, Fut>
+where
+    I: Iterator,
+    F: FnMut(I::Item) -> Fut,
+{
+    /// Start items for as long as permits are available, dropping the rest of them if acquiring fails
+    fn start_ready(&mut self, cx: &mut Context<'_>) {
+        while self.items.is_some() {
+            let permit = match &mut self.acquire {
+                Some(acquire) => match Pin::new(acquire).poll(cx) {
+                    Poll::Ready(result) => result,
+                    Poll::Pending => return,
+                },
+                None => match self.semaphore.try_get_owned() {
+                    Err(SemaphoreError::AtMaxCount) => {
+                        self.acquire = Some(self.semaphore.acquire_owned());
+                        continue;
+                    }
+                    result => result,
+                },
+            };
+            self.acquire = None;
+            let Ok(permit) = permit else {
+                self.items = None;
+                return;
+            };
+
+            match self.items.as_mut().and_then(Iterator::next) {
+                Some(item) => self.running.push((permit, Box::pin((self.f)(item)))),
+                None => self.items = None,
+            }
+        }
+    }
+}
+
+impl<I, F, Fut> Stream for BoundedJoin<I, F, Fut>
+where
+    I: Iterator,
+    F: FnMut(I::Item) -> Fut,
+    Fut: Future,
+{
+    type Item = Fut::Output;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        this.start_ready(cx);
+
+        for index in 0..this.running.len() {
+            if let Poll::Ready(output) = this.running[index].1.as_mut().poll(cx) {
+                // Dropping the permit lets the next item start on the following poll
+                drop(this.running.swap_remove(index));
+                return Poll::Ready(Some(output));
+            }
+        }
+
+        if this.items.is_none() && this.running.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use futures::{executor::block_on, stream, StreamExt};
+
+    /// Returns `Pending` once so other futures get a chance to run
+    async fn yield_now() {
+        let mut yielded = false;
+        futures::future::poll_fn(|cx| {
+            if yielded {
+                Poll::Ready(())
+            } else {
+                yielded = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        })
+        .await;
+    }
+
+    #[test]
+    fn test_gated_futures_respect_max() {
+        let semaphore = Arc::new(Semaphore::new((), 2));
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+
+        let futures = stream::iter(0..16).map(|i| {
+            let (running, peak) = (&running, &peak);
+            async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                yield_now().await;
+                yield_now().await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                i
+            }
+        });
+
+        let mut output: Vec<_> = block_on(
+            gate_stream(Arc::clone(&semaphore), futures)
+                .buffer_unordered(16)
+                .map(Result::unwrap)
+                .collect(),
+        );
+        output.sort_unstable();
+
+        assert_eq!(output, (0..16).collect::<Vec<_>>());
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_permit_stream_pends_at_max() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let semaphore = Arc::new(Semaphore::new((), 2));
+        let mut permits = permit_stream(Arc::clone(&semaphore));
+
+        runtime.block_on(async {
+            let first = permits.next().await.unwrap();
+            let _second = permits.next().await.unwrap();
+            assert!(futures::poll!(permits.next()).is_pending());
+
+            drop(first);
+            let _third = permits.next().await.unwrap();
+            assert!(futures::poll!(permits.next()).is_pending());
+            assert_eq!(semaphore.waiter_count(), 1);
+        });
+
+        // Dropping the waiting stream leaves no waiter and no permit behind
+        drop(permits);
+        assert_eq!(semaphore.waiter_count(), 0);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_closing_ends_the_helpers() {
+        let semaphore = Arc::new(Semaphore::new((), 1));
+        let held = semaphore.try_get_owned().unwrap();
+        let mut permits = permit_stream(Arc::clone(&semaphore));
+        let mut gated = gate_stream(
+            Arc::clone(&semaphore),
+            stream::iter([futures::future::ready(())]),
+        );
+        let mut joined = bounded_join(Arc::clone(&semaphore), 0..4, |i| async move { i });
+
+        block_on(async {
+            assert!(futures::poll!(permits.next()).is_pending());
+            let mut gated = gated.next().await.unwrap();
+            assert!(futures::poll!(&mut gated).is_pending());
+            assert!(futures::poll!(joined.next()).is_pending());
+
+            semaphore.close();
+            assert!(permits.next().await.is_none());
+            assert_eq!(gated.await, Err(SemaphoreError::Closed));
+            assert!(joined.next().await.is_none());
+        });
+
+        assert_eq!(semaphore.waiter_count(), 0);
+        drop(held);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_bounded_join_respects_max() {
+        let semaphore = Arc::new(Semaphore::new((), 3));
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+
+        let mut output: Vec<_> = block_on(
+            bounded_join(Arc::clone(&semaphore), 0..16, |i| {
+                let (running, peak) = (&running, &peak);
+                async move {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    for _ in 0..i % 3 {
+                        yield_now().await;
+                    }
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    i * 2
+                }
+            })
+            .collect(),
+        );
+        output.sort_unstable();
+
+        assert_eq!(output, (0..16).map(|i| i * 2).collect::<Vec<_>>());
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,467 @@
+//! Storage for the wakers of tasks waiting on a [`Semaphore`][`crate::raw::Semaphore`]
+//!
+//! With `async` the wakers are kept in a `std` FIFO queue, with only `embassy` they are kept in
+//! embassy's `WakerRegistration`. Both expose the same interface to `raw::Semaphore::poll_acquire`.
+//! With `std` the threads blocked with [`WaitStrategy::Park`][`crate::WaitStrategy::Park`] are kept too,
+//! along with the callbacks given to [`Semaphore::on_available`][`crate::raw::Semaphore::on_available`]
+//!
+//! Every `std` queue mirrors its length in a `len` atomic next to its mutex, so releases and acquires
+//! can tell that nobody is waiting without taking the lock
+
+#[cfg(feature = "async")]
+pub(crate) use self::queue::{WaitEntry, WakerQueue};
+
+#[cfg(feature = "std")]
+pub(crate) use self::park::ParkedThreads;
+
+#[cfg(feature = "std")]
+pub(crate) use self::callbacks::Callbacks;
+
+#[cfg(feature = "std")]
+pub(crate) use self::fair::FairQueue;
+
+#[cfg(all(feature = "embassy", not(feature = "async")))]
+pub(crate) use self::registration::{WaitEntry, WakerQueue};
+
+/// The allocator given to [`Semaphore::new_in`][`crate::raw::Semaphore::new_in`] for the waiter queues
+#[cfg(feature = "allocator_api")]
+pub(crate) type QueueAlloc = &'static (dyn core::alloc::Allocator + Sync);
+
+/// A queue of waiting threads or tasks, kept in the semaphore's allocator with `allocator_api`
+#[cfg(feature = "allocator_api")]
+type Queue<T> = std::collections::VecDeque<T, QueueAlloc>;
+#[cfg(all(feature = "std", not(feature = "allocator_api")))]
+type Queue<T> = std::collections::VecDeque<T>;
+
+/// Create an empty [`Queue`] in the global allocator
+#[cfg(feature = "allocator_api")]
+const fn new_queue<T>() -> Queue<T> {
+    Queue::new_in(&std::alloc::Global)
+}
+#[cfg(all(feature = "std", not(feature = "allocator_api")))]
+const fn new_queue<T>() -> Queue<T> {
+    Queue::new()
+}
+
+#[cfg(feature = "async")]
+mod queue {
+    use core::{
+        sync::atomic::{AtomicUsize, Ordering},
+        task::Waker,
+    };
+    use std::sync::Mutex;
+
+    use super::{new_queue, Queue};
+    use crate::{lock_ignoring_poison, raw::WakeOrder};
+
+    /// A queue of tasks waiting for a permit, woken in the order they registered
+    pub(crate) struct WakerQueue {
+        /// The length of `wakers`, see the module docs
+        len: AtomicUsize,
+        inner: Mutex<Inner>,
+    }
+
+    struct Inner {
+        next_id: usize,
+        /// The id of each waiting task, how many permits it needs and its waker
+        wakers: Queue<(usize, usize, Waker)>,
+    }
+
+    /// A task's place in a [`WakerQueue`]
+    #[derive(Default)]
+    pub(crate) struct WaitEntry {
+        id: Option<usize>,
+    }
+
+    impl WakerQueue {
+        pub(crate) const fn new() -> Self {
+            WakerQueue {
+                len: AtomicUsize::new(0),
+                inner: Mutex::new(Inner {
+                    next_id: 0,
+                    wakers: new_queue(),
+                }),
+            }
+        }
+
+        /// Create an empty queue that allocates from `alloc`
+        #[cfg(feature = "allocator_api")]
+        pub(crate) const fn new_in(alloc: super::QueueAlloc) -> Self {
+            WakerQueue {
+                len: AtomicUsize::new(0),
+                inner: Mutex::new(Inner {
+                    next_id: 0,
+                    wakers: Queue::new_in(alloc),
+                }),
+            }
+        }
+
+        /// Get the number of queued tasks without taking the lock
+        pub(crate) fn len(&self) -> usize {
+            self.len.load(Ordering::SeqCst)
+        }
+
+        /// Add `entry` waiting for `permits` to the back of the queue, or refresh its waker if it is already queued
+        pub(crate) fn register(&self, entry: &mut WaitEntry, waker: &Waker, permits: usize) {
+            let mut inner = lock_ignoring_poison(&self.inner);
+
+            if let Some(id) = entry.id {
+                if let Some((_, _, queued)) =
+                    inner.wakers.iter_mut().find(|(queued, _, _)| *queued == id)
+                {
+                    if !queued.will_wake(waker) {
+                        queued.clone_from(waker);
+                    }
+                    return;
+                }
+            }
+
+            let id = inner.next_id;
+            inner.next_id = inner.next_id.wrapping_add(1);
+            inner.wakers.push_back((id, permits, waker.clone()));
+            self.len.store(inner.wakers.len(), Ordering::SeqCst);
+            entry.id = Some(id);
+        }
+
+        /// Remove `entry` from the queue
+        ///
+        /// Returns `true` if the entry had been woken but not yet removed,
+        /// meaning the wakeup it received still has to be handed to someone else
+        pub(crate) fn remove(&self, entry: &mut WaitEntry) -> bool {
+            let Some(id) = entry.id.take() else {
+                return false;
+            };
+
+            let mut inner = lock_ignoring_poison(&self.inner);
+            match inner.wakers.iter().position(|(queued, _, _)| *queued == id) {
+                Some(index) => {
+                    inner.wakers.remove(index);
+                    self.len.store(inner.wakers.len(), Ordering::SeqCst);
+                    false
+                }
+                None => true,
+            }
+        }
+
+        /// Wake the tasks at the front of the queue whose permits fit in `available`,
+        /// the front being the newest task with [`WakeOrder::Lifo`]
+        ///
+        /// Stops at the first task that needs more than what is left, so a large request
+        /// isn't woken just to find it still can't proceed, and isn't overtaken by smaller ones
+        pub(crate) fn wake_available(&self, mut available: usize, order: WakeOrder) {
+            if available == 0 || self.len.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+
+            let woken = {
+                let mut inner = lock_ignoring_poison(&self.inner);
+                #[cfg(feature = "allocator_api")]
+                let mut woken = Vec::new_in(*inner.wakers.allocator());
+                #[cfg(not(feature = "allocator_api"))]
+                let mut woken = Vec::new();
+                loop {
+                    let next = match order {
+                        WakeOrder::Lifo => inner.wakers.back(),
+                        WakeOrder::Fifo => inner.wakers.front(),
+                    };
+                    match next {
+                        Some((_, permits, _)) if *permits <= available => available -= *permits,
+                        _ => break,
+                    }
+                    let waker = match order {
+                        WakeOrder::Lifo => inner.wakers.pop_back(),
+                        WakeOrder::Fifo => inner.wakers.pop_front(),
+                    };
+                    woken.extend(waker.map(|(_, _, waker)| waker));
+                }
+                self.len.store(inner.wakers.len(), Ordering::SeqCst);
+                woken
+            };
+
+            for waker in woken {
+                waker.wake();
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "embassy", not(feature = "async")))]
+mod registration {
+    use core::{cell::RefCell, task::Waker};
+
+    use crate::raw::WakeOrder;
+    use embassy_sync::{
+        blocking_mutex::{raw::CriticalSectionRawMutex, Mutex},
+        waitqueue::WakerRegistration,
+    };
+
+    /// A single registered waker, shared by every waiting task
+    ///
+    /// When several tasks wait at once they keep replacing (and waking) each other's waker,
+    /// which costs some polling but needs no allocation
+    pub(crate) struct WakerQueue {
+        waker: Mutex<CriticalSectionRawMutex, RefCell<WakerRegistration>>,
+    }
+
+    /// Whether a task has registered its waker with a [`WakerQueue`]
+    #[derive(Default)]
+    pub(crate) struct WaitEntry {
+        registered: bool,
+    }
+
+    impl WakerQueue {
+        pub(crate) const fn new() -> Self {
+            WakerQueue {
+                waker: Mutex::new(RefCell::new(WakerRegistration::new())),
+            }
+        }
+
+        /// Register the task's waker, the number of permits it needs isn't tracked
+        pub(crate) fn register(&self, entry: &mut WaitEntry, waker: &Waker, _permits: usize) {
+            self.waker
+                .lock(|registration| registration.borrow_mut().register(waker));
+            entry.registered = true;
+        }
+
+        /// Returns `true` if the task had registered, since there is no way to tell whether
+        /// the registration was already woken the caller has to assume it was
+        #[allow(clippy::unused_self)] // Matches the interface of the std queue
+        pub(crate) fn remove(&self, entry: &mut WaitEntry) -> bool {
+            core::mem::take(&mut entry.registered)
+        }
+
+        /// With a single waker to wake, the `order` can't matter
+        pub(crate) fn wake_available(&self, available: usize, _order: WakeOrder) {
+            if available > 0 {
+                self.waker
+                    .lock(|registration| registration.borrow_mut().wake());
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+mod park {
+    use core::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+    use std::{
+        sync::Mutex,
+        thread::{self, Thread},
+    };
+
+    use super::{new_queue, Queue};
+    use crate::{lock_ignoring_poison, raw::WakeOrder};
+
+    /// Threads parked until a permit is released, unparked in the order they parked or the reverse
+    pub(crate) struct ParkedThreads {
+        /// The length of `threads`, see the module docs
+        len: AtomicUsize,
+        threads: Mutex<Queue<Thread>>,
+    }
+
+    impl ParkedThreads {
+        pub(crate) const fn new() -> Self {
+            ParkedThreads {
+                len: AtomicUsize::new(0),
+                threads: Mutex::new(new_queue()),
+            }
+        }
+
+        /// Create an empty queue that allocates from `alloc`
+        #[cfg(feature = "allocator_api")]
+        pub(crate) const fn new_in(alloc: super::QueueAlloc) -> Self {
+            ParkedThreads {
+                len: AtomicUsize::new(0),
+                threads: Mutex::new(Queue::new_in(alloc)),
+            }
+        }
+
+        /// Get the number of parked threads without taking the lock
+        pub(crate) fn len(&self) -> usize {
+            self.len.load(Ordering::SeqCst)
+        }
+
+        /// Park the current thread until it is unparked or `timeout` passes
+        ///
+        /// The thread is registered before `ready` is checked, so a permit released in between
+        /// still unparks it. Spurious wakeups are possible, the caller has to check the count again
+        pub(crate) fn park_timeout(&self, timeout: Duration, ready: impl FnOnce() -> bool) {
+            let current = thread::current();
+            let id = current.id();
+            {
+                let mut threads = lock_ignoring_poison(&self.threads);
+                threads.push_back(current);
+                self.len.store(threads.len(), Ordering::SeqCst);
+            }
+
+            if !ready() {
+                thread::park_timeout(timeout);
+            }
+
+            let mut threads = lock_ignoring_poison(&self.threads);
+            if let Some(index) = threads.iter().position(|parked| parked.id() == id) {
+                threads.remove(index);
+                self.len.store(threads.len(), Ordering::SeqCst);
+            }
+        }
+
+        /// Unpark the thread that has been parked the longest, or the most recently parked one with [`WakeOrder::Lifo`]
+        pub(crate) fn unpark_one(&self, order: WakeOrder) {
+            if self.len.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+
+            let thread = {
+                let mut threads = lock_ignoring_poison(&self.threads);
+                let thread = match order {
+                    WakeOrder::Lifo => threads.pop_back(),
+                    WakeOrder::Fifo => threads.pop_front(),
+                };
+                self.len.store(threads.len(), Ordering::SeqCst);
+                thread
+            };
+
+            if let Some(thread) = thread {
+                thread.unpark();
+            }
+        }
+
+        /// Unpark up to `count` threads, one at a time with [`ParkedThreads::unpark_one`]
+        pub(crate) fn unpark_many(&self, count: usize, order: WakeOrder) {
+            for _ in 0..count.min(self.len.load(Ordering::SeqCst)) {
+                self.unpark_one(order);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+mod callbacks {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::{boxed::Box, collections::VecDeque, sync::Mutex};
+
+    use crate::lock_ignoring_poison;
+
+    type Callback = Box<dyn FnOnce() + Send>;
+
+    /// Callbacks waiting for a permit to be released, run in the order they were registered
+    pub(crate) struct Callbacks {
+        /// The length of `callbacks`, see the module docs
+        len: AtomicUsize,
+        callbacks: Mutex<VecDeque<Callback>>,
+    }
+
+    impl Callbacks {
+        pub(crate) const fn new() -> Self {
+            Callbacks {
+                len: AtomicUsize::new(0),
+                callbacks: Mutex::new(VecDeque::new()),
+            }
+        }
+
+        pub(crate) fn push(&self, callback: Callback) {
+            let mut callbacks = lock_ignoring_poison(&self.callbacks);
+            callbacks.push_back(callback);
+            self.len.store(callbacks.len(), Ordering::SeqCst);
+        }
+
+        /// Run up to `count` of the oldest callbacks on the current thread
+        ///
+        /// They are run after the lock is released, so a callback can register another one
+        pub(crate) fn run(&self, count: usize) {
+            if count == 0 || self.len.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+
+            let ready: Vec<_> = {
+                let mut callbacks = lock_ignoring_poison(&self.callbacks);
+                let count = count.min(callbacks.len());
+                let ready = callbacks.drain(..count).collect();
+                self.len.store(callbacks.len(), Ordering::SeqCst);
+                ready
+            };
+
+            for callback in ready {
+                callback();
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+mod fair {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::{
+        collections::VecDeque,
+        sync::Mutex,
+        thread::{self, Thread},
+    };
+
+    use crate::lock_ignoring_poison;
+
+    /// Threads blocked in a fair acquire, served strictly in the order they arrived
+    pub(crate) struct FairQueue {
+        /// The length of `threads`, see the module docs
+        len: AtomicUsize,
+        threads: Mutex<VecDeque<Thread>>,
+    }
+
+    impl FairQueue {
+        pub(crate) const fn new() -> Self {
+            FairQueue {
+                len: AtomicUsize::new(0),
+                threads: Mutex::new(VecDeque::new()),
+            }
+        }
+
+        /// Returns true if no thread is queued
+        pub(crate) fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+
+        /// Get the number of queued threads without taking the lock
+        pub(crate) fn len(&self) -> usize {
+            self.len.load(Ordering::SeqCst)
+        }
+
+        /// Add the current thread to the back of the queue
+        pub(crate) fn join(&self) {
+            let mut threads = lock_ignoring_poison(&self.threads);
+            threads.push_back(thread::current());
+            self.len.store(threads.len(), Ordering::SeqCst);
+        }
+
+        /// Returns true if the current thread is the next to be served
+        pub(crate) fn is_front(&self) -> bool {
+            let id = thread::current().id();
+            lock_ignoring_poison(&self.threads)
+                .front()
+                .is_some_and(|front| front.id() == id)
+        }
+
+        /// Remove the current thread from the queue, waking the thread that is served next
+        pub(crate) fn leave(&self) {
+            let id = thread::current().id();
+            let mut threads = lock_ignoring_poison(&self.threads);
+            if let Some(index) = threads.iter().position(|queued| queued.id() == id) {
+                threads.remove(index);
+                self.len.store(threads.len(), Ordering::SeqCst);
+            }
+            if let Some(front) = threads.front() {
+                front.unpark();
+            }
+        }
+
+        /// Wake the thread that is served next, if any
+        pub(crate) fn unpark_front(&self) {
+            if self.is_empty() {
+                return;
+            }
+            if let Some(front) = lock_ignoring_poison(&self.threads).front() {
+                front.unpark();
+            }
+        }
+    }
+}
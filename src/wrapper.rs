@@ -1,14 +1,46 @@
-use crate::{raw, SemaphoreError};
+use crate::{raw, SemaphoreError, WaitStrategy, WakeOrder};
+#[cfg(any(feature = "async", feature = "embassy"))]
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
 use core::{
     ops::Deref,
-    sync::atomic::{AtomicUsize, Ordering},
+    sync::atomic::{AtomicBool, Ordering},
 };
+#[cfg(feature = "std")]
+use std::sync::Arc;
+
+/// Something that can tell a blocking acquire to stop waiting, see [`Semaphore::get_with_cancel`]
+///
+/// Implement this for the cancellation token of your runtime, e.g. tokio's `CancellationToken`
+pub trait Cancel {
+    /// Returns true once the wait should be abandoned
+    fn is_cancelled(&self) -> bool;
+}
+
+impl Cancel for AtomicBool {
+    fn is_cancelled(&self) -> bool {
+        self.load(Ordering::SeqCst)
+    }
+}
 
 /// Allows up to `max` references to the data in the Semaphore
 ///
 /// This behaves like [`RwLock<T>`][`std::sync::RwLock`] with some key differences
 /// 1. You can't get a `&mut T`, only a `&T`
 /// 2. You can have up to a maximum number of references at once
+///
+/// Unlike `RwLock`, poisoning is opt-in: only semaphores created with
+/// [`Semaphore::new_poisoning`] track panics in guard holders
+///
+/// Like `RwLock`, sharing the semaphore between threads shares `&T` between them, so it is only `Sync` if `T` is
+///
+/// ```compile_fail
+/// fn assert_sync<S: Sync>() {}
+/// assert_sync::<semaphorus::Semaphore<core::cell::Cell<u64>>>();
+/// ```
 pub struct Semaphore<T: ?Sized> {
     raw: raw::Semaphore,
     data: T,
@@ -17,97 +49,3621 @@ pub struct Semaphore<T: ?Sized> {
 impl<T: ?Sized> Semaphore<T> {
     /// Returns true if the current cound is >= the maximum count
     #[must_use]
+    #[inline]
+    #[track_caller]
     pub fn at_max(&self, ordering: Ordering) -> bool {
         self.raw.at_max(ordering)
     }
 
     /// Get the current number of references to the data
     #[must_use]
+    #[inline]
+    #[track_caller]
     pub fn count(&self, ordering: Ordering) -> usize {
         self.raw.count(ordering)
     }
 
-    /// This function can be inefficient, as it uses [`std::thread::sleep`] on `std` and [`core::hint::spin_loop`] on `no_std`.
+    /// Get the maximum number of references that can be held at once
+    #[must_use]
+    #[inline]
+    pub fn max(&self) -> usize {
+        self.raw.max()
+    }
+
+    /// Read the count and the max as one view, see [`raw::Semaphore::snapshot`]
+    #[must_use]
+    pub fn snapshot(&self) -> crate::SemaphoreSnapshot {
+        self.raw.snapshot()
+    }
+
+    /// Get how many more acquires are allowed, see [`raw::Semaphore::remaining_total`]
+    #[must_use]
+    pub fn remaining_total(&self) -> Option<usize> {
+        self.raw.remaining_total()
+    }
+
+    /// Get the highest number of references held at once, see [`raw::Semaphore::peak_count`]
+    #[must_use]
+    pub fn peak_count(&self) -> usize {
+        self.raw.peak_count()
+    }
+
+    /// Get how many acquires succeeded, see [`raw::Semaphore::total_acquired`]
+    #[must_use]
+    pub fn total_acquired(&self) -> usize {
+        self.raw.total_acquired()
+    }
+
+    /// Zero the acquire total and start the peak over, see [`raw::Semaphore::reset_metrics`]
+    pub fn reset_metrics(&self) {
+        self.raw.reset_metrics();
+    }
+
+    /// Change the maximum number of references, see [`raw::Semaphore::set_max`]
     /// # Panics
-    /// This function will panic if `max` == 0 because that will cause an infinite loop
-    pub fn get(&self) -> SemaphoreGuard<T> {
+    /// Panics if the semaphore is indexed and `new_max` > [`usize::BITS`]
+    pub fn set_max(&self, new_max: usize) {
+        self.raw.set_max(new_max);
+    }
+
+    /// Raise the maximum number of references by `permits`, see [`raw::Semaphore::add_permits`]
+    /// # Panics
+    /// Panics if the semaphore is indexed and the max would pass [`usize::BITS`]
+    pub fn add_permits(&self, permits: usize) {
+        self.raw.add_permits(permits);
+    }
+
+    /// Run `f` with a new semaphore over a reference to the data, allowing up to `sub_max` references
+    ///
+    /// The child has its own count: its guards don't count against this semaphore and this semaphore's
+    /// guards don't count against it, so it only throttles the code that acquires through it
+    pub fn scope<R>(&self, sub_max: usize, f: impl FnOnce(&Semaphore<&T>) -> R) -> R {
+        f(&Semaphore::new(&self.data, sub_max))
+    }
+
+    /// Raise the max by `extra` while `f` runs, lowering it again afterwards even if `f` panics
+    ///
+    /// Guards taken during the boost stay valid after it ends. Until enough of them are dropped
+    /// the count is above the restored max and no new permits are handed out, the max itself never underflows
+    /// # Panics
+    /// Panics if the semaphore is indexed and the max would pass [`usize::BITS`]
+    pub fn with_boosted_max<R>(&self, extra: usize, f: impl FnOnce() -> R) -> R {
+        self.raw.add_permits(extra);
+        let _restore = Deferred(Some(|| self.raw.remove_permits(extra)));
+        f()
+    }
+
+    /// Get how many more references can be taken right now, see [`raw::Semaphore::available`]
+    #[must_use]
+    #[inline]
+    #[track_caller]
+    pub fn available(&self, ordering: Ordering) -> usize {
+        self.raw.available(ordering)
+    }
+
+    /// Hand back `permits` that no guard holds, like those taken by [`SemaphoreBuilder::initial_count`]
+    /// # Panics
+    /// This function will panic if `permits` is more than the current count
+    pub fn release_initial(&self, permits: usize) {
+        self.raw.release_initial(permits);
+    }
+
+    /// Block until a permit is available, waiting with the semaphore's [`WaitStrategy`]
+    ///
+    /// By default this parks the thread until a guard is dropped on `std`, and busy-waits with [`core::hint::spin_loop`] on `no_std`.
+    /// With the `crossbeam` feature it uses [`crossbeam_utils::Backoff`] instead, which spins and then yields the thread.
+    /// With the `log` feature a warning is logged once a wait passes [`Semaphore::slow_wait_threshold`]
+    ///
+    /// With the `no_block` feature this never waits. On a single-threaded target like `wasm32-unknown-unknown`
+    /// nothing could release a permit while the thread blocks, so it panics instead of hanging forever.
+    /// Use [`Semaphore::try_get`] or [`Semaphore::acquire`] there
+    /// # Panics
+    /// This function will panic if `max` == 0 because that will cause an infinite loop,
+    /// if the semaphore is poisoned, with `no_block` if the semaphore is at its max,
+    /// or if it has to wait after [`shutdown_all`][`crate::shutdown_all`]
+    pub fn get(&self) -> SemaphoreGuard<'_, T> {
         assert_ne!(
-            self.raw.max, 0,
+            self.raw.max(),
+            0,
             "Calling 'Semaphore::get' on a semaphore with a max of 0 will loop forever!"
         );
-        while self.at_max(Ordering::Relaxed) {
-            #[cfg(feature = "std")]
-            std::thread::sleep(std::time::Duration::from_millis(50));
-            #[cfg(not(feature = "std"))]
-            core::hint::spin_loop();
+        match self.get_or_shutdown() {
+            Ok(guard) => guard,
+            Err(err) => panic!("Semaphore::get failed: {err}"),
+        }
+    }
+
+    /// Like [`Semaphore::get`], but returns an error instead of panicking
+    ///
+    /// Once [`shutdown_all`][`crate::shutdown_all`] is called it stops waiting and returns
+    /// [`SemaphoreError::ShuttingDown`], so threads blocked during a shutdown don't need a cancel token each
+    /// # Errors
+    /// This function will return [`SemaphoreError::ShuttingDown`] if it had to wait during a shutdown,
+    /// [`SemaphoreError::AtMaxCount`] with `no_block` if the semaphore is at its max,
+    /// or [`SemaphoreError::Poisoned`] if the semaphore is poisoned
+    /// # Panics
+    /// This function will panic if `max` == 0 because that will cause an infinite loop
+    pub fn get_or_shutdown(&self) -> Result<SemaphoreGuard<'_, T>, SemaphoreError> {
+        assert_ne!(
+            self.raw.max(), 0,
+            "Calling 'Semaphore::get_or_shutdown' on a semaphore with a max of 0 will loop forever!"
+        );
+        #[cfg(feature = "std")]
+        if self.raw.is_fair() {
+            return self
+                .raw
+                .get_fair(None)
+                .map(|guard| SemaphoreGuard::new(guard, self));
+        }
+        loop {
+            match self.try_get() {
+                Ok(guard) => return Ok(guard),
+                Err(SemaphoreError::AtMaxCount) => {
+                    if !self.raw.wait_while(|| true) {
+                        return Err(self.raw.gave_up_error());
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Block until `permits` permits are available, returning one guard that holds all of them
+    ///
+    /// Freed permits are reserved one at a time until there are enough, so a large request isn't
+    /// starved by a steady stream of single permit requests. While it reserves, the permits it has
+    /// collected are unavailable to everyone else, and other `get_many` callers wait behind it
+    /// # Panics
+    /// This function will panic if `permits` > `max` because that will cause an infinite loop,
+    /// if the semaphore is poisoned, with `no_block` if fewer than `permits` are free,
+    /// or if it has to wait after [`shutdown_all`][`crate::shutdown_all`]
+    #[cfg(feature = "std")]
+    pub fn get_many(&self, permits: usize) -> SemaphoreGuard<'_, T> {
+        assert!(
+            permits <= self.raw.max(),
+            "Calling 'Semaphore::get_many' with more permits than the max will loop forever!"
+        );
+        match self.raw.reserve_many(permits) {
+            Ok(guard) => SemaphoreGuard::new(guard, self),
+            Err(err) => panic!("Semaphore::get_many failed: {err}"),
+        }
+    }
+
+    /// Like [`Semaphore::get_many`], but returns an error instead of panicking, including when `permits` > `max`
+    ///
+    /// The guard is only handed out once all `permits` are held, so the caller gets exactly that many or none
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] right away if `permits` > `max` since it could never succeed,
+    /// and otherwise fails in the same cases as [`Semaphore::get_or_shutdown`]
+    #[cfg(feature = "std")]
+    pub fn get_exactly(&self, permits: usize) -> Result<SemaphoreGuard<'_, T>, SemaphoreError> {
+        if permits > self.raw.max() {
+            return Err(SemaphoreError::AtMaxCount);
+        }
+        self.raw
+            .reserve_many(permits)
+            .map(|guard| SemaphoreGuard::new(guard, self))
+    }
+
+    /// Like [`Semaphore::get`], but gives up once `token` is cancelled
+    ///
+    /// The token is checked before every wait, so how quickly a cancellation is noticed
+    /// depends on the [`WaitStrategy`]. With `no_block` or after [`shutdown_all`][`crate::shutdown_all`] it returns `None` instead of waiting,
+    /// and it returns `None` once the semaphore is closed, whether that happened before or during the wait
+    /// # Panics
+    /// This function will panic if the semaphore is poisoned or exhausted
+    pub fn get_with_cancel(&self, token: &impl Cancel) -> Option<SemaphoreGuard<'_, T>> {
+        loop {
+            match self.try_get() {
+                Ok(guard) => return Some(guard),
+                Err(SemaphoreError::AtMaxCount) => {
+                    if !self.raw.wait_while(|| !token.is_cancelled()) {
+                        return None;
+                    }
+                }
+                Err(SemaphoreError::Closed) => return None,
+                Err(err) => panic!("Semaphore::get_with_cancel failed: {err}"),
+            }
+        }
+    }
+
+    /// Like [`Semaphore::get`], but gives up if no permit becomes available within `timeout`
+    ///
+    /// The timeout is checked between waits, so a [`WaitStrategy::Sleep`] can overshoot it by one sleep.
+    /// With `no_block` or after [`shutdown_all`][`crate::shutdown_all`] it returns `None` instead of waiting,
+    /// and it returns `None` once the semaphore is closed, whether that happened before or during the wait
+    /// # Panics
+    /// This function will panic if the semaphore is poisoned or exhausted
+    #[cfg(feature = "std")]
+    pub fn get_timeout(&self, timeout: core::time::Duration) -> Option<SemaphoreGuard<'_, T>> {
+        self.get_timeout_with_clock(timeout, &crate::clock::RealClock)
+    }
+
+    /// Like [`Semaphore::get_timeout`], but measures the timeout and sleeps with `clock`
+    ///
+    /// Passing a [`ManualClock`][`crate::clock::ManualClock`] makes the timeout testable without real sleeps
+    /// # Panics
+    /// This function will panic in the same cases as [`Semaphore::get_timeout`]
+    #[cfg(feature = "std")]
+    pub fn get_timeout_with_clock(
+        &self,
+        timeout: core::time::Duration,
+        clock: &impl crate::clock::Clock,
+    ) -> Option<SemaphoreGuard<'_, T>> {
+        // A timeout too large for an `Instant` waits without a deadline
+        let deadline = clock.now().checked_add(timeout);
+        loop {
+            match self.try_get() {
+                Ok(guard) => return Some(guard),
+                Err(SemaphoreError::AtMaxCount) => {
+                    if !self.raw.wait_while_sleeping(
+                        || deadline.is_none_or(|deadline| clock.now() < deadline),
+                        |d| clock.sleep(d),
+                    ) {
+                        return None;
+                    }
+                }
+                Err(SemaphoreError::Closed) => return None,
+                Err(err) => panic!("Semaphore::get_timeout failed: {err}"),
+            }
+        }
+    }
+
+    /// Like [`Semaphore::get_timeout`], but panics if no permit becomes available within `timeout`
+    ///
+    /// Meant for tests that should fail fast instead of hanging, the panic message includes
+    /// the count and max and points at the caller
+    /// # Panics
+    /// This function will panic if it times out, or if the semaphore is poisoned, exhausted or closed
+    #[cfg(feature = "std")]
+    #[track_caller]
+    pub fn get_or_panic_after(&self, timeout: core::time::Duration) -> SemaphoreGuard<'_, T> {
+        match self.get_timeout(timeout) {
+            Some(guard) => guard,
+            None if self.is_closed() => panic!(
+                "Semaphore::get_or_panic_after failed: {}",
+                SemaphoreError::Closed
+            ),
+            None => panic!(
+                "Semaphore::get_or_panic_after timed out after {timeout:?} ({}/{} permits in use)",
+                self.count(Ordering::SeqCst),
+                self.raw.max()
+            ),
+        }
+    }
+
+    /// Get the strategy used by [`Semaphore::get`] while waiting
+    #[must_use]
+    #[inline]
+    pub fn wait_strategy(&self) -> WaitStrategy {
+        self.raw.wait_strategy()
+    }
+
+    /// Change the strategy used by [`Semaphore::get`] while waiting, see [`raw::Semaphore::set_wait_strategy`]
+    #[inline]
+    pub fn set_wait_strategy(&self, strategy: WaitStrategy) {
+        self.raw.set_wait_strategy(strategy);
+    }
+
+    /// Get which waiter is woken first when a permit is released
+    #[must_use]
+    #[inline]
+    pub fn wake_order(&self) -> WakeOrder {
+        self.raw.wake_order()
+    }
+
+    /// Call `callback` once a permit is free, see [`raw::Semaphore::on_available`]
+    ///
+    /// The callback runs on the thread that releases the permit
+    #[cfg(feature = "std")]
+    pub fn on_available(&self, callback: Box<dyn FnOnce() + Send>) {
+        self.raw.on_available(callback);
+    }
+
+    /// Call `callback` with the new count after every acquire and release, see [`raw::Semaphore::on_change`]
+    #[cfg(feature = "std")]
+    pub fn on_change(&mut self, callback: Box<dyn Fn(usize) + Send + Sync>) {
+        self.raw.on_change(callback);
+    }
+
+    /// Change which waiter is woken first when a permit is released, see [`raw::Semaphore::set_wake_order`]
+    #[inline]
+    pub fn set_wake_order(&self, order: WakeOrder) {
+        self.raw.set_wake_order(order);
+    }
+
+    /// Get how long [`Semaphore::get`] can wait before it logs a warning
+    #[cfg(feature = "log")]
+    #[must_use]
+    pub fn slow_wait_threshold(&self) -> core::time::Duration {
+        self.raw.slow_wait_threshold()
+    }
+
+    /// Change how long [`Semaphore::get`] can wait before it logs a warning, see [`raw::Semaphore::set_slow_wait_threshold`]
+    #[cfg(feature = "log")]
+    pub fn set_slow_wait_threshold(&self, threshold: core::time::Duration) {
+        self.raw.set_slow_wait_threshold(threshold);
+    }
+
+    /// Like [`Semaphore::get`], but also reports whether the call had to wait
+    ///
+    /// The `bool` is `true` if the first attempt failed and the call blocked,
+    /// which is cheap enough to use for logging only the slow acquisitions
+    /// # Panics
+    /// This function will panic if `max` == 0, or if the semaphore is poisoned
+    pub fn get_reporting(&self) -> (SemaphoreGuard<'_, T>, bool) {
+        match self.try_get() {
+            Ok(guard) => (guard, false),
+            Err(_) => (self.get(), true),
+        }
+    }
+
+    /// Another name for [`Semaphore::get_reporting`], for code that profiles the uncontended hit rate
+    ///
+    /// Both make exactly one [`Semaphore::try_get`] attempt before blocking in [`Semaphore::get`],
+    /// so counting the `false`s measures how often the fast path succeeds
+    /// # Panics
+    /// This function will panic if `max` == 0, or if the semaphore is poisoned
+    pub fn get_or_block(&self) -> (SemaphoreGuard<'_, T>, bool) {
+        self.get_reporting()
+    }
+
+    /// Block until a permit is available, run `f` on the data and release the permit when it returns
+    ///
+    /// The permit is also released if `f` panics
+    /// # Panics
+    /// This function will panic in the same cases as [`Semaphore::get`], or if `f` panics
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&*self.get())
+    }
+
+    /// Like [`Semaphore::with`], but never blocks
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if the current count is >= the maximum count,
+    /// or [`SemaphoreError::Poisoned`] if the semaphore is poisoned
+    pub fn try_with<R>(&self, f: impl FnOnce(&T) -> R) -> Result<R, SemaphoreError> {
+        Ok(f(&*self.try_get()?))
+    }
+
+    /// Run `f` with the data without taking a permit, even while the semaphore is at its max
+    ///
+    /// This is sound because the semaphore only ever hands out `&T` while it is shared, `&mut T` needs `&mut self`.
+    /// The permits are bypassed entirely though: peeks don't count towards `max`, can't be waited on,
+    /// and may see the data halfway through a change made with interior mutability by a holder
+    pub fn peek<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.data)
+    }
+
+    /// Clone the data out and release the permit before returning, so the permit is held only while cloning
+    ///
+    /// The returned value is a snapshot, interior mutability in the semaphore may change the data right after.
+    /// This function will never block
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if the current count is >= the maximum count,
+    /// or [`SemaphoreError::Poisoned`] if the semaphore is poisoned
+    pub fn try_get_cloned(&self) -> Result<T, SemaphoreError>
+    where
+        T: Clone,
+    {
+        self.try_with(T::clone)
+    }
+
+    /// Like [`Semaphore::get`], but also returns how long the acquisition took
+    ///
+    /// The time is measured with [`std::time::Instant`] around the wait, so it costs nothing unless
+    /// this is called. An acquire that succeeds on the first attempt reports a duration near zero
+    /// # Panics
+    /// This function will panic in the same cases as [`Semaphore::get`]
+    #[cfg(feature = "std")]
+    pub fn get_timed(&self) -> (SemaphoreGuard<'_, T>, core::time::Duration) {
+        let started = std::time::Instant::now();
+        let guard = self.get();
+        (guard, started.elapsed())
+    }
+
+    /// Get an iterator whose `next` blocks like [`Semaphore::get_or_shutdown`] and yields the guard
+    ///
+    /// Pulling guards from it as they free up keeps `max` units of work running. It only ends once an acquire fails,
+    /// because the semaphore was closed or poisoned, or during a [`shutdown_all`][`crate::shutdown_all`]
+    /// # Panics
+    /// `next` panics if `max` == 0 because that will cause an infinite loop
+    pub fn get_iter(&self) -> GetIter<'_, T> {
+        GetIter { semaphore: self }
+    }
+
+    /// Wait for a permit without blocking the thread
+    ///
+    /// Dropping the future before it completes gives up its place without consuming a permit
+    /// # Errors
+    /// The future resolves to an error in the same cases as [`raw::Semaphore::acquire`]
+    #[cfg(any(feature = "async", feature = "embassy"))]
+    pub fn acquire(&self) -> Acquire<'_, T> {
+        Acquire {
+            raw: self.raw.acquire(),
+            semaphore: self,
+        }
+    }
+
+    /// Wait for `permits` permits at once without blocking the thread, see [`raw::Semaphore::acquire_many`]
+    ///
+    /// Dropping the future before it completes gives up its place without consuming any permits
+    /// # Errors
+    /// The future resolves to an error in the same cases as [`raw::Semaphore::acquire`]
+    #[cfg(any(feature = "async", feature = "embassy"))]
+    pub fn acquire_many(&self, permits: usize) -> Acquire<'_, T> {
+        Acquire {
+            raw: self.raw.acquire_many(permits),
+            semaphore: self,
+        }
+    }
+
+    /// Wait for a permit without blocking the thread, giving up once `deadline` completes
+    ///
+    /// `deadline` can be any future, like a timer from the runtime in use. The acquire is polled first,
+    /// so a permit that is available when the deadline fires still wins. On timeout the waiting acquire
+    /// is dropped right away, its place in the queue is given up and a wakeup it already received is
+    /// passed on, so it never holds on to a permit that frees later
+    /// # Errors
+    /// The future resolves to an error in the same cases as [`raw::Semaphore::acquire`]
+    #[cfg(any(feature = "async", feature = "embassy"))]
+    pub fn acquire_until<D: Future>(&self, deadline: D) -> AcquireUntil<'_, T, D> {
+        AcquireUntil {
+            acquire: Some(self.acquire()),
+            deadline,
         }
-        self.try_get().unwrap()
+    }
+
+    /// Like [`Semaphore::acquire_until`], with a [`tokio::time::sleep`] of `timeout` as the deadline
+    ///
+    /// Has to be polled inside a tokio runtime with the timer enabled
+    /// # Errors
+    /// The future resolves to an error in the same cases as [`raw::Semaphore::acquire`]
+    #[cfg(feature = "tokio")]
+    pub fn acquire_timeout(
+        &self,
+        timeout: core::time::Duration,
+    ) -> AcquireUntil<'_, T, tokio::time::Sleep> {
+        self.acquire_until(tokio::time::sleep(timeout))
     }
 
     /// Attempt to get the value in the semaphore.
     ///
     /// This function will never block
     /// # Errors
-    /// This function will return [`SemaphoreError::AtMax`] if the current count is >= the maximum count
+    /// This function will return [`SemaphoreError::AtMaxCount`] if the current count is >= the maximum count,
+    /// or [`SemaphoreError::Poisoned`] if the semaphore is poisoned
     #[inline]
-    pub fn try_get(&self) -> Result<SemaphoreGuard<T>, SemaphoreError> {
-        Ok(SemaphoreGuard::new(self.raw.try_get()?, &self.data))
+    #[must_use = "if unused, a successful acquire will immediatly unlock"]
+    pub fn try_get(&self) -> Result<SemaphoreGuard<'_, T>, SemaphoreError> {
+        Ok(SemaphoreGuard::new(self.raw.try_get()?, self))
     }
 
-    /// Get a mutable reference to the data in the semaphore
+    /// Same as [`Semaphore::get`], named like [`std::sync::RwLock::read`] to ease moving over from one
+    ///
+    /// There is no `write`, the only exclusive access is [`Semaphore::get_mut`] through `&mut self`
+    /// # Panics
+    /// Panics in the same cases as [`Semaphore::get`]
     #[inline]
-    pub fn get_mut(&mut self) -> &mut T {
-        &mut self.data
+    pub fn read(&self) -> SemaphoreGuard<'_, T> {
+        self.get()
     }
-}
 
-impl<T> Semaphore<T> {
-    /// Create a new semaphore with 0 counted references
-    pub fn new(value: T, max: usize) -> Self {
-        debug_assert_ne!(
-            max, 0,
-            "A semaphore with a maximum count of '0' generally useless"
-        );
+    /// Same as [`Semaphore::try_get`], named like [`std::sync::RwLock::try_read`]
+    /// # Errors
+    /// Errors in the same cases as [`Semaphore::try_get`]
+    #[inline]
+    #[must_use = "if unused, a successful acquire will immediatly unlock"]
+    pub fn try_read(&self) -> Result<SemaphoreGuard<'_, T>, SemaphoreError> {
+        self.try_get()
+    }
 
-        Semaphore {
-            raw: raw::Semaphore::new(max),
-            data: value,
+    /// Like [`Semaphore::try_get`], but runs `fallback` with the error instead of returning it
+    ///
+    /// Saves a match when a full semaphore has an alternative, like serving a cached value.
+    /// This function will never block
+    pub fn try_get_or_else<R, F: FnOnce(SemaphoreError) -> R>(
+        &self,
+        fallback: F,
+    ) -> Either<SemaphoreGuard<'_, T>, R> {
+        match self.try_get() {
+            Ok(guard) => Either::Left(guard),
+            Err(err) => Either::Right(fallback(err)),
         }
     }
 
-    /// Move the value out of the semaphore
-    pub fn into_inner(self) -> T {
-        self.data
+    /// Try each semaphore in order, returning the index and guard of the first one with a free permit
+    ///
+    /// Meant for tiers of capacity, like a fast pool with a slow pool behind it. A failed attempt holds nothing,
+    /// so no permit from an earlier semaphore is held while a later one is tried.
+    /// This function will never block, returns `None` if every semaphore is full or poisoned
+    pub fn try_get_any<'guard>(
+        semaphores: &[&'guard Semaphore<T>],
+    ) -> Option<(usize, SemaphoreGuard<'guard, T>)> {
+        semaphores
+            .iter()
+            .enumerate()
+            .find_map(|(index, semaphore)| Some((index, semaphore.try_get().ok()?)))
     }
-}
 
-unsafe impl<T: ?Sized + Send> Send for Semaphore<T> {}
-unsafe impl<T: ?Sized + Send> Sync for Semaphore<T> {}
+    /// Like [`Semaphore::try_get`], but also returns [`Semaphore::waiter_count`] read right after the acquire
+    ///
+    /// Meant as an autoscaling input, the count says how far demand exceeds capacity.
+    /// It is a snapshot like [`raw::Semaphore::waiter_count`], not read atomically with the acquire.
+    /// This function will never block
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if the current count is >= the maximum count,
+    /// or [`SemaphoreError::Poisoned`] if the semaphore is poisoned, use [`Semaphore::waiter_count`] to get the depth then
+    #[cfg(feature = "std")]
+    pub fn try_get_with_depth(&self) -> Result<(SemaphoreGuard<'_, T>, usize), SemaphoreError> {
+        let guard = self.try_get()?;
+        Ok((guard, self.waiter_count()))
+    }
 
-/// A wrapper around a reference to the data in the semaphore
-/// Automatically decrements the reference count when it is dropped
-/// For mutable access, consider using a [cell][`std::cell`] type or use [`Semaphore::get_mut`]
-#[must_use = "if unused, the guard will immediatly unlock"]
-pub struct SemaphoreGuard<'guard, T: ?Sized> {
-    _inner: raw::SemaphoreGuard<'guard>,
-    data: &'guard T,
-}
+    /// Get the number of threads and tasks waiting for a reference, see [`raw::Semaphore::waiter_count`]
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn waiter_count(&self) -> usize {
+        self.raw.waiter_count()
+    }
 
-impl<'guard, T: ?Sized> SemaphoreGuard<'guard, T> {
-    /// Create a guard around a `Semaphore`, and increment the reference count
-    fn new(raw_guard: raw::SemaphoreGuard<'guard>, data: &'guard T) -> Self {
-        SemaphoreGuard {
-            _inner: raw_guard,
-            data,
+    /// Like [`Semaphore::try_get`], but also hands back `extra` with the same lifetime as the guard
+    ///
+    /// Useful for shared state next to the semaphore, such as a config, that should be borrowed for as long as the data.
+    /// This function will never block
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if the current count is >= the maximum count,
+    /// or [`SemaphoreError::Poisoned`] if the semaphore is poisoned
+    pub fn try_get_with<'a, U: ?Sized>(
+        &'a self,
+        extra: &'a U,
+    ) -> Result<(SemaphoreGuard<'a, T>, &'a U), SemaphoreError> {
+        Ok((self.try_get()?, extra))
+    }
+
+    /// Like [`Semaphore::try_get`], but waits up to `timeout` for a permit, see [`raw::Semaphore::try_get_for`]
+    ///
+    /// Unlike [`Semaphore::get_timeout`] the wait never overshoots the timeout by a whole sleep or park
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if it timed out,
+    /// [`SemaphoreError::ShuttingDown`] if it had to wait after [`shutdown_all`][`crate::shutdown_all`],
+    /// or [`SemaphoreError::Poisoned`] if the semaphore is poisoned
+    #[cfg(feature = "std")]
+    pub fn try_get_for(
+        &self,
+        timeout: core::time::Duration,
+    ) -> Result<SemaphoreGuard<'_, T>, SemaphoreError> {
+        Ok(SemaphoreGuard::new(self.raw.try_get_for(timeout)?, self))
+    }
+
+    /// Like [`Semaphore::try_get_for`], but waits until `deadline`, see [`raw::Semaphore::try_get_until`]
+    ///
+    /// A deadline that has already passed makes a single attempt like [`Semaphore::try_get`]
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if the deadline passed,
+    /// [`SemaphoreError::ShuttingDown`] if it had to wait after [`shutdown_all`][`crate::shutdown_all`],
+    /// or [`SemaphoreError::Poisoned`] if the semaphore is poisoned
+    #[cfg(feature = "std")]
+    pub fn try_get_until(
+        &self,
+        deadline: std::time::Instant,
+    ) -> Result<SemaphoreGuard<'_, T>, SemaphoreError> {
+        Ok(SemaphoreGuard::new(self.raw.try_get_until(deadline)?, self))
+    }
+
+    /// Like [`Semaphore::get`], but for a pinned semaphore, with a guard that hands out `Pin<&T>`
+    ///
+    /// The data is structurally pinned: once the semaphore is pinned the data is never moved
+    /// or handed out as `&mut T` again, because [`Semaphore::get_mut`] and [`Semaphore::into_inner`]
+    /// need an unpinned semaphore. That makes it sound to keep `!Unpin` state, like futures, in the semaphore
+    /// # Panics
+    /// This function will panic in the same cases as [`Semaphore::get`]
+    pub fn get_pin(self: core::pin::Pin<&Self>) -> PinnedGuard<'_, T> {
+        PinnedGuard {
+            guard: self.get_ref().get(),
         }
     }
-}
 
-impl<'guard, T: ?Sized> Deref for SemaphoreGuard<'guard, T> {
-    type Target = T;
+    /// Attempt to take `permits` permits at once, see [`raw::Semaphore::try_get_many`]
+    ///
+    /// This function will never block
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if fewer than `permits` are free,
+    /// or [`SemaphoreError::Poisoned`] if the semaphore is poisoned
+    pub fn try_get_many(&self, permits: usize) -> Result<SemaphoreGuard<'_, T>, SemaphoreError> {
+        Ok(SemaphoreGuard::new(self.raw.try_get_many(permits)?, self))
+    }
 
-    fn deref(&self) -> &Self::Target {
-        self.data
+    /// Attempt to get the value in the semaphore, spinning up to `max_spins` times while it is at its max
+    ///
+    /// See [`raw::Semaphore::try_get_budget`], the budget counts spins rather than time
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if the semaphore is still at its max once the budget is spent,
+    /// or [`SemaphoreError::Poisoned`] if the semaphore is poisoned
+    pub fn try_get_budget(&self, max_spins: u32) -> Result<SemaphoreGuard<'_, T>, SemaphoreError> {
+        Ok(SemaphoreGuard::new(
+            self.raw.try_get_budget(max_spins)?,
+            self,
+        ))
+    }
+
+    /// Attempt to get the value in the semaphore, leaving at least `min_free_after` permits free for others
+    ///
+    /// See [`raw::Semaphore::try_get_leaving`].
+    /// This function will never block
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if taking a permit would leave fewer than `min_free_after` free,
+    /// or [`SemaphoreError::Poisoned`] if the semaphore is poisoned
+    pub fn try_get_leaving(
+        &self,
+        min_free_after: usize,
+    ) -> Result<SemaphoreGuard<'_, T>, SemaphoreError> {
+        Ok(SemaphoreGuard::new(
+            self.raw.try_get_leaving(min_free_after)?,
+            self,
+        ))
+    }
+
+    /// Attempt to get the value in the semaphore along with a slot index no other guard holds
+    ///
+    /// See [`raw::Semaphore::try_get_indexed`].
+    /// This function will never block
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if the current count is >= the maximum count,
+    /// or [`SemaphoreError::Poisoned`] if the semaphore is poisoned
+    /// # Panics
+    /// This function will panic if the semaphore wasn't created with [`Semaphore::new_indexed`]
+    pub fn try_get_indexed(&self) -> Result<(SemaphoreGuard<'_, T>, usize), SemaphoreError> {
+        let (guard, slot) = self.raw.try_get_indexed()?;
+        Ok((SemaphoreGuard::new(guard, self), slot))
+    }
+
+    /// Attempt to get the value in the semaphore, recording `label` as the holder
+    ///
+    /// See [`raw::Semaphore::try_get_labeled`].
+    /// This function will never block
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if the current count is >= the maximum count,
+    /// or [`SemaphoreError::Poisoned`] if the semaphore is poisoned
+    pub fn try_get_labeled(
+        &self,
+        label: &'static str,
+    ) -> Result<SemaphoreGuard<'_, T>, SemaphoreError> {
+        Ok(SemaphoreGuard::new(self.raw.try_get_labeled(label)?, self))
+    }
+
+    /// Attempt to get the value in the semaphore, keeping `span` entered until the guard is dropped
+    ///
+    /// See [`raw::Semaphore::try_get_instrumented`].
+    /// This function will never block
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if the current count is >= the maximum count,
+    /// or [`SemaphoreError::Poisoned`] if the semaphore is poisoned
+    #[cfg(feature = "tracing")]
+    pub fn try_get_instrumented(
+        &self,
+        span: tracing::Span,
+    ) -> Result<SemaphoreGuard<'_, T>, SemaphoreError> {
+        Ok(SemaphoreGuard::new(
+            self.raw.try_get_instrumented(span)?,
+            self,
+        ))
+    }
+
+    /// Get the labels of the live guards from [`Semaphore::try_get_labeled`], see [`raw::Semaphore::holders`]
+    #[cfg(feature = "debug_holders")]
+    #[must_use]
+    pub fn holders(&self) -> Vec<&'static str> {
+        self.raw.holders()
+    }
+
+    /// Get where every live guard was acquired, see [`raw::Semaphore::holders_with_backtraces`]
+    #[cfg(feature = "backtrace")]
+    #[must_use]
+    pub fn holders_with_backtraces(&self) -> Vec<Arc<std::backtrace::Backtrace>> {
+        self.raw.holders_with_backtraces()
+    }
+
+    /// Attempt to get the value in the semaphore, even if it is poisoned
+    ///
+    /// This function will never block
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if the current count is >= the maximum count
+    #[cfg(feature = "std")]
+    pub fn try_get_poisoned(
+        &self,
+    ) -> Result<
+        Result<SemaphoreGuard<'_, T>, crate::PoisonError<SemaphoreGuard<'_, T>>>,
+        SemaphoreError,
+    > {
+        Ok(match self.raw.try_get_poisoned()? {
+            Ok(guard) => Ok(SemaphoreGuard::new(guard, self)),
+            Err(poisoned) => Err(crate::PoisonError::new(SemaphoreGuard::new(
+                poisoned.into_inner(),
+                self,
+            ))),
+        })
+    }
+
+    /// Like [`Semaphore::get`], but ignores poisoning, for callers that can recover the data after a holder panicked
+    ///
+    /// The semaphore stays poisoned, use [`Semaphore::clear_poison`] to let the other acquires succeed again
+    /// # Panics
+    /// This function will panic if `max` == 0 because that will cause an infinite loop, if the semaphore
+    /// is closed or exhausted, or if it can't wait with `no_block` or after [`shutdown_all`][`crate::shutdown_all`]
+    #[cfg(feature = "std")]
+    pub fn get_unpoisoned(&self) -> SemaphoreGuard<'_, T> {
+        assert_ne!(
+            self.raw.max(),
+            0,
+            "Calling 'Semaphore::get_unpoisoned' on a semaphore with a max of 0 will loop forever!"
+        );
+        loop {
+            match self.try_get_poisoned() {
+                Ok(Ok(guard)) => return guard,
+                Ok(Err(poisoned)) => return poisoned.into_inner(),
+                Err(SemaphoreError::AtMaxCount) => assert!(
+                    self.raw.wait_while(|| true),
+                    "Semaphore::get_unpoisoned failed: {}",
+                    self.raw.gave_up_error()
+                ),
+                Err(err) => panic!("Semaphore::get_unpoisoned failed: {err}"),
+            }
+        }
+    }
+
+    /// Returns true if a guard was dropped while its thread was panicking
+    #[cfg(feature = "std")]
+    #[must_use]
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        self.raw.is_poisoned()
+    }
+
+    /// Clear the poisoned state of the semaphore
+    #[cfg(feature = "std")]
+    pub fn clear_poison(&self) {
+        self.raw.clear_poison();
+    }
+
+    /// Stop handing out references, see [`raw::Semaphore::close`]
+    pub fn close(&self) {
+        self.raw.close();
+    }
+
+    /// Returns true once [`Semaphore::close`] was called
+    #[must_use]
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.raw.is_closed()
+    }
+
+    /// Block until a reference could be taken, without taking it, see [`raw::Semaphore::wait_for_available`]
+    #[cfg(feature = "std")]
+    pub fn wait_for_available(&self) -> bool {
+        self.raw.wait_for_available()
+    }
+
+    /// Like [`Semaphore::wait_for_available`], but gives up after `timeout`
+    #[cfg(feature = "std")]
+    pub fn wait_for_available_timeout(&self, timeout: core::time::Duration) -> bool {
+        self.raw.wait_for_available_timeout(timeout)
+    }
+
+    /// Block until every reference has been dropped, see [`raw::Semaphore::wait_for_idle`]
+    #[cfg(feature = "std")]
+    pub fn wait_for_idle(&self) -> bool {
+        self.raw.wait_for_idle()
+    }
+
+    /// Close the semaphore, wait for the outstanding guards to be dropped, then run `on_drain` with the data
+    ///
+    /// Threads blocked in acquires fail with [`SemaphoreError::Closed`] right away, so by the time
+    /// `on_drain` runs nobody else holds a reference and nobody can get a new one
+    /// # Panics
+    /// This function will panic if it can't wait with `no_block` or after [`shutdown_all`][`crate::shutdown_all`]
+    #[cfg(feature = "std")]
+    pub fn shutdown(&self, on_drain: impl FnOnce(&T)) {
+        self.close();
+        assert!(
+            self.wait_for_idle(),
+            "Semaphore::shutdown failed: {}",
+            self.raw.gave_up_error()
+        );
+        on_drain(&self.data);
+    }
+
+    /// Get a mutable reference to the data in the semaphore
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Attempt to get the value in the semaphore, returning a guard that keeps the semaphore alive
+    ///
+    /// The guard doesn't borrow the semaphore, so it is `'static` whenever `T` is
+    /// and can be stored in collections or moved into other threads.
+    /// This function will never block
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if the current count is >= the maximum count,
+    /// or [`SemaphoreError::Poisoned`] if the semaphore is poisoned
+    #[cfg(feature = "std")]
+    pub fn try_get_owned(self: &Arc<Self>) -> Result<OwnedSemaphoreGuard<T>, SemaphoreError> {
+        self.raw.try_get()?.detach();
+        Ok(OwnedSemaphoreGuard::acquired(Arc::clone(self)))
+    }
+
+    /// Like [`Semaphore::get`], but returns a guard that keeps the semaphore alive, see [`Semaphore::try_get_owned`]
+    /// # Panics
+    /// This function will panic in the same cases as [`Semaphore::get`]
+    #[cfg(feature = "std")]
+    pub fn get_owned(self: &Arc<Self>) -> OwnedSemaphoreGuard<T> {
+        let SemaphoreGuard { _inner: inner, .. } = self.get();
+        inner.detach();
+        OwnedSemaphoreGuard::acquired(Arc::clone(self))
+    }
+
+    /// Wait for a permit without blocking the thread, returning a guard that keeps the semaphore alive
+    ///
+    /// Dropping the future before it completes gives up its place without consuming a permit
+    /// # Errors
+    /// The future resolves to an error in the same cases as [`raw::Semaphore::acquire`]
+    #[cfg(feature = "async")]
+    pub fn acquire_owned(self: &Arc<Self>) -> AcquireOwned<T> {
+        AcquireOwned {
+            semaphore: Some(Arc::clone(self)),
+            entry: crate::wait::WaitEntry::default(),
+        }
+    }
+}
+
+impl<T> Semaphore<T> {
+    /// Create a new semaphore with 0 counted references
+    ///
+    /// A `max` of 0 makes [`Semaphore::get`] panic, prefer [`Semaphore::new_nonzero`] when the max isn't a literal.
+    /// Use [`Semaphore::builder`] for more options
+    pub fn new(value: T, max: usize) -> Self {
+        SemaphoreBuilder::new().max(max).build(value)
+    }
+
+    /// Like [`Semaphore::new`], but rejects a `max` of 0 instead of making [`Semaphore::get`] panic later
+    ///
+    /// Meant for a max read from configuration, where a 0 should be reported rather than crash
+    /// # Errors
+    /// This function will return [`SemaphoreError::InvalidMax`] if `max` == 0
+    pub fn try_new(value: T, max: usize) -> Result<Self, SemaphoreError> {
+        if max == 0 {
+            return Err(SemaphoreError::InvalidMax);
+        }
+        Ok(Semaphore::new(value, max))
+    }
+
+    /// Create a new semaphore that starts with `initial` permits taken, see [`raw::Semaphore::with_initial`]
+    ///
+    /// Same as [`SemaphoreBuilder::initial_count`], hand the permits back with [`Semaphore::release_initial`]
+    /// # Panics
+    /// This function will panic if `initial` > `max`
+    pub fn with_initial(value: T, max: usize, initial: usize) -> Self {
+        SemaphoreBuilder::new()
+            .max(max)
+            .initial_count(initial)
+            .build(value)
+    }
+
+    /// Start building a semaphore, see [`SemaphoreBuilder`]
+    pub fn builder() -> SemaphoreBuilder<T> {
+        SemaphoreBuilder::new()
+    }
+
+    /// Create a new semaphore with 0 counted references and a max that can't be 0
+    ///
+    /// Taking a [`NonZeroUsize`][`core::num::NonZeroUsize`] rules out the semaphore that can never be acquired,
+    /// so [`Semaphore::get`] can't loop forever
+    pub fn new_nonzero(value: T, max: core::num::NonZeroUsize) -> Self {
+        Semaphore {
+            raw: raw::Semaphore::new(max.get()),
+            data: value,
+        }
+    }
+
+    /// Create a new semaphore that serves blocked [`Semaphore::get`] calls in arrival order,
+    /// see [`raw::Semaphore::new_fair`] for the tradeoff
+    #[cfg(feature = "std")]
+    pub fn new_fair(value: T, max: usize) -> Self {
+        SemaphoreBuilder::new().max(max).fair(true).build(value)
+    }
+
+    /// Create a new semaphore that hands out slot indexes, see [`raw::Semaphore::new_indexed`]
+    /// # Panics
+    /// This function will panic if `max` > [`usize::BITS`]
+    pub fn new_indexed(value: T, max: usize) -> Self {
+        Semaphore {
+            raw: raw::Semaphore::new_indexed(max),
+            data: value,
+        }
+    }
+
+    /// Create a new semaphore with a max of 1, see [`Mutex1`]
+    pub fn new_mutex(value: T) -> Mutex1<T> {
+        Mutex1::new(value)
+    }
+
+    /// Create a new semaphore with a max of [`usize::MAX`], see [`raw::Semaphore::unbounded`]
+    pub fn unbounded(value: T) -> Self {
+        Semaphore {
+            raw: raw::Semaphore::unbounded(),
+            data: value,
+        }
+    }
+
+    /// Create a new semaphore that reports to the `metrics` facade, see [`raw::Semaphore::new_with_metrics`]
+    #[cfg(feature = "metrics")]
+    pub fn new_with_metrics(value: T, max: usize, prefix: &str) -> Self {
+        Semaphore {
+            raw: raw::Semaphore::new_with_metrics(max, prefix),
+            data: value,
+        }
+    }
+
+    /// Create a new semaphore whose waiter queues allocate from `alloc`, see [`raw::Semaphore::new_in`]
+    ///
+    /// Requires the `allocator_api` feature and a nightly compiler
+    #[cfg(feature = "allocator_api")]
+    pub fn new_in(
+        value: T,
+        max: usize,
+        alloc: &'static (dyn core::alloc::Allocator + Sync),
+    ) -> Self {
+        Semaphore {
+            raw: raw::Semaphore::new_in(max, alloc),
+            data: value,
+        }
+    }
+
+    /// Create a new semaphore whose blocking acquires wait with `parker`, see [`raw::Semaphore::new_with_parker`]
+    pub fn new_with_parker(value: T, max: usize, parker: &'static dyn crate::Parker) -> Self {
+        Semaphore {
+            raw: raw::Semaphore::new_with_parker(max, parker),
+            data: value,
+        }
+    }
+
+    /// Create a new semaphore that allows at most `total` acquires ever, see [`raw::Semaphore::new_with_total_cap`]
+    pub fn new_with_total_cap(value: T, max: usize, total: usize) -> Self {
+        Semaphore {
+            raw: raw::Semaphore::new_with_total_cap(max, total),
+            data: value,
+        }
+    }
+
+    /// Create a new semaphore that becomes poisoned if a guard is dropped while panicking
+    #[cfg(feature = "std")]
+    pub fn new_poisoning(value: T, max: usize) -> Self {
+        debug_assert_ne!(
+            max, 0,
+            "A semaphore with a maximum count of '0' generally useless"
+        );
+
+        Semaphore {
+            raw: raw::Semaphore::new_poisoning(max),
+            data: value,
+        }
+    }
+
+    /// Move the value out of the semaphore
+    ///
+    /// Guards borrow the semaphore, so none can be alive here. The count can still be above 0 from
+    /// forgotten or leaked guards, which this ignores, use [`Semaphore::try_into_inner`] to check for them
+    pub fn into_inner(self) -> T {
+        self.data
+    }
+
+    /// Move the value out of the semaphore if no permits are held
+    /// # Errors
+    /// Returns the semaphore unchanged if the count isn't 0, like after [`SemaphoreGuard::forget`]
+    #[allow(clippy::result_large_err)] // Handing the semaphore back is the point, like `Arc::try_unwrap`
+    pub fn try_into_inner(self) -> Result<T, Self> {
+        if self.raw.count(Ordering::SeqCst) == 0 {
+            Ok(self.data)
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Box the semaphore and leak it, like [`Box::leak`], for a limiter that lives as long as the program
+    ///
+    /// The memory is never freed and the data is never dropped, so this is meant for semaphores
+    /// set up once at startup, not ones created over and over
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn leak(self) -> &'static Semaphore<T>
+    where
+        T: 'static,
+    {
+        Box::leak(Box::new(self))
+    }
+
+    /// Put `value` in the semaphore and return the old value
+    ///
+    /// `&mut self` means no guard is alive, so the count isn't touched
+    pub fn replace(&mut self, value: T) -> T {
+        core::mem::replace(&mut self.data, value)
+    }
+
+    /// Take the value out of the semaphore, leaving [`Default::default`] in its place
+    pub fn take(&mut self) -> T
+    where
+        T: Default,
+    {
+        core::mem::take(&mut self.data)
+    }
+
+    /// Split the semaphore into its counter and its data, see [`Semaphore::from_parts`]
+    pub fn into_parts(self) -> (raw::Semaphore, T) {
+        (self.raw, self.data)
+    }
+
+    /// Put a semaphore back together from a counter and data, keeping the counter's count, max and settings
+    pub fn from_parts(raw: raw::Semaphore, data: T) -> Self {
+        Semaphore { raw, data }
+    }
+}
+
+/// Shows a relaxed snapshot of the count next to the max, the data is left out so `T` doesn't need to be `Debug`
+impl<T: ?Sized> core::fmt::Debug for Semaphore<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Semaphore")
+            .field("count", &self.count(Ordering::Relaxed))
+            .field("max", &self.max())
+            .finish_non_exhaustive()
+    }
+}
+
+unsafe impl<T: ?Sized + Send> Send for Semaphore<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for Semaphore<T> {}
+
+/// Only the data and the max are written, the count belongs to guards that can't be serialized
+#[cfg(feature = "serde")]
+impl<T: ?Sized + serde::Serialize> serde::Serialize for Semaphore<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("Semaphore", 2)?;
+        state.serialize_field("data", &self.data)?;
+        state.serialize_field("max", &self.max())?;
+        state.end()
+    }
+}
+
+/// The fields written by the `Serialize` impl of [`Semaphore`]
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(rename = "Semaphore")]
+struct SerializedSemaphore<T> {
+    data: T,
+    max: usize,
+}
+
+/// Always creates a fresh semaphore with a count of 0, no guard from before serializing is restored.
+/// A max of 0 is rejected, like with [`Semaphore::try_new`]
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for Semaphore<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let SerializedSemaphore { data, max } = SerializedSemaphore::deserialize(deserializer)?;
+        Semaphore::try_new(data, max).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Collects the options for a [`Semaphore`] before it is created, see [`Semaphore::builder`]
+///
+/// ```
+/// let semaphore = semaphorus::Semaphore::builder().max(4).initial_count(1).build("data");
+/// assert_eq!(semaphore.available(core::sync::atomic::Ordering::SeqCst), 3);
+/// ```
+#[must_use = "a builder does nothing until 'build' is called"]
+pub struct SemaphoreBuilder<T> {
+    max: Option<usize>,
+    initial_count: usize,
+    wait_strategy: WaitStrategy,
+    #[cfg(feature = "std")]
+    fair: bool,
+    _value: core::marker::PhantomData<fn(T) -> T>,
+}
+
+impl<T> SemaphoreBuilder<T> {
+    /// Create a builder with no max, no fairness, an initial count of 0 and the default wait strategy
+    pub fn new() -> Self {
+        SemaphoreBuilder {
+            max: None,
+            initial_count: 0,
+            wait_strategy: WaitStrategy::default(),
+            #[cfg(feature = "std")]
+            fair: false,
+            _value: core::marker::PhantomData,
+        }
+    }
+
+    /// Set the maximum number of references, this has to be set before [`SemaphoreBuilder::build`]
+    pub fn max(mut self, max: usize) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    /// Serve blocked acquires in arrival order, see [`raw::Semaphore::new_fair`]
+    #[cfg(feature = "std")]
+    pub fn fair(mut self, fair: bool) -> Self {
+        self.fair = fair;
+        self
+    }
+
+    /// Wait with `strategy` in blocking acquires, see [`Semaphore::set_wait_strategy`]
+    pub fn wait_strategy(mut self, strategy: WaitStrategy) -> Self {
+        self.wait_strategy = strategy;
+        self
+    }
+
+    /// Start with `count` permits taken, hand them back with [`Semaphore::release_initial`]
+    pub fn initial_count(mut self, count: usize) -> Self {
+        self.initial_count = count;
+        self
+    }
+
+    /// Create the semaphore holding `value`
+    /// # Panics
+    /// This function will panic if no max was set or if the initial count is above the max.
+    /// Debug builds also panic if the max is 0
+    pub fn build(self, value: T) -> Semaphore<T> {
+        let max = self
+            .max
+            .expect("Calling 'SemaphoreBuilder::build' without setting a max");
+        debug_assert_ne!(
+            max, 0,
+            "A semaphore with a maximum count of '0' generally useless"
+        );
+        assert!(
+            self.initial_count <= max,
+            "Calling 'SemaphoreBuilder::build' with an initial count of {} above the max of {max}",
+            self.initial_count
+        );
+
+        #[cfg(feature = "std")]
+        let raw = if self.fair {
+            raw::Semaphore::new_fair(max)
+        } else {
+            raw::Semaphore::new(max)
+        };
+        #[cfg(not(feature = "std"))]
+        let raw = raw::Semaphore::new(max);
+        raw.set_wait_strategy(self.wait_strategy);
+        Semaphore {
+            raw: raw.with_initial_count(self.initial_count),
+            data: value,
+        }
+    }
+}
+
+impl<T> Default for SemaphoreBuilder<T> {
+    fn default() -> Self {
+        SemaphoreBuilder::new()
+    }
+}
+
+/// A [`Semaphore`] with a max of 1, which gives one holder at a time shared access like a read-only mutex
+///
+/// The max is fixed when it is created, so unlike a [`Semaphore`] it can't be built or resized
+/// to let more than one holder in. Acquiring a max of 1 is a single `0 -> 1` compare-and-swap
+pub struct Mutex1<T: ?Sized> {
+    semaphore: Semaphore<T>,
+}
+
+impl<T: ?Sized> Mutex1<T> {
+    /// Attempt to lock the mutex, like [`std::sync::Mutex::try_lock`]
+    ///
+    /// This function will never block
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if the mutex is locked
+    #[inline]
+    #[must_use = "if unused, a successful lock will immediatly unlock"]
+    pub fn try_lock(&self) -> Result<SemaphoreGuard<'_, T>, SemaphoreError> {
+        self.semaphore.try_get()
+    }
+
+    /// Lock the mutex, blocking until the holder lets go, like [`std::sync::Mutex::lock`]
+    /// # Panics
+    /// Panics in the same cases as [`Semaphore::get`]
+    #[inline]
+    pub fn lock(&self) -> SemaphoreGuard<'_, T> {
+        self.semaphore.get()
+    }
+
+    /// Returns true if the mutex is locked
+    #[must_use]
+    pub fn is_locked(&self, ordering: Ordering) -> bool {
+        self.semaphore.at_max(ordering)
+    }
+
+    /// Get a mutable reference to the data in the mutex
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.semaphore.get_mut()
+    }
+}
+
+impl<T> Mutex1<T> {
+    /// Create a new unlocked mutex
+    pub fn new(value: T) -> Self {
+        Mutex1 {
+            semaphore: Semaphore::new(value, 1),
+        }
+    }
+
+    /// Move the value out of the mutex
+    pub fn into_inner(self) -> T {
+        self.semaphore.into_inner()
+    }
+}
+
+impl<T: ?Sized> core::fmt::Debug for Mutex1<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Mutex1")
+            .field("locked", &self.is_locked(Ordering::Relaxed))
+            .finish_non_exhaustive()
+    }
+}
+
+/// A wrapper around a reference to the data in the semaphore
+/// Automatically decrements the reference count when it is dropped
+/// For mutable access, consider using a [cell][`std::cell`] type or use [`Semaphore::get_mut`]
+#[must_use = "if unused, the guard will immediatly unlock"]
+pub struct SemaphoreGuard<'guard, T: ?Sized> {
+    _inner: raw::SemaphoreGuard<'guard>,
+    semaphore: &'guard Semaphore<T>,
+}
+
+impl<'guard, T: ?Sized> SemaphoreGuard<'guard, T> {
+    /// Create a guard around a `Semaphore`, and increment the reference count
+    #[inline]
+    pub(crate) fn new(
+        raw_guard: raw::SemaphoreGuard<'guard>,
+        semaphore: &'guard Semaphore<T>,
+    ) -> Self {
+        SemaphoreGuard {
+            _inner: raw_guard,
+            semaphore,
+        }
+    }
+
+    /// Get the semaphore this guard was taken from, to check its count or permits while holding one
+    #[must_use]
+    pub fn semaphore(&self) -> &'guard Semaphore<T> {
+        self.semaphore
+    }
+
+    /// Get the process-unique id assigned when the permit was acquired, see [`raw::SemaphoreGuard::id`]
+    #[cfg(feature = "guard_ids")]
+    #[must_use]
+    pub fn id(&self) -> u64 {
+        let SemaphoreGuard { _inner: inner, .. } = self;
+        inner.id()
+    }
+
+    /// Get how many permits this guard holds, more than 1 for [`Semaphore::try_get_many`] and [`Semaphore::get_many`]
+    #[must_use]
+    pub fn permits(&self) -> usize {
+        let SemaphoreGuard { _inner: inner, .. } = self;
+        inner.permits()
+    }
+
+    /// Drop the guard but keep its permits held forever, see [`raw::SemaphoreGuard::forget`]
+    ///
+    /// The semaphore has that many fewer permits available until [`Semaphore::add_permits`] is called
+    pub fn forget(self) {
+        let SemaphoreGuard { _inner: inner, .. } = self;
+        inner.forget();
+    }
+
+    /// Hand one permit back early and keep the rest, see [`raw::SemaphoreGuard::release_one`]
+    pub fn release_one(&mut self) {
+        let SemaphoreGuard { _inner: inner, .. } = self;
+        inner.release_one();
+    }
+
+    /// Hand `permits` back early and keep the rest, see [`raw::SemaphoreGuard::release_many`]
+    ///
+    /// Asking for more than the guard holds releases all of them
+    pub fn release_many(&mut self, permits: usize) {
+        let SemaphoreGuard { _inner: inner, .. } = self;
+        inner.release_many(permits);
+    }
+
+    /// Try to turn this guard into one that holds every permit, see [`raw::SemaphoreGuard::try_promote`]
+    ///
+    /// Like upgrading a read lock, this only works while no other guard is alive.
+    /// Concurrent promotions never deadlock, they both fail and keep their guards
+    /// # Errors
+    /// Returns the guard unchanged if any other permit is held
+    pub fn try_promote(self) -> Result<Self, Self> {
+        let SemaphoreGuard {
+            _inner: inner,
+            semaphore,
+        } = self;
+        inner
+            .try_promote()
+            .map(|inner| SemaphoreGuard::new(inner, semaphore))
+            .map_err(|inner| SemaphoreGuard::new(inner, semaphore))
+    }
+
+    /// Keep a single permit and release the rest, see [`raw::SemaphoreGuard::downgrade`]
+    ///
+    /// The dual of [`SemaphoreGuard::try_promote`], for writing with every permit and then reading alongside others
+    pub fn downgrade(self) -> Self {
+        let SemaphoreGuard {
+            _inner: inner,
+            semaphore,
+        } = self;
+        SemaphoreGuard::new(inner.downgrade(), semaphore)
+    }
+
+    /// Attach `f` to the guard, to run right after the permit is released
+    ///
+    /// The closure is stored in the returned guard without boxing. It also runs when the guard
+    /// is dropped during a panic, even if releasing the permit panics
+    pub fn on_drop<F: FnOnce()>(self, f: F) -> OnDropGuard<'guard, T, F> {
+        OnDropGuard {
+            guard: self,
+            _deferred: Deferred(Some(f)),
+        }
+    }
+
+    /// Turn the guard into a [`SharedRef`] that can be cloned cheaply
+    ///
+    /// Every clone shares this guard's single permit, which is only released once the last clone is dropped
+    #[cfg(feature = "std")]
+    pub fn into_shared(self) -> SharedRef<'guard, T> {
+        let SemaphoreGuard {
+            _inner: inner,
+            semaphore,
+        } = self;
+        SharedRef {
+            _inner: std::rc::Rc::new(inner),
+            data: &semaphore.data,
+        }
+    }
+
+    /// Narrow the guard to part of the data, like [`std::sync::RwLockReadGuard::map`]
+    ///
+    /// The permit moves into the returned guard and stays held until it is dropped
+    pub fn map<U: ?Sized, F: FnOnce(&T) -> &U>(
+        guard: Self,
+        f: F,
+    ) -> MappedSemaphoreGuard<'guard, U> {
+        let SemaphoreGuard {
+            _inner: inner,
+            semaphore,
+        } = guard;
+        MappedSemaphoreGuard {
+            _inner: inner,
+            data: f(&semaphore.data),
+        }
+    }
+
+    /// Like [`SemaphoreGuard::map`], but `f` can decline to narrow the guard
+    /// # Errors
+    /// Returns the guard unchanged if `f` returns `None`
+    pub fn try_map<U: ?Sized, F: FnOnce(&T) -> Option<&U>>(
+        guard: Self,
+        f: F,
+    ) -> Result<MappedSemaphoreGuard<'guard, U>, Self> {
+        let Some(data) = f(&guard.semaphore.data) else {
+            return Err(guard);
+        };
+        let SemaphoreGuard { _inner: inner, .. } = guard;
+        Ok(MappedSemaphoreGuard {
+            _inner: inner,
+            data,
+        })
+    }
+}
+
+impl<T: ?Sized> Deref for SemaphoreGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.semaphore.data
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for SemaphoreGuard<'_, T> {
+    #[inline]
+    fn as_ref(&self) -> &T {
+        &self.semaphore.data
+    }
+}
+
+/// Lets a guard around a socket or file be registered with `poll` or `epoll` while the permit is held
+#[cfg(all(unix, feature = "std"))]
+impl<T: ?Sized + std::os::fd::AsRawFd> std::os::fd::AsRawFd for SemaphoreGuard<'_, T> {
+    #[inline]
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        self.semaphore.data.as_raw_fd()
+    }
+}
+
+#[cfg(all(unix, feature = "std"))]
+impl<T: ?Sized + std::os::fd::AsFd> std::os::fd::AsFd for SemaphoreGuard<'_, T> {
+    #[inline]
+    fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+        self.semaphore.data.as_fd()
+    }
+}
+
+impl<T: ?Sized> core::borrow::Borrow<T> for SemaphoreGuard<'_, T> {
+    #[inline]
+    fn borrow(&self) -> &T {
+        &self.semaphore.data
+    }
+}
+
+/// Compares the data the guard refers to with `other`
+impl<T: ?Sized + PartialEq> PartialEq<T> for SemaphoreGuard<'_, T> {
+    fn eq(&self, other: &T) -> bool {
+        self.semaphore.data == *other
+    }
+}
+
+/// Compares the data the guards refer to, guards from different semaphores are equal if their data is
+impl<'other, T: ?Sized + PartialEq> PartialEq<SemaphoreGuard<'other, T>> for SemaphoreGuard<'_, T> {
+    fn eq(&self, other: &SemaphoreGuard<'other, T>) -> bool {
+        self.semaphore.data == other.semaphore.data
+    }
+}
+
+impl<T: ?Sized + Eq> Eq for SemaphoreGuard<'_, T> {}
+
+/// Formats the data the guard refers to
+impl<T: ?Sized + core::fmt::Debug> core::fmt::Debug for SemaphoreGuard<'_, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self.semaphore.data, f)
+    }
+}
+
+/// Forwards indexing to the data, so `guard[i]` works while the permit is held
+impl<T: ?Sized + core::ops::Index<I>, I> core::ops::Index<I> for SemaphoreGuard<'_, T> {
+    type Output = T::Output;
+
+    #[inline]
+    fn index(&self, index: I) -> &Self::Output {
+        &self.semaphore.data[index]
+    }
+}
+unsafe impl<T: ?Sized + Sync> Sync for SemaphoreGuard<'_, T> {}
+
+/// A guard that reaches part of the data in the semaphore, created by [`SemaphoreGuard::map`]
+#[must_use = "if unused, the guard will immediatly unlock"]
+pub struct MappedSemaphoreGuard<'guard, U: ?Sized> {
+    _inner: raw::SemaphoreGuard<'guard>,
+    data: &'guard U,
+}
+
+impl<U: ?Sized> Deref for MappedSemaphoreGuard<'_, U> {
+    type Target = U;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.data
+    }
+}
+unsafe impl<U: ?Sized + Sync> Sync for MappedSemaphoreGuard<'_, U> {}
+
+/// A [`SemaphoreGuard`] that runs a closure after its permit is released, created by [`SemaphoreGuard::on_drop`]
+#[must_use = "if unused, the guard will immediatly unlock"]
+pub struct OnDropGuard<'guard, T: ?Sized, F: FnOnce()> {
+    // Fields are dropped in order, so the permit is released before the closure runs
+    // and an unwind out of the release still drops `_deferred`
+    guard: SemaphoreGuard<'guard, T>,
+    _deferred: Deferred<F>,
+}
+
+impl<T: ?Sized, F: FnOnce()> Deref for OnDropGuard<'_, T, F> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+/// A guard from [`Semaphore::get_pin`] that gives pinned access to the data
+#[must_use = "if unused, the guard will immediatly unlock"]
+pub struct PinnedGuard<'guard, T: ?Sized> {
+    guard: SemaphoreGuard<'guard, T>,
+}
+
+impl<T: ?Sized> PinnedGuard<'_, T> {
+    /// Get a pinned reference to the data
+    #[must_use]
+    pub fn as_pin(&self) -> core::pin::Pin<&T> {
+        // SAFETY: the guard was created from a pinned semaphore, and the data is structurally pinned in it
+        unsafe { core::pin::Pin::new_unchecked(&self.guard.semaphore.data) }
+    }
+}
+
+impl<T: ?Sized> Deref for PinnedGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+/// Runs the closure when dropped
+struct Deferred<F: FnOnce()>(Option<F>);
+
+impl<F: FnOnce()> Drop for Deferred<F> {
+    fn drop(&mut self) {
+        if let Some(f) = self.0.take() {
+            f();
+        }
+    }
+}
+
+/// A cloneable reference to the data in the semaphore, created by [`SemaphoreGuard::into_shared`]
+///
+/// All clones hold the same permit, so cloning doesn't touch the semaphore's count.
+/// The permit stays held until every clone has been dropped
+#[cfg(feature = "std")]
+#[must_use = "if unused, the guard will immediatly unlock"]
+pub struct SharedRef<'guard, T: ?Sized> {
+    _inner: std::rc::Rc<raw::SemaphoreGuard<'guard>>,
+    data: &'guard T,
+}
+
+#[cfg(feature = "std")]
+impl<T: ?Sized> Clone for SharedRef<'_, T> {
+    fn clone(&self) -> Self {
+        let SharedRef {
+            _inner: inner,
+            data,
+        } = self;
+        SharedRef {
+            _inner: std::rc::Rc::clone(inner),
+            data,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: ?Sized> Deref for SharedRef<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.data
+    }
+}
+
+/// A cloneable handle to a shared [`Semaphore`], for types that want to `#[derive(Clone)]`
+///
+/// This is just an `Arc<Semaphore<T>>` whose guards are the `'static` [`OwnedSemaphoreGuard`]s,
+/// the rest of the semaphore is available through [`Deref`]
+#[cfg(feature = "std")]
+pub struct ArcSemaphore<T: ?Sized> {
+    semaphore: Arc<Semaphore<T>>,
+}
+
+#[cfg(feature = "std")]
+impl<T> ArcSemaphore<T> {
+    /// Create a new shared semaphore, see [`Semaphore::new`]
+    #[must_use]
+    pub fn new(value: T, max: usize) -> Self {
+        ArcSemaphore::from(Arc::new(Semaphore::new(value, max)))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: ?Sized> ArcSemaphore<T> {
+    /// Attempt to get a guard that keeps the semaphore alive, see [`Semaphore::try_get_owned`]
+    /// # Errors
+    /// This function will return [`SemaphoreError::AtMaxCount`] if the current count is >= the maximum count,
+    /// or [`SemaphoreError::Poisoned`] if the semaphore is poisoned
+    pub fn try_get_owned(&self) -> Result<OwnedSemaphoreGuard<T>, SemaphoreError> {
+        self.semaphore.try_get_owned()
+    }
+
+    /// Wait for a guard that keeps the semaphore alive, see [`Semaphore::acquire_owned`]
+    #[cfg(feature = "async")]
+    pub fn acquire_owned(&self) -> AcquireOwned<T> {
+        self.semaphore.acquire_owned()
+    }
+
+    /// Get the `Arc` this handle wraps
+    #[must_use]
+    pub fn as_arc(&self) -> &Arc<Semaphore<T>> {
+        &self.semaphore
+    }
+
+    /// Unwrap the handle into its `Arc`
+    #[must_use]
+    pub fn into_arc(self) -> Arc<Semaphore<T>> {
+        self.semaphore
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: ?Sized> Clone for ArcSemaphore<T> {
+    fn clone(&self) -> Self {
+        ArcSemaphore {
+            semaphore: Arc::clone(&self.semaphore),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: ?Sized> From<Arc<Semaphore<T>>> for ArcSemaphore<T> {
+    fn from(semaphore: Arc<Semaphore<T>>) -> Self {
+        ArcSemaphore { semaphore }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: ?Sized> Deref for ArcSemaphore<T> {
+    type Target = Semaphore<T>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.semaphore
+    }
+}
+
+/// A guard that holds an [`Arc`] to its semaphore instead of borrowing it
+/// Automatically decrements the reference count when it is dropped
+#[cfg(feature = "std")]
+#[must_use = "if unused, the guard will immediatly unlock"]
+pub struct OwnedSemaphoreGuard<T: ?Sized> {
+    semaphore: Arc<Semaphore<T>>,
+    /// Created by the first call to [`OwnedSemaphoreGuard::release_notify`]
+    #[cfg(feature = "async")]
+    released: std::sync::OnceLock<Arc<ReleaseSignal>>,
+}
+
+#[cfg(feature = "std")]
+impl<T: ?Sized> OwnedSemaphoreGuard<T> {
+    /// Create a guard for a permit that has already been taken from `semaphore`
+    fn acquired(semaphore: Arc<Semaphore<T>>) -> Self {
+        OwnedSemaphoreGuard {
+            semaphore,
+            #[cfg(feature = "async")]
+            released: std::sync::OnceLock::new(),
+        }
+    }
+
+    /// Get the semaphore this guard was acquired from
+    #[must_use]
+    pub fn semaphore(&self) -> &Arc<Semaphore<T>> {
+        &self.semaphore
+    }
+
+    /// Get a future that completes once this guard is dropped and its permit released
+    ///
+    /// The future doesn't borrow the guard, so it can be handed to another task that
+    /// has to react to this specific permit being released
+    #[cfg(feature = "async")]
+    pub fn release_notify(&self) -> ReleaseNotify {
+        ReleaseNotify {
+            signal: Arc::clone(self.released.get_or_init(Arc::default)),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: ?Sized> Deref for OwnedSemaphoreGuard<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.semaphore.data
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: ?Sized> Drop for OwnedSemaphoreGuard<T> {
+    fn drop(&mut self) {
+        self.semaphore.raw.release();
+        #[cfg(feature = "async")]
+        if let Some(signal) = self.released.get() {
+            signal.notify();
+        }
+    }
+}
+
+/// Shared between an [`OwnedSemaphoreGuard`] and the [`ReleaseNotify`] futures it hands out
+#[cfg(feature = "async")]
+#[derive(Default)]
+struct ReleaseSignal {
+    released: core::sync::atomic::AtomicBool,
+    wakers: std::sync::Mutex<Vec<core::task::Waker>>,
+}
+
+#[cfg(feature = "async")]
+impl ReleaseSignal {
+    fn notify(&self) {
+        self.released.store(true, Ordering::SeqCst);
+        let wakers = core::mem::take(&mut *crate::lock_ignoring_poison(&self.wakers));
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+/// The future returned by [`OwnedSemaphoreGuard::release_notify`]
+#[cfg(feature = "async")]
+#[must_use = "futures do nothing unless polled"]
+pub struct ReleaseNotify {
+    signal: Arc<ReleaseSignal>,
+}
+
+#[cfg(feature = "async")]
+impl Future for ReleaseNotify {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.signal.released.load(Ordering::SeqCst) {
+            return Poll::Ready(());
+        }
+
+        let mut wakers = crate::lock_ignoring_poison(&self.signal.wakers);
+        if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            wakers.push(cx.waker().clone());
+        }
+        drop(wakers);
+
+        // The guard may have been dropped before the waker was registered
+        if self.signal.released.load(Ordering::SeqCst) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Move a held permit into a new thread, which releases it when `f` is done with the guard
+///
+/// Borrowed [`SemaphoreGuard`]s can't be sent to other threads,
+/// so the permit is handed over as an [`OwnedSemaphoreGuard`]
+#[cfg(feature = "std")]
+pub fn spawn_with_guard<T, F, R>(guard: OwnedSemaphoreGuard<T>, f: F) -> std::thread::JoinHandle<R>
+where
+    T: ?Sized + Send + Sync + 'static,
+    F: FnOnce(OwnedSemaphoreGuard<T>) -> R + Send + 'static,
+    R: Send + 'static,
+{
+    std::thread::spawn(move || f(guard))
+}
+
+/// The iterator returned by [`Semaphore::get_iter`], every guard it yields releases its own permit
+#[must_use = "iterators are lazy and do nothing unless consumed"]
+pub struct GetIter<'guard, T: ?Sized> {
+    semaphore: &'guard Semaphore<T>,
+}
+
+impl<'guard, T: ?Sized> Iterator for GetIter<'guard, T> {
+    type Item = SemaphoreGuard<'guard, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.semaphore.get_or_shutdown().ok()
+    }
+}
+
+/// Returned by [`Semaphore::try_get_or_else`], either the guard or what the fallback returned
+#[derive(Debug)]
+pub enum Either<L, R> {
+    /// The acquire succeeded
+    Left(L),
+    /// The acquire failed and the fallback ran
+    Right(R),
+}
+
+impl<L, R> Either<L, R> {
+    /// Get the left value, dropping the right one
+    pub fn left(self) -> Option<L> {
+        match self {
+            Either::Left(left) => Some(left),
+            Either::Right(_) => None,
+        }
+    }
+
+    /// Get the right value, dropping the left one
+    pub fn right(self) -> Option<R> {
+        match self {
+            Either::Left(_) => None,
+            Either::Right(right) => Some(right),
+        }
+    }
+}
+
+/// The future returned by [`Semaphore::acquire`]
+#[cfg(any(feature = "async", feature = "embassy"))]
+#[must_use = "futures do nothing unless polled"]
+pub struct Acquire<'guard, T: ?Sized> {
+    raw: raw::Acquire<'guard>,
+    semaphore: &'guard Semaphore<T>,
+}
+
+#[cfg(any(feature = "async", feature = "embassy"))]
+impl<'guard, T: ?Sized> Future for Acquire<'guard, T> {
+    type Output = Result<SemaphoreGuard<'guard, T>, SemaphoreError>;
+
+    /// # Panics
+    /// Panics if polled after completion
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let semaphore = self.semaphore;
+        Pin::new(&mut self.raw)
+            .poll(cx)
+            .map_ok(|guard| SemaphoreGuard::new(guard, semaphore))
+    }
+}
+
+/// The future returned by [`Semaphore::acquire_until`], resolves to `Ok(None)` if the deadline completes first
+#[cfg(any(feature = "async", feature = "embassy"))]
+#[must_use = "futures do nothing unless polled"]
+pub struct AcquireUntil<'guard, T: ?Sized, D> {
+    /// Dropped as soon as the deadline completes, giving up its place in the queue
+    acquire: Option<Acquire<'guard, T>>,
+    deadline: D,
+}
+
+#[cfg(any(feature = "async", feature = "embassy"))]
+impl<'guard, T: ?Sized, D: Future> Future for AcquireUntil<'guard, T, D> {
+    type Output = Result<Option<SemaphoreGuard<'guard, T>>, SemaphoreError>;
+
+    /// # Panics
+    /// Panics if polled after completion
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: `deadline` is structurally pinned and never moved out of `self`,
+        // the acquire is `Unpin`
+        let this = unsafe { self.get_unchecked_mut() };
+        let acquire = this
+            .acquire
+            .as_mut()
+            .expect("AcquireUntil polled after completion");
+
+        if let Poll::Ready(result) = Pin::new(acquire).poll(cx) {
+            this.acquire = None;
+            return Poll::Ready(result.map(Some));
+        }
+        // SAFETY: `this` came from a pinned `self` and `deadline` is never moved out of it,
+        // so it stays at this address until the `AcquireUntil` is dropped
+        let deadline = unsafe { Pin::new_unchecked(&mut this.deadline) };
+        if deadline.poll(cx).is_ready() {
+            this.acquire = None;
+            return Poll::Ready(Ok(None));
+        }
+        Poll::Pending
+    }
+}
+
+/// The future returned by [`Semaphore::acquire_owned`]
+#[cfg(feature = "async")]
+#[must_use = "futures do nothing unless polled"]
+pub struct AcquireOwned<T: ?Sized> {
+    /// Taken once the permit has been acquired
+    semaphore: Option<Arc<Semaphore<T>>>,
+    entry: crate::wait::WaitEntry,
+}
+
+#[cfg(feature = "async")]
+impl<T: ?Sized> Future for AcquireOwned<T> {
+    type Output = Result<OwnedSemaphoreGuard<T>, SemaphoreError>;
+
+    /// # Panics
+    /// Panics if polled after completion
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let semaphore = this
+            .semaphore
+            .as_ref()
+            .expect("AcquireOwned polled after completion");
+
+        match semaphore.raw.poll_acquire(cx, &mut this.entry, 1) {
+            Poll::Ready(result) => {
+                // Taken either way, a failed poll already gave up its place in the queue
+                let semaphore = this.semaphore.take().unwrap();
+                Poll::Ready(result.map(|()| OwnedSemaphoreGuard::acquired(semaphore)))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T: ?Sized> Drop for AcquireOwned<T> {
+    fn drop(&mut self) {
+        if let Some(semaphore) = &self.semaphore {
+            semaphore.raw.cancel_acquire(&mut self.entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guard_forwards_index() {
+        let semaphore = Semaphore::new(vec![1_u8, 2, 3], 1);
+
+        let guard = semaphore.get();
+        assert_eq!(guard[0], 1);
+        assert_eq!(guard[1..], [2, 3]);
+    }
+
+    #[test]
+    fn test_new_nonzero_keeps_max() {
+        let max = core::num::NonZeroUsize::new(2).unwrap();
+        let semaphore = Semaphore::new_nonzero((), max);
+
+        let _first = semaphore.get();
+        let _second = semaphore.get();
+        assert!(semaphore.at_max(Ordering::SeqCst));
+        assert_eq!(semaphore.available(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_with_initial_at_max_rejects_until_a_drop() {
+        let semaphore = Semaphore::with_initial((), 2, 1);
+        let guard = semaphore.try_get().unwrap();
+        assert!(semaphore.try_get().is_err());
+
+        drop(guard);
+        assert!(semaphore.try_get().is_ok());
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_panicking_holder_is_unwind_safe() {
+        fn assert_unwind_safe<T: std::panic::UnwindSafe + std::panic::RefUnwindSafe>() {}
+        assert_unwind_safe::<raw::Semaphore>();
+        assert_unwind_safe::<Semaphore<i32>>();
+
+        let semaphore = Semaphore::new(1, 2);
+        // No `AssertUnwindSafe`, the semaphore can be borrowed across the unwind as is
+        let panicked = std::panic::catch_unwind(|| {
+            semaphore.with(|_| panic!("the holder failed"));
+        });
+        assert!(panicked.is_err());
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+        assert_eq!(semaphore.with(|data| *data), 1);
+    }
+
+    #[test]
+    fn test_try_new_rejects_a_zero_max() {
+        assert_eq!(
+            Semaphore::try_new((), 0).unwrap_err(),
+            SemaphoreError::InvalidMax
+        );
+
+        let semaphore = Semaphore::try_new((), 4).unwrap();
+        assert_eq!(semaphore.max(), 4);
+        assert!(semaphore.try_get().is_ok());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_shared_refs_hold_one_permit() {
+        let semaphore = Semaphore::new(vec![1, 2, 3], 2);
+
+        let shared = semaphore.try_get().unwrap().into_shared();
+        let clones = [shared.clone(), shared.clone()];
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+        assert_eq!(clones[1][..], [1, 2, 3]);
+
+        drop(shared);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+        drop(clones);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_changing_wait_strategy_while_waiting() {
+        let semaphore = Semaphore::new((), 1);
+        semaphore.set_wait_strategy(WaitStrategy::Yield);
+        let guard = semaphore.get();
+
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| drop(semaphore.get()));
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            semaphore.set_wait_strategy(WaitStrategy::Spin);
+            drop(guard);
+            waiter.join().unwrap();
+        });
+
+        assert_eq!(semaphore.wait_strategy(), WaitStrategy::Spin);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_parked_waiters_are_unparked() {
+        let semaphore = Semaphore::new(std::sync::atomic::AtomicUsize::new(0), 1);
+        semaphore.set_wait_strategy(WaitStrategy::Park);
+
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        semaphore.get().fetch_add(1, Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+        assert_eq!(semaphore.try_get().unwrap().load(Ordering::SeqCst), 400);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_try_get_for_succeeds_immediately() {
+        let semaphore = Semaphore::new(5, 1);
+
+        assert_eq!(
+            *semaphore.try_get_for(core::time::Duration::ZERO).unwrap(),
+            5
+        );
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_try_get_for_succeeds_after_release() {
+        let semaphore = Semaphore::new((), 1);
+        let guard = semaphore.get();
+
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| {
+                semaphore
+                    .try_get_for(std::time::Duration::from_secs(10))
+                    .map(drop)
+            });
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            drop(guard);
+            assert!(waiter.join().unwrap().is_ok());
+        });
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_try_get_for_times_out_without_overshooting() {
+        let semaphore = Semaphore::new((), 1);
+        let _guard = semaphore.get();
+        // Each sleep is far longer than the timeout, so only cutting it short can return in time
+        semaphore.set_wait_strategy(WaitStrategy::Sleep(std::time::Duration::from_secs(10)));
+
+        let timeout = std::time::Duration::from_millis(20);
+        let started = std::time::Instant::now();
+        assert_eq!(
+            semaphore.try_get_for(timeout).unwrap_err(),
+            SemaphoreError::AtMaxCount
+        );
+        let waited = started.elapsed();
+        assert!(waited >= timeout && waited < std::time::Duration::from_secs(1));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_try_get_until_past_deadline_tries_once() {
+        let semaphore = Semaphore::new((), 1);
+        let past = std::time::Instant::now();
+        // A sleep that would blow the assertion below if the call waited at all
+        semaphore.set_wait_strategy(WaitStrategy::Sleep(std::time::Duration::from_secs(10)));
+
+        let guard = semaphore.try_get_until(past).unwrap();
+        let started = std::time::Instant::now();
+        assert_eq!(
+            semaphore.try_get_until(past).unwrap_err(),
+            SemaphoreError::AtMaxCount
+        );
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+        drop(guard);
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_try_get_until_shared_deadline() {
+        let semaphore = Semaphore::new((), 1);
+        let guard = semaphore.get();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| semaphore.try_get_until(deadline).map(drop));
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            drop(guard);
+            assert!(waiter.join().unwrap().is_ok());
+        });
+
+        let _guard = semaphore.get();
+        let expired = std::time::Instant::now() + std::time::Duration::from_millis(20);
+        assert_eq!(
+            semaphore.try_get_until(expired).unwrap_err(),
+            SemaphoreError::AtMaxCount
+        );
+        assert!(std::time::Instant::now() >= expired);
+    }
+
+    #[test]
+    fn test_available_tracks_guards() {
+        let semaphore = Semaphore::new((), 3);
+        assert_eq!(semaphore.max(), 3);
+
+        let first = semaphore.try_get().unwrap();
+        let second = semaphore.try_get().unwrap();
+        assert_eq!(semaphore.available(Ordering::SeqCst), 1);
+        drop(first);
+        assert_eq!(semaphore.available(Ordering::SeqCst), 2);
+        drop(second);
+        assert_eq!(semaphore.available(Ordering::SeqCst), semaphore.max());
+    }
+
+    #[test]
+    fn test_pinned_guard_hands_out_pinned_data() {
+        struct Pinned {
+            value: u8,
+            _pinned: core::marker::PhantomPinned,
+        }
+
+        let semaphore = core::pin::pin!(Semaphore::new(
+            Pinned {
+                value: 7,
+                _pinned: core::marker::PhantomPinned,
+            },
+            1
+        ));
+        let guard = semaphore.as_ref().get_pin();
+        let pinned: core::pin::Pin<&Pinned> = guard.as_pin();
+        assert_eq!(pinned.value, 7);
+        assert!(semaphore.try_get().is_err());
+        drop(guard);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_growing_wakes_blocked_get() {
+        let semaphore = Semaphore::new((), 1);
+        let _held = semaphore.get();
+
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| drop(semaphore.get()));
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            assert!(!waiter.is_finished());
+
+            let grown = std::time::Instant::now();
+            semaphore.add_permits(1);
+            waiter.join().unwrap();
+            assert!(grown.elapsed() < raw::Semaphore::PARK_TIMEOUT / 4);
+        });
+        assert_eq!(semaphore.max(), 2);
+    }
+
+    #[test]
+    fn test_shrinking_below_held_guards() {
+        let semaphore = Semaphore::new((), 3);
+        let guards: Vec<_> = (0..3).map(|_| semaphore.try_get().unwrap()).collect();
+
+        semaphore.set_max(1);
+        assert_eq!(semaphore.available(Ordering::SeqCst), 0);
+        assert!(semaphore.at_max(Ordering::SeqCst));
+        drop(guards);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+
+        let _only = semaphore.try_get().unwrap();
+        assert!(semaphore.try_get().is_err());
+        semaphore.add_permits(usize::MAX);
+        assert_eq!(semaphore.max(), usize::MAX);
+    }
+
+    #[cfg(feature = "allocator_api")]
+    #[test]
+    fn test_new_in_parks_waiters_in_the_allocator() {
+        use core::{
+            alloc::{AllocError, Allocator, Layout},
+            ptr::NonNull,
+            sync::atomic::AtomicUsize,
+        };
+
+        struct Counting(AtomicUsize);
+
+        // SAFETY: every call is forwarded to the global allocator
+        unsafe impl Allocator for Counting {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                std::alloc::Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                unsafe { std::alloc::Global.deallocate(ptr, layout) }
+            }
+        }
+
+        static ALLOC: Counting = Counting(AtomicUsize::new(0));
+        let semaphore = Semaphore::new_in((), 1, &ALLOC);
+        semaphore.set_wait_strategy(WaitStrategy::Park);
+        let guard = semaphore.get();
+
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| drop(semaphore.get()));
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            drop(guard);
+            waiter.join().unwrap();
+        });
+        assert!(ALLOC.0.load(Ordering::SeqCst) > 0);
+    }
+
+    #[test]
+    fn test_with_releases_after_the_closure() {
+        let semaphore = Semaphore::new(3, 2);
+        let _held = semaphore.try_get().unwrap();
+
+        assert_eq!(
+            semaphore.with(|value| {
+                assert_eq!(semaphore.count(Ordering::SeqCst), 2);
+                value * 2
+            }),
+            6
+        );
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+        assert_eq!(semaphore.try_with(|value| *value).unwrap(), 3);
+        let _full = semaphore.try_get().unwrap();
+        assert!(semaphore.try_with(|_| ()).is_err());
+    }
+
+    #[test]
+    fn test_parts_round_trip_keeps_the_count() {
+        let semaphore = Semaphore::new("data", 3);
+        core::mem::forget(semaphore.try_get().unwrap());
+
+        let (raw, data) = semaphore.into_parts();
+        assert_eq!(
+            (raw.count(Ordering::SeqCst), raw.max(), data),
+            (1, 3, "data")
+        );
+
+        let semaphore = Semaphore::from_parts(raw, data);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+        assert_eq!(semaphore.max(), 3);
+        assert_eq!(*semaphore.try_get().unwrap(), "data");
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_shutdown_drains_after_the_last_release() {
+        use core::sync::atomic::AtomicUsize;
+
+        let semaphore = Semaphore::new(AtomicUsize::new(0), 2);
+        let drained = AtomicUsize::new(0);
+        let released = AtomicBool::new(false);
+
+        std::thread::scope(|s| {
+            let guards = [semaphore.get(), semaphore.get()];
+            let blocked = s.spawn(|| semaphore.get_or_shutdown().map(drop));
+            std::thread::sleep(std::time::Duration::from_millis(10));
+
+            let shutdown = s.spawn(|| {
+                semaphore.shutdown(|data| {
+                    assert!(released.load(Ordering::SeqCst));
+                    assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+                    data.fetch_add(1, Ordering::SeqCst);
+                    drained.fetch_add(1, Ordering::SeqCst);
+                });
+            });
+            assert_eq!(blocked.join().unwrap().unwrap_err(), SemaphoreError::Closed);
+
+            // The drain waits for the guards that were held when it closed
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            assert_eq!(drained.load(Ordering::SeqCst), 0);
+            released.store(true, Ordering::SeqCst);
+            drop(guards);
+            shutdown.join().unwrap();
+        });
+
+        assert_eq!(drained.load(Ordering::SeqCst), 1);
+        assert_eq!(semaphore.try_get().unwrap_err(), SemaphoreError::Closed);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_get_unpoisoned_ignores_a_panicked_holder() {
+        let semaphore = Semaphore::new_poisoning(1, 2);
+
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _guard = semaphore.get();
+            panic!("holder panicked");
+        }));
+        assert!(panicked.is_err());
+        assert!(semaphore.is_poisoned());
+        assert_eq!(semaphore.try_get().unwrap_err(), SemaphoreError::Poisoned);
+        let get = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(semaphore.get())));
+        assert!(get.is_err());
+
+        let guard = semaphore.get_unpoisoned();
+        assert_eq!(*guard, 1);
+        assert!(semaphore.is_poisoned());
+        drop(guard);
+
+        semaphore.clear_poison();
+        assert_eq!(*semaphore.try_get().unwrap(), 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    #[should_panic = "Semaphore::get_unpoisoned failed: Semaphore is closed!"]
+    fn test_get_unpoisoned_panics_when_closed() {
+        let semaphore = Semaphore::new((), 2);
+        semaphore.close();
+        drop(semaphore.get_unpoisoned());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    #[should_panic = "Semaphore::get_unpoisoned failed: Semaphore has no acquires left!"]
+    fn test_get_unpoisoned_panics_when_exhausted() {
+        let semaphore = Semaphore::new_with_total_cap((), 2, 1);
+        drop(semaphore.try_get().unwrap());
+        drop(semaphore.get_unpoisoned());
+    }
+
+    #[test]
+    fn test_try_get_with_borrows_extra_alongside() {
+        struct Config {
+            scale: u32,
+        }
+
+        let semaphore = Semaphore::new(4, 1);
+        let config = Config { scale: 3 };
+
+        let (guard, config) = semaphore.try_get_with(&config).unwrap();
+        assert_eq!(*guard * config.scale, 12);
+        assert!(semaphore.try_get_with(config).is_err());
+        drop(guard);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_depth_counts_parked_waiters() {
+        let semaphore = Semaphore::new((), 2);
+        semaphore.set_wait_strategy(WaitStrategy::Park);
+        let (first, depth) = semaphore.try_get_with_depth().unwrap();
+        assert_eq!(depth, 0);
+        let second = semaphore.try_get().unwrap();
+
+        std::thread::scope(|s| {
+            let waiters: Vec<_> = (0..3).map(|_| s.spawn(|| drop(semaphore.get()))).collect();
+            while semaphore.waiter_count() < 3 {
+                std::thread::yield_now();
+            }
+            assert!(semaphore.try_get_with_depth().is_err());
+
+            drop((first, second));
+            for waiter in waiters {
+                waiter.join().unwrap();
+            }
+        });
+        assert_eq!(semaphore.waiter_count(), 0);
+    }
+
+    #[test]
+    fn test_guard_compares_and_borrows_its_data() {
+        use core::borrow::Borrow;
+
+        fn length(value: impl AsRef<String>) -> usize {
+            value.as_ref().len()
+        }
+
+        let first = Semaphore::new(String::from("data"), 2);
+        let second = Semaphore::new(String::from("data"), 1);
+        let other = Semaphore::new(String::from("other"), 1);
+
+        let guard = first.try_get().unwrap();
+        assert_eq!(guard, String::from("data"));
+        assert_eq!(guard, first.try_get().unwrap());
+        assert_eq!(guard, second.try_get().unwrap());
+        assert_ne!(guard, other.try_get().unwrap());
+
+        let borrowed: &String = guard.borrow();
+        assert_eq!(borrowed, "data");
+        assert_eq!(length(guard), 4);
+    }
+
+    #[test]
+    fn test_promotion_needs_every_other_permit_free() {
+        let semaphore = Semaphore::new((), 3);
+        let reader = semaphore.try_get().unwrap();
+        let other = semaphore.try_get().unwrap();
+
+        // Both promotions fail instead of waiting for each other
+        let reader = reader.try_promote().unwrap_err();
+        let other = other.try_promote().unwrap_err();
+        assert_eq!(semaphore.count(Ordering::SeqCst), 2);
+
+        drop(other);
+        let writer = reader.try_promote().unwrap();
+        assert_eq!(semaphore.count(Ordering::SeqCst), 3);
+        assert_eq!(semaphore.try_get().unwrap_err(), SemaphoreError::AtMaxCount);
+
+        drop(writer);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_downgrade_keeps_one_permit() {
+        let semaphore = Semaphore::new((), 3);
+        let writer = semaphore.try_get_many(3).unwrap();
+        assert_eq!(semaphore.try_get().unwrap_err(), SemaphoreError::AtMaxCount);
+
+        let reader = writer.downgrade();
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+        // No one can claim every permit while the downgraded guard is held
+        assert!(semaphore.try_get_many(3).is_err());
+        let first = semaphore.try_get().unwrap();
+        let second = semaphore.try_get().unwrap();
+        assert!(semaphore.try_get().is_err());
+
+        drop((first, second));
+        // Promoting again works once the other readers are gone
+        let writer = reader.try_promote().unwrap();
+        assert_eq!(semaphore.count(Ordering::SeqCst), 3);
+        drop(writer.downgrade());
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_try_get_any_picks_the_first_free_tier() {
+        let (fast, slow, spare) = (
+            Semaphore::new("fast", 1),
+            Semaphore::new("slow", 1),
+            Semaphore::new("spare", 1),
+        );
+        let _fast = fast.try_get().unwrap();
+        let _slow = slow.try_get().unwrap();
+
+        let (index, guard) = Semaphore::try_get_any(&[&fast, &slow, &spare]).unwrap();
+        assert_eq!((index, *guard), (2, "spare"));
+        assert_eq!(fast.count(Ordering::SeqCst), 1);
+        assert_eq!(slow.count(Ordering::SeqCst), 1);
+        assert!(Semaphore::try_get_any(&[&fast, &slow, &spare]).is_none());
+    }
+
+    #[test]
+    fn test_try_get_or_else_branches() {
+        let semaphore = Semaphore::new("fresh", 1);
+
+        let guard = semaphore
+            .try_get_or_else(|_| unreachable!("the semaphore has a free permit"))
+            .left()
+            .unwrap();
+        assert_eq!(*guard, "fresh");
+
+        let fallback = semaphore.try_get_or_else(|err| {
+            assert_eq!(err, SemaphoreError::AtMaxCount);
+            "cached"
+        });
+        assert_eq!(fallback.right(), Some("cached"));
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip_resets_the_count() {
+        let semaphore = Semaphore::new(vec![1, 2, 3], 4);
+        let _guard = semaphore.try_get().unwrap();
+
+        let json = serde_json::to_string(&semaphore).unwrap();
+        assert_eq!(json, r#"{"data":[1,2,3],"max":4}"#);
+
+        let restored: Semaphore<Vec<i32>> = serde_json::from_str(&json).unwrap();
+        assert_eq!(*restored.try_get().unwrap(), [1, 2, 3]);
+        assert_eq!(restored.max(), 4);
+        assert_eq!(restored.count(Ordering::SeqCst), 0);
+
+        let err = serde_json::from_str::<Semaphore<Vec<i32>>>(r#"{"data":[],"max":0}"#)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("max can't be 0"), "{err}");
+    }
+
+    #[cfg(all(unix, feature = "std"))]
+    #[test]
+    fn test_guard_forwards_the_file_descriptor() {
+        use std::os::fd::{AsFd, AsRawFd};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let fd = listener.as_raw_fd();
+        let semaphore = Semaphore::new(listener, 1);
+
+        let guard = semaphore.try_get().unwrap();
+        assert_eq!(guard.as_raw_fd(), fd);
+        assert_eq!(guard.as_fd().as_raw_fd(), fd);
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_get_iter_yields_as_permits_free_up() {
+        use std::sync::atomic::AtomicBool;
+
+        let semaphore = Semaphore::new((), 2);
+        let mut guards: Vec<_> = semaphore.get_iter().take(2).collect();
+        let pulled = AtomicBool::new(false);
+
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let _guard = semaphore.get_iter().next().unwrap();
+                pulled.store(true, Ordering::SeqCst);
+            });
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            assert!(!pulled.load(Ordering::SeqCst));
+            guards.pop();
+        });
+
+        assert!(pulled.load(Ordering::SeqCst));
+        semaphore.close();
+        assert!(semaphore.get_iter().next().is_none());
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_close_fails_waiters_and_keeps_releases() {
+        let semaphore = Semaphore::new((), 1);
+        let guard = semaphore.try_get().unwrap();
+
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| semaphore.get_or_shutdown().map(drop));
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            semaphore.close();
+            assert_eq!(waiter.join().unwrap(), Err(SemaphoreError::Closed));
+        });
+
+        // Closing again changes nothing, the outstanding guard still releases
+        semaphore.close();
+        assert!(semaphore.is_closed());
+        assert_eq!(semaphore.try_get().unwrap_err(), SemaphoreError::Closed);
+        drop(guard);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+        assert_eq!(semaphore.try_get().unwrap_err(), SemaphoreError::Closed);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_timed_and_cancellable_gets_give_up_when_closed() {
+        let semaphore = Semaphore::new((), 2);
+        semaphore.close();
+
+        assert!(semaphore.get_with_cancel(&AtomicBool::new(false)).is_none());
+        assert!(semaphore
+            .get_timeout(core::time::Duration::from_secs(1))
+            .is_none());
+        let clock = crate::clock::ManualClock::new();
+        assert!(semaphore
+            .get_timeout_with_clock(core::time::Duration::from_secs(1), &clock)
+            .is_none());
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            drop(semaphore.get_or_panic_after(core::time::Duration::ZERO));
+        }));
+        let message = panicked.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(
+            *message,
+            "Semaphore::get_or_panic_after failed: Semaphore is closed!"
+        );
+    }
+
+    #[test]
+    fn test_replace_and_take_swap_the_data() {
+        let mut semaphore = Semaphore::new(String::from("first"), 2);
+        drop(semaphore.try_get().unwrap());
+
+        assert_eq!(semaphore.replace(String::from("second")), "first");
+        assert_eq!(semaphore.take(), "second");
+        assert_eq!(*semaphore.try_get().unwrap(), "");
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_peek_takes_no_permit() {
+        let semaphore = Semaphore::new(7, 1);
+        let _full = semaphore.try_get().unwrap();
+
+        assert_eq!(semaphore.peek(|value| value + 1), 8);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_try_get_cloned_releases_before_returning() {
+        let semaphore = Semaphore::new(vec![1, 2, 3], 1);
+
+        let snapshot = semaphore.try_get_cloned().unwrap();
+        assert_eq!(snapshot, [1, 2, 3]);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+
+        let _full = semaphore.try_get().unwrap();
+        assert_eq!(
+            semaphore.try_get_cloned().unwrap_err(),
+            SemaphoreError::AtMaxCount
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_with_releases_when_the_closure_panics() {
+        let semaphore = Semaphore::new((), 1);
+
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            semaphore.with(|()| panic!("closure panicked"));
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_debug_shows_count_and_max() {
+        struct Opaque;
+
+        let semaphore = Semaphore::new(Opaque, 8);
+        let _guards: Vec<_> = (0..3).map(|_| semaphore.try_get().unwrap()).collect();
+        assert_eq!(
+            format!("{semaphore:?}"),
+            "Semaphore { count: 3, max: 8, .. }"
+        );
+
+        let semaphore = Semaphore::new(vec![1, 2], 1);
+        assert_eq!(format!("{:?}", semaphore.try_get().unwrap()), "[1, 2]");
+    }
+
+    #[test]
+    fn test_on_drop_runs_after_release() {
+        let semaphore = Semaphore::new(4, 1);
+        let released = AtomicBool::new(false);
+
+        let guard = semaphore.try_get().unwrap().on_drop(|| {
+            assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+            released.store(true, Ordering::SeqCst);
+        });
+        assert_eq!(*guard, 4);
+        assert!(!released.load(Ordering::SeqCst));
+        drop(guard);
+        assert!(released.load(Ordering::SeqCst));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_on_drop_runs_while_panicking() {
+        let semaphore = Semaphore::new((), 1);
+        let released = AtomicBool::new(false);
+
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _guard = semaphore
+                .try_get()
+                .unwrap()
+                .on_drop(|| released.store(true, Ordering::SeqCst));
+            panic!("holder panicked");
+        }));
+        assert!(panicked.is_err());
+        assert!(released.load(Ordering::SeqCst));
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_mapped_guard_holds_the_permit() {
+        let semaphore = Semaphore::new((1, String::from("field")), 1);
+
+        let mapped = SemaphoreGuard::map(semaphore.try_get().unwrap(), |data| data.1.as_str());
+        assert_eq!(&*mapped, "field");
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+        assert!(semaphore.try_get().is_err());
+        drop(mapped);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+
+        let Err(guard) = SemaphoreGuard::try_map(semaphore.try_get().unwrap(), |data| {
+            (data.0 > 1).then_some(&data.0)
+        }) else {
+            panic!("the closure declined to map")
+        };
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+        let Ok(mapped) = SemaphoreGuard::try_map(guard, |data| Some(&data.0)) else {
+            panic!("the closure mapped to the first field")
+        };
+        assert_eq!(*mapped, 1);
+        drop(mapped);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_get_timed_measures_the_wait() {
+        let semaphore = Semaphore::new((), 1);
+        let wait = std::time::Duration::from_millis(20);
+
+        let (guard, immediate) = semaphore.get_timed();
+        assert!(immediate < wait);
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| semaphore.get_timed().1);
+            std::thread::sleep(wait);
+            drop(guard);
+            assert!(waiter.join().unwrap() >= wait);
+        });
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_blocked_get_wakes_soon_after_release() {
+        let semaphore = Semaphore::new((), 1);
+        let guard = semaphore.get();
+
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| {
+                drop(semaphore.get());
+                std::time::Instant::now()
+            });
+
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            let released = std::time::Instant::now();
+            drop(guard);
+            let acquired = waiter.join().unwrap();
+            // Well under the park timeout, so the release woke it rather than a periodic check
+            assert!(acquired.duration_since(released) < raw::Semaphore::PARK_TIMEOUT / 4);
+        });
+    }
+
+    #[test]
+    fn test_builder_sets_max_and_initial_count() {
+        let semaphore = Semaphore::builder().max(3).build(());
+        assert_eq!(semaphore.max(), 3);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+
+        let semaphore = Semaphore::builder()
+            .max(3)
+            .initial_count(2)
+            .wait_strategy(WaitStrategy::Yield)
+            .build(());
+        assert_eq!(semaphore.wait_strategy(), WaitStrategy::Yield);
+        assert_eq!(semaphore.available(Ordering::SeqCst), 1);
+        let guard = semaphore.try_get().unwrap();
+        assert!(semaphore.try_get().is_err());
+        semaphore.release_initial(2);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+        drop(guard);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_builder_makes_fair_semaphores() {
+        assert!(Semaphore::builder()
+            .max(2)
+            .fair(true)
+            .build(())
+            .raw
+            .is_fair());
+        assert!(!Semaphore::builder()
+            .max(2)
+            .fair(false)
+            .build(())
+            .raw
+            .is_fair());
+        assert!(!Semaphore::new((), 2).raw.is_fair());
+    }
+
+    #[test]
+    #[should_panic = "initial count of 3 above the max of 2"]
+    fn test_builder_rejects_initial_count_above_max() {
+        let _ = Semaphore::builder().max(2).initial_count(3).build(());
+    }
+
+    #[test]
+    #[should_panic = "without setting a max"]
+    fn test_builder_requires_max() {
+        let _ = SemaphoreBuilder::new().build(());
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_mutex1_has_one_holder_at_a_time() {
+        let mutex: Mutex1<_> = Semaphore::new_mutex(5);
+        let guard = mutex.try_lock().unwrap();
+        assert_eq!(*guard, 5);
+        assert!(mutex.is_locked(Ordering::SeqCst));
+        assert_eq!(mutex.try_lock().unwrap_err(), SemaphoreError::AtMaxCount);
+
+        let holding = AtomicBool::new(true);
+        std::thread::scope(|s| {
+            // Waits for the holder to let go
+            let waiter = s.spawn(|| {
+                let guard = mutex.lock();
+                assert!(!holding.load(Ordering::SeqCst));
+                *guard
+            });
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            holding.store(false, Ordering::SeqCst);
+            drop(guard);
+            assert_eq!(waiter.join().unwrap(), 5);
+        });
+        assert!(!mutex.is_locked(Ordering::SeqCst));
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_fair_semaphore_serves_waiters_in_arrival_order() {
+        let semaphore = Semaphore::new_fair((), 1);
+        let order = std::sync::Mutex::new(Vec::new());
+        let guard = semaphore.get();
+
+        std::thread::scope(|s| {
+            for waiter in 0..4 {
+                let (semaphore, order) = (&semaphore, &order);
+                s.spawn(move || {
+                    let guard = semaphore.get();
+                    order.lock().unwrap().push(waiter);
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                    drop(guard);
+                });
+                // Give each waiter time to queue before the next one arrives
+                std::thread::sleep(std::time::Duration::from_millis(20));
+            }
+
+            drop(guard);
+            // A newcomer can't barge ahead of the queued waiters
+            assert!(semaphore.try_get().is_err());
+        });
+
+        assert_eq!(*order.lock().unwrap(), [0, 1, 2, 3]);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+        assert!(semaphore.try_get().is_ok());
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_get_many_waits_its_turn_in_the_fair_queue() {
+        let semaphore = Semaphore::new_fair((), 2);
+        let order = std::sync::Mutex::new(Vec::new());
+        let guard = semaphore.get_many(2);
+
+        std::thread::scope(|s| {
+            let (semaphore, order) = (&semaphore, &order);
+            s.spawn(move || {
+                let guard = semaphore.get();
+                order.lock().unwrap().push("get");
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                drop(guard);
+            });
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            s.spawn(move || {
+                let guard = semaphore.get_many(2);
+                order.lock().unwrap().push("get_many");
+                drop(guard);
+            });
+            std::thread::sleep(std::time::Duration::from_millis(20));
+
+            drop(guard);
+        });
+
+        assert_eq!(*order.lock().unwrap(), ["get", "get_many"]);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_fair_waiter_that_times_out_leaves_the_queue() {
+        use std::time::Duration;
+
+        let semaphore = Semaphore::new_fair((), 1);
+        let order = std::sync::Mutex::new(Vec::new());
+        let guard = semaphore.get();
+
+        std::thread::scope(|s| {
+            let acquire = |waiter: usize, timeout: Option<Duration>| {
+                let (semaphore, order) = (&semaphore, &order);
+                let waiter = s.spawn(move || {
+                    let guard = match timeout {
+                        Some(timeout) => semaphore.try_get_for(timeout)?,
+                        None => semaphore.get(),
+                    };
+                    order.lock().unwrap().push(waiter);
+                    drop(guard);
+                    Ok::<_, SemaphoreError>(())
+                });
+                std::thread::sleep(Duration::from_millis(20));
+                waiter
+            };
+            let first = acquire(0, None);
+            let middle = acquire(1, Some(Duration::from_millis(10)));
+            let last = acquire(2, None);
+
+            // The middle waiter has given up by now, it must not hold up the last one
+            assert_eq!(
+                middle.join().unwrap().unwrap_err(),
+                SemaphoreError::AtMaxCount
+            );
+            drop(guard);
+            first.join().unwrap().unwrap();
+            last.join().unwrap().unwrap();
+        });
+
+        assert_eq!(*order.lock().unwrap(), [0, 2]);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_nested_guards_only_wake_their_own_waiters() {
+        let outer = Semaphore::new((), 1);
+        let inner = Semaphore::new((), 1);
+        let outer_guard = outer.get();
+        let inner_guard = inner.get();
+
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| drop(outer.get()));
+
+            drop(inner_guard);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            assert!(!waiter.is_finished());
+            assert_eq!(outer.count(Ordering::SeqCst), 1);
+            assert_eq!(inner.count(Ordering::SeqCst), 0);
+
+            drop(outer_guard);
+            waiter.join().unwrap();
+        });
+
+        assert_eq!(outer.count(Ordering::SeqCst), 0);
+        assert_eq!(inner.count(Ordering::SeqCst), 0);
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_get_many_is_not_starved_by_single_permits() {
+        use std::sync::atomic::AtomicBool;
+
+        let semaphore = Semaphore::new((), 4);
+        semaphore.set_wait_strategy(WaitStrategy::Yield);
+        let done = AtomicBool::new(false);
+
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    while !done.load(Ordering::SeqCst) {
+                        let _guard = semaphore.get();
+                        std::thread::yield_now();
+                    }
+                });
+            }
+
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            let all = semaphore.get_many(4);
+            assert_eq!(semaphore.count(Ordering::SeqCst), 4);
+            done.store(true, Ordering::SeqCst);
+            drop(all);
+        });
+
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_get_exactly_takes_every_permit_under_churn() {
+        use std::sync::atomic::AtomicBool;
+
+        let semaphore = Semaphore::new((), 3);
+        assert_eq!(
+            semaphore.get_exactly(4).unwrap_err(),
+            SemaphoreError::AtMaxCount
+        );
+        let done = AtomicBool::new(false);
+
+        std::thread::scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| {
+                    while !done.load(Ordering::SeqCst) {
+                        let _guard = semaphore.get();
+                    }
+                });
+            }
+
+            let all = semaphore.get_exactly(3).unwrap();
+            assert_eq!(semaphore.count(Ordering::SeqCst), 3);
+            done.store(true, Ordering::SeqCst);
+            drop(all);
+        });
+
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_try_get_many_takes_permits_at_once() {
+        let semaphore = Semaphore::new((), 4);
+
+        let three = semaphore.try_get_many(3).unwrap();
+        assert_eq!(semaphore.count(Ordering::SeqCst), 3);
+        assert!(semaphore.try_get_many(2).is_err());
+        let one = semaphore.try_get().unwrap();
+
+        drop(three);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+        drop(one);
+        assert!(semaphore.try_get_many(4).is_ok());
+    }
+
+    #[test]
+    fn test_total_cap_outlasts_releases() {
+        let semaphore = Semaphore::new_with_total_cap((), 2, 3);
+        assert_eq!(Semaphore::new((), 2).remaining_total(), None);
+
+        let first = semaphore.try_get().unwrap();
+        let second = semaphore.try_get().unwrap();
+        // Full at once, so the concurrency limit fails first
+        assert_eq!(semaphore.try_get().unwrap_err(), SemaphoreError::AtMaxCount);
+        assert_eq!(semaphore.remaining_total(), Some(1));
+
+        drop((first, second));
+        let third = semaphore.try_get().unwrap();
+        assert_eq!(semaphore.remaining_total(), Some(0));
+        drop(third);
+
+        // Releasing never restores the total, and failing keeps no permit
+        assert_eq!(semaphore.try_get().unwrap_err(), SemaphoreError::Exhausted);
+        assert_eq!(
+            semaphore.try_get_many(2).unwrap_err(),
+            SemaphoreError::Exhausted
+        );
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+        assert_eq!(semaphore.total_acquired(), 3);
+    }
+
+    #[test]
+    fn test_forget_keeps_the_permit_counted() {
+        let semaphore = Semaphore::new((), 2);
+        {
+            let broken = semaphore.try_get().unwrap();
+            broken.forget();
+        }
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+        assert_eq!(semaphore.available(Ordering::SeqCst), 1);
+
+        semaphore.add_permits(1);
+        assert_eq!(semaphore.available(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_batch_releases_permits_early() {
+        let semaphore = Semaphore::new((), 4);
+        let _one = semaphore.try_get().unwrap();
+
+        // A failed batch leaves the count untouched
+        assert!(semaphore.try_get_many(4).is_err());
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+
+        let mut batch = semaphore.try_get_many(3).unwrap();
+        assert_eq!(batch.permits(), 3);
+        batch.release_one();
+        assert_eq!(batch.permits(), 2);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 3);
+        let _freed = semaphore.try_get().unwrap();
+
+        drop(batch);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_boosted_max_is_restored() {
+        let semaphore = Semaphore::new((), 1);
+        let held = semaphore.try_get().unwrap();
+
+        let boosted = semaphore.with_boosted_max(2, || {
+            assert_eq!(semaphore.max(), 3);
+            std::thread::scope(|s| {
+                let acquires: Vec<_> = (0..2)
+                    .map(|_| s.spawn(|| semaphore.try_get().map(SemaphoreGuard::forget)))
+                    .collect();
+                for acquire in acquires {
+                    assert!(acquire.join().unwrap().is_ok());
+                }
+            });
+            semaphore.try_get()
+        });
+        assert!(boosted.is_err());
+        assert_eq!(semaphore.max(), 1);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 3);
+
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            semaphore.with_boosted_max(4, || panic!("the boosted scope failed"));
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(semaphore.max(), 1);
+
+        // A guard from the boost keeps its permit once the max is back down
+        semaphore.set_max(2);
+        drop(held);
+        let outliving = semaphore.with_boosted_max(1, || semaphore.try_get().unwrap());
+        assert_eq!(semaphore.max(), 2);
+        assert!(semaphore.try_get().is_err());
+        drop(outliving);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_scope_counts_apart_from_the_parent() {
+        let semaphore = Semaphore::new(vec![1, 2], 4);
+        let _held = semaphore.try_get().unwrap();
+
+        let sum = semaphore.scope(1, |child| {
+            let guard = child.try_get().unwrap();
+            assert!(child.try_get().is_err());
+            assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+            guard.iter().sum::<i32>()
+        });
+        assert_eq!(sum, 3);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_try_into_inner_needs_a_zero_count() {
+        let semaphore = Semaphore::new(7, 2);
+        drop(semaphore.try_get().unwrap());
+        assert_eq!(semaphore.try_into_inner().ok(), Some(7));
+
+        let semaphore = Semaphore::new(7, 2);
+        semaphore.try_get().unwrap().forget();
+        let semaphore = semaphore.try_into_inner().unwrap_err();
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+        semaphore.release_initial(1);
+        assert_eq!(semaphore.try_into_inner().ok(), Some(7));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_leaked_semaphore_counts_normally() {
+        let semaphore: &'static Semaphore<usize> = Semaphore::new(5, 2).leak();
+
+        let guard = semaphore.try_get().unwrap();
+        let _second = semaphore.try_get().unwrap();
+        assert_eq!(*guard, 5);
+        assert!(semaphore.try_get().is_err());
+
+        drop(guard);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+        assert!(semaphore.try_get().is_ok());
+    }
+
+    #[cfg(not(feature = "no_block"))]
+    #[test]
+    fn test_read_matches_get() {
+        let semaphore = Semaphore::new(3, 2);
+
+        let read = semaphore.read();
+        let try_read = semaphore.try_read().unwrap();
+        assert_eq!((*read, *try_read), (3, 3));
+        assert_eq!(semaphore.count(Ordering::SeqCst), 2);
+        assert_eq!(
+            semaphore.try_read().unwrap_err(),
+            SemaphoreError::AtMaxCount
+        );
+
+        drop((read, try_read));
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_guard_reaches_its_semaphore() {
+        let semaphore = Semaphore::new((), 3);
+        let guard = semaphore.try_get().unwrap();
+        assert!(core::ptr::eq(
+            guard.semaphore(),
+            core::ptr::from_ref(&semaphore)
+        ));
+        assert_eq!(guard.semaphore().count(Ordering::Relaxed), 1);
+
+        let batch = guard.semaphore().try_get_many(2).unwrap();
+        assert_eq!(guard.semaphore().available(Ordering::Relaxed), 0);
+        drop(batch);
+        assert_eq!(guard.semaphore().count(Ordering::Relaxed), 1);
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_batch_release_many_wakes_that_many_waiters() {
+        use core::sync::atomic::{AtomicBool, AtomicUsize};
+        let wait = || std::thread::sleep(core::time::Duration::from_millis(1));
+
+        let semaphore = Semaphore::new((), 3);
+        semaphore.set_wait_strategy(raw::WaitStrategy::Park);
+        let mut batch = semaphore.try_get_many(3).unwrap();
+        let (acquired, done) = (AtomicUsize::new(0), AtomicBool::new(false));
+
+        std::thread::scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| {
+                    let _guard = semaphore.get();
+                    acquired.fetch_add(1, Ordering::SeqCst);
+                    while !done.load(Ordering::SeqCst) {
+                        wait();
+                    }
+                });
+            }
+            while semaphore.waiter_count() < 3 {
+                wait();
+            }
+
+            batch.release_many(2);
+            while acquired.load(Ordering::SeqCst) < 2 {
+                wait();
+            }
+            std::thread::sleep(core::time::Duration::from_millis(10));
+            assert_eq!(acquired.load(Ordering::SeqCst), 2);
+            assert_eq!(batch.permits(), 1);
+
+            drop(batch);
+            while acquired.load(Ordering::SeqCst) < 3 {
+                wait();
+            }
+            done.store(true, Ordering::SeqCst);
+        });
+
+        // Releasing more than is held stops at what the guard has
+        let mut batch = semaphore.try_get_many(2).unwrap();
+        batch.release_many(5);
+        assert_eq!(batch.permits(), 0);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_get_reporting_reports_blocking() {
+        let semaphore = Semaphore::new((), 1);
+
+        let (guard, blocked) = semaphore.get_reporting();
+        assert!(!blocked);
+
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| semaphore.get_reporting().1);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            drop(guard);
+            assert!(waiter.join().unwrap());
+        });
+    }
+
+    #[cfg(feature = "no_block")]
+    #[test]
+    fn test_no_block_gives_up_instead_of_waiting() {
+        let semaphore = Semaphore::new((), 1);
+        let _guard = semaphore.get();
+
+        assert!(semaphore
+            .get_timeout(std::time::Duration::from_mins(1))
+            .is_none());
+        let blocked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| semaphore.get()));
+        assert!(blocked.is_err());
+    }
+
+    #[test]
+    fn test_get_or_block_takes_fast_path_when_free() {
+        let semaphore = Semaphore::new((), 2);
+
+        let (_first, blocked_first) = semaphore.get_or_block();
+        let (_second, blocked_second) = semaphore.get_or_block();
+        assert!(!blocked_first && !blocked_second);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 2);
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_get_timeout_with_manual_clock() {
+        let semaphore = Semaphore::new((), 1);
+        semaphore.set_wait_strategy(WaitStrategy::Sleep(std::time::Duration::from_millis(50)));
+        let clock = crate::clock::ManualClock::new();
+        let guard = semaphore.get();
+
+        assert!(semaphore
+            .get_timeout_with_clock(std::time::Duration::from_mins(1), &clock)
+            .is_none());
+        assert_eq!(clock.elapsed(), std::time::Duration::from_mins(1));
+
+        drop(guard);
+        assert!(semaphore
+            .get_timeout_with_clock(std::time::Duration::ZERO, &clock)
+            .is_some());
+        assert!(semaphore
+            .get_timeout_with_clock(std::time::Duration::MAX, &clock)
+            .is_some());
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no_block")))]
+    #[test]
+    fn test_get_or_panic_after_waits_for_release() {
+        let semaphore = Semaphore::new((), 1);
+        semaphore.set_wait_strategy(WaitStrategy::Yield);
+        let guard = semaphore.get();
+
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| {
+                drop(semaphore.get_or_panic_after(std::time::Duration::from_secs(5)));
+            });
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            drop(guard);
+            waiter.join().unwrap();
+        });
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    #[should_panic(expected = "timed out after 10ms (1/1 permits in use)")]
+    fn test_get_or_panic_after_times_out() {
+        let semaphore = Semaphore::new((), 1);
+        let _guard = semaphore.get();
+        let _ = semaphore.get_or_panic_after(std::time::Duration::from_millis(10));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_get_with_cancel_gives_up() {
+        let semaphore = Semaphore::new((), 1);
+        semaphore.set_wait_strategy(WaitStrategy::Yield);
+        let cancel = AtomicBool::new(false);
+        let guard = semaphore.get();
+
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| semaphore.get_with_cancel(&cancel).is_none());
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            cancel.store(true, Ordering::SeqCst);
+            assert!(waiter.join().unwrap());
+        });
+
+        drop(guard);
+        assert!(semaphore.get_with_cancel(&cancel).is_some());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_owned_guard_is_static() {
+        fn assert_static<G: 'static>(_: &G) {}
+
+        let semaphore = Arc::new(Semaphore::new(String::from("data"), 1));
+        let guard = semaphore.try_get_owned().unwrap();
+        assert_static(&guard);
+        assert_eq!(&*guard, "data");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_owned_guard_keeps_semaphore_alive_across_threads() {
+        let semaphore = Arc::new(Semaphore::new(String::from("data"), 1));
+        let guard = semaphore.get_owned();
+        let weak = Arc::downgrade(&semaphore);
+
+        let counted = std::thread::spawn(move || {
+            assert_eq!(&*guard, "data");
+            Arc::clone(guard.semaphore())
+        })
+        .join()
+        .unwrap();
+        assert_eq!(counted.count(Ordering::SeqCst), 0);
+        drop((counted, semaphore));
+        assert!(weak.upgrade().is_none());
+
+        let semaphore = Arc::new(Semaphore::new((), 1));
+        let guard = semaphore.get_owned();
+        let weak = Arc::downgrade(&semaphore);
+        drop(semaphore);
+        // Only the guard keeps it alive now
+        assert_eq!(weak.upgrade().unwrap().count(Ordering::SeqCst), 1);
+        drop(guard);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_spawn_with_guard_releases_once_in_worker() {
+        let semaphore = Arc::new(Semaphore::new(7, 2));
+        let _held = semaphore.try_get_owned().unwrap();
+
+        let worker = spawn_with_guard(semaphore.try_get_owned().unwrap(), |guard| {
+            let count = guard.semaphore().count(Ordering::SeqCst);
+            (*guard, count)
+        });
+
+        assert_eq!(worker.join().unwrap(), (7, 2));
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_arc_semaphore_clones_share_permits() {
+        #[derive(Clone)]
+        struct Handle {
+            limiter: ArcSemaphore<&'static str>,
+        }
+
+        let handle = Handle {
+            limiter: ArcSemaphore::new("data", 1),
+        };
+        let clone = handle.clone();
+
+        let guard = handle.limiter.try_get_owned().unwrap();
+        assert_eq!(*guard, "data");
+        assert!(clone.limiter.try_get_owned().is_err());
+        assert_eq!(clone.limiter.count(Ordering::SeqCst), 1);
+
+        drop(guard);
+        assert!(clone.limiter.try_get_owned().is_ok());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_timed_out_acquire_does_not_steal_permit() {
+        let semaphore = Semaphore::new((), 1);
+        let held = semaphore.try_get().unwrap();
+
+        let timed_out =
+            futures::executor::block_on(semaphore.acquire_until(futures::future::ready(())));
+        assert!(timed_out.unwrap().is_none());
+
+        drop(held);
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+        let _guard = semaphore.try_get().unwrap();
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_acquire_timeout_passes_on_wakeup() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .start_paused(true)
+            .build()
+            .unwrap();
+        let semaphore = Semaphore::new((), 1);
+
+        runtime.block_on(async {
+            let held = semaphore.try_get().unwrap();
+            let short = semaphore.acquire_timeout(core::time::Duration::from_millis(10));
+            let long = semaphore.acquire_timeout(core::time::Duration::from_mins(1));
+            let release = async {
+                tokio::time::sleep(core::time::Duration::from_millis(20)).await;
+                drop(held);
+            };
+
+            let (short, long, ()) = tokio::join!(short, long, release);
+            assert!(short.unwrap().is_none());
+            assert!(long.unwrap().is_some());
+        });
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_acquire_losing_select_leaks_nothing() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .start_paused(true)
+            .build()
+            .unwrap();
+        let semaphore = Semaphore::new((), 1);
+        let ms = core::time::Duration::from_millis;
+
+        runtime.block_on(async {
+            let holder = async {
+                for _ in 0..200 {
+                    let guard = semaphore.acquire().await.unwrap();
+                    tokio::time::sleep(ms(3)).await;
+                    drop(guard);
+                    tokio::time::sleep(ms(1)).await;
+                }
+            };
+            let racer = async {
+                let mut won = 0;
+                for _ in 0..200 {
+                    tokio::select! {
+                        guard = semaphore.acquire() => {
+                            won += 1;
+                            tokio::time::sleep(ms(1)).await;
+                            drop(guard);
+                        }
+                        () = tokio::time::sleep(ms(2)) => {}
+                    }
+                }
+                won
+            };
+
+            let ((), won) = tokio::join!(holder, racer);
+            assert!(won > 0 && won < 200);
+            assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+
+            // A wakeup must reach a live waiter, not one left behind by a dropped acquire
+            let held = semaphore.try_get().unwrap();
+            let release = async {
+                tokio::time::sleep(ms(1)).await;
+                drop(held);
+            };
+            let (guard, ()) = tokio::join!(semaphore.acquire(), release);
+            drop(guard.unwrap());
+        });
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_release_notify_completes_on_drop() {
+        let semaphore = Arc::new(Semaphore::new((), 1));
+        let guard = semaphore.try_get_owned().unwrap();
+        let released = guard.release_notify();
+
+        std::thread::scope(|s| {
+            let watcher = s.spawn(|| {
+                futures::executor::block_on(released);
+                semaphore.count(Ordering::SeqCst)
+            });
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            assert!(!watcher.is_finished());
+
+            drop(guard);
+            assert_eq!(watcher.join().unwrap(), 0);
+        });
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_owned_guards_in_slab_release_permits() {
+        let semaphore = Arc::new(Semaphore::new((), 2));
+        let mut live = slab::Slab::new();
+
+        let first = live.insert(semaphore.try_get_owned().unwrap());
+        let second = live.insert(semaphore.try_get_owned().unwrap());
+        assert!(semaphore.try_get_owned().is_err());
+
+        drop(live.remove(first));
+        assert_eq!(semaphore.count(Ordering::SeqCst), 1);
+        live.insert(semaphore.try_get_owned().unwrap());
+
+        drop(live.remove(second));
+        live.clear();
+        assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn test_slow_get_logs_warning() {
+        use core::sync::atomic::AtomicUsize;
+
+        static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+        struct CountWarnings;
+        impl log::Log for CountWarnings {
+            fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+                metadata.level() == log::Level::Warn
+            }
+            fn log(&self, record: &log::Record<'_>) {
+                if self.enabled(record.metadata()) {
+                    WARNINGS.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+            fn flush(&self) {}
+        }
+
+        log::set_logger(&CountWarnings).unwrap();
+        log::set_max_level(log::LevelFilter::Warn);
+
+        let semaphore = Semaphore::new((), 1);
+        semaphore.set_wait_strategy(WaitStrategy::Yield);
+        semaphore.set_slow_wait_threshold(std::time::Duration::from_millis(5));
+        let guard = semaphore.get();
+
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| drop(semaphore.get()));
+            std::thread::sleep(std::time::Duration::from_millis(30));
+            drop(guard);
+            waiter.join().unwrap();
+        });
+
+        // Only the first time the threshold is passed is logged
+        assert_eq!(WARNINGS.load(Ordering::SeqCst), 1);
     }
 }
-unsafe impl<'guard, T: ?Sized + Sync> Sync for SemaphoreGuard<'guard, T> {}
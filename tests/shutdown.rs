@@ -0,0 +1,36 @@
+//! `shutdown_all` is process-wide, so it is tested in its own binary where it can't
+//! interfere with the blocking tests in the library
+
+#![cfg(all(feature = "std", feature = "wrapper", not(feature = "no_block")))]
+
+use std::{sync::atomic::Ordering, thread, time::Duration};
+
+use semaphorus::{is_shutting_down, reset_shutdown, shutdown_all, Semaphore, SemaphoreError};
+
+#[test]
+fn test_shutdown_stops_blocking_acquires() {
+    let semaphore = Semaphore::new((), 1);
+    let held = semaphore.get();
+
+    thread::scope(|s| {
+        let waiter = s.spawn(|| semaphore.get_or_shutdown().map(drop));
+        thread::sleep(Duration::from_millis(10));
+        assert!(!waiter.is_finished());
+
+        shutdown_all();
+        assert!(is_shutting_down());
+        assert_eq!(
+            waiter.join().unwrap().unwrap_err(),
+            SemaphoreError::ShuttingDown
+        );
+    });
+    assert!(semaphore.get_timeout(Duration::from_mins(1)).is_none());
+
+    // Acquires that don't have to wait are unaffected
+    drop(held);
+    assert!(semaphore.get_or_shutdown().is_ok());
+    assert_eq!(semaphore.count(Ordering::SeqCst), 0);
+
+    reset_shutdown();
+    assert!(!is_shutting_down());
+}